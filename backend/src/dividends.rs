@@ -0,0 +1,253 @@
+// Dividend distributions: Merkle trees over a holder snapshot, matching the
+// leaf and node hashing of the on-chain `claim_dividend` instruction.
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use crate::Config;
+
+const DIVIDENDS_DIR: &str = "cache/dividends";
+// Limits of the on-chain stock listings
+const MAX_STOCK_SYMBOL_LEN: usize = 10;
+const MAX_STOCK_DECIMALS: u8 = 9;
+
+#[derive(Debug, Deserialize)]
+pub struct HolderBalance {
+    pub holder: String, // Base58 wallet address
+    pub balance: u64,   // Stock token base units held at the record date
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DistributionRequest {
+    pub symbol: String,
+    pub record_date: i64,
+    pub decimals: u8,
+    pub amount_per_token: u64, // Quote base units paid per whole stock token
+    pub holders: Vec<HolderBalance>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DividendClaim {
+    pub index: u32,
+    pub holder: String,
+    pub amount: u64,
+    pub proof: Vec<String>, // Hex-encoded sibling hashes, leaf to root
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DistributionTree {
+    pub symbol: String,
+    pub record_date: i64,
+    pub merkle_root: String,
+    pub total_amount: u64,
+    pub claimant_count: u32,
+    pub claims: Vec<DividendClaim>,
+}
+
+fn to_hex(bytes: &[u8; 32]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hash_leaf(index: u32, holder: &[u8; 32], amount: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]);
+    hasher.update(index.to_le_bytes());
+    hasher.update(holder);
+    hasher.update(amount.to_le_bytes());
+    hasher.finalize().into()
+}
+
+fn hash_node(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn parse_holder(holder: &str) -> Result<[u8; 32]> {
+    bs58::decode(holder)
+        .into_vec()
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| actix_web::error::ErrorBadRequest(format!("Invalid holder address '{}'", holder)))
+}
+
+// Pays each holder `balance * amount_per_token / 10^decimals`, rounded down,
+// and builds the tree over holders in address order. An unpaired node is
+// carried up a level unchanged, so its proof simply has no sibling there.
+pub fn build_distribution_tree(request: &DistributionRequest) -> Result<DistributionTree> {
+    validate_symbol(&request.symbol)?;
+    if request.decimals > MAX_STOCK_DECIMALS {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "Decimals must be at most {}",
+            MAX_STOCK_DECIMALS
+        )));
+    }
+    
+    let mut balances: BTreeMap<[u8; 32], (String, u64)> = BTreeMap::new();
+    for entry in &request.holders {
+        let key = parse_holder(&entry.holder)?;
+        let (_, balance) = balances.entry(key).or_insert((entry.holder.clone(), 0));
+        *balance = balance
+            .checked_add(entry.balance)
+            .ok_or_else(|| actix_web::error::ErrorBadRequest("Holder balance overflow"))?;
+    }
+    
+    let scale = 10u128.pow(request.decimals as u32);
+    let mut claims = Vec::new();
+    let mut leaves = Vec::new();
+    let mut total_amount: u64 = 0;
+    for (key, (holder, balance)) in balances {
+        let amount = u64::try_from(balance as u128 * request.amount_per_token as u128 / scale)
+            .map_err(|_| actix_web::error::ErrorBadRequest("Dividend amount overflow"))?;
+        if amount == 0 {
+            continue;
+        }
+        total_amount = total_amount
+            .checked_add(amount)
+            .ok_or_else(|| actix_web::error::ErrorBadRequest("Dividend total overflow"))?;
+        let index = claims.len() as u32;
+        leaves.push(hash_leaf(index, &key, amount));
+        claims.push(DividendClaim { index, holder, amount, proof: Vec::new() });
+    }
+    
+    if claims.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("No holder is owed a dividend"));
+    }
+    
+    // Track each leaf's position as the levels collapse
+    let mut positions: Vec<usize> = (0..leaves.len()).collect();
+    let mut level = leaves;
+    while level.len() > 1 {
+        for (claim, position) in claims.iter_mut().zip(positions.iter_mut()) {
+            let sibling = *position ^ 1;
+            if sibling < level.len() {
+                claim.proof.push(to_hex(&level[sibling]));
+            }
+            *position /= 2;
+        }
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [a, b] => hash_node(a, b),
+                [a] => *a,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    
+    Ok(DistributionTree {
+        symbol: request.symbol.to_uppercase(),
+        record_date: request.record_date,
+        merkle_root: to_hex(&level[0]),
+        total_amount,
+        claimant_count: claims.len() as u32,
+        claims,
+    })
+}
+
+// Symbols end up in file names, so only plain tickers are accepted
+fn validate_symbol(symbol: &str) -> Result<()> {
+    if symbol.is_empty()
+        || symbol.len() > MAX_STOCK_SYMBOL_LEN
+        || !symbol.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        return Err(actix_web::error::ErrorBadRequest(format!("Invalid symbol '{}'", symbol)));
+    }
+    Ok(())
+}
+
+// Compares the bearer token against the configured admin key without
+// short-circuiting on the first differing byte
+fn require_admin(req: &HttpRequest, config: &Config) -> Result<()> {
+    let admin_api_key = config
+        .admin_api_key
+        .as_deref()
+        .ok_or_else(|| actix_web::error::ErrorForbidden("Admin endpoints are disabled"))?;
+    let token = req
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    
+    let matches = token.len() == admin_api_key.len()
+        && token
+            .bytes()
+            .zip(admin_api_key.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    if !matches {
+        return Err(actix_web::error::ErrorUnauthorized("Invalid admin credentials"));
+    }
+    Ok(())
+}
+
+fn get_distribution_path(symbol: &str, record_date: i64) -> Result<String> {
+    validate_symbol(symbol)?;
+    Ok(format!("{}/{}_{}.json", DIVIDENDS_DIR, symbol.to_uppercase(), record_date))
+}
+
+// Builds and stores the tree; the returned root, total and claimant count are
+// what the vault authority passes to `create_distribution`. A stored
+// distribution is never overwritten, since holders may already hold its proofs,
+// and only admins may store one, so nobody can squat a symbol and record date.
+pub async fn create_dividend_distribution(
+    req: HttpRequest,
+    config: web::Data<Config>,
+    request: web::Json<DistributionRequest>,
+) -> Result<HttpResponse> {
+    require_admin(&req, &config)?;
+    let tree = build_distribution_tree(&request)?;
+    
+    fs::create_dir_all(DIVIDENDS_DIR).map_err(actix_web::error::ErrorInternalServerError)?;
+    let json_content = serde_json::to_string_pretty(&tree)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(get_distribution_path(&tree.symbol, tree.record_date)?)
+        .map_err(|e| match e.kind() {
+            ErrorKind::AlreadyExists => actix_web::error::ErrorConflict("Distribution already exists"),
+            _ => actix_web::error::ErrorInternalServerError(e),
+        })?;
+    file.write_all(json_content.as_bytes())
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    println!("💾 Dividend distribution stored for {} at {}", tree.symbol, tree.record_date);
+    
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "symbol": tree.symbol,
+        "record_date": tree.record_date,
+        "merkle_root": tree.merkle_root,
+        "total_amount": tree.total_amount,
+        "claimant_count": tree.claimant_count,
+    })))
+}
+
+// Proof a holder submits with `claim_dividend`
+pub async fn get_dividend_proof(
+    path: web::Path<(String, i64, String)>,
+) -> Result<HttpResponse> {
+    let (symbol, record_date, holder) = path.into_inner();
+    let distribution_path = get_distribution_path(&symbol, record_date)?;
+    
+    if !Path::new(&distribution_path).exists() {
+        return Err(actix_web::error::ErrorNotFound("Distribution not found"));
+    }
+    
+    let content = fs::read_to_string(&distribution_path)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let tree: DistributionTree = serde_json::from_str(&content)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    
+    match tree.claims.into_iter().find(|claim| claim.holder == holder) {
+        Some(claim) => Ok(HttpResponse::Ok().json(claim)),
+        None => Err(actix_web::error::ErrorNotFound("Holder has no dividend in this distribution")),
+    }
+}
@@ -12,6 +12,7 @@ use base64::{Engine as _, engine::general_purpose};
 use chrono::Utc;
 use dotenv;
 
+mod dividends;
 // mod services;
 // use services::solana_service::SolanaService;
 
@@ -29,6 +30,9 @@ pub struct Config {
     pub okx_secret_key: String,
     pub okx_passphrase: String,
     pub okx_project_id: String,
+    
+    // Bearer token for admin endpoints; they are refused while it is unset
+    pub admin_api_key: Option<String>,
 }
 
 impl Config {
@@ -44,6 +48,7 @@ impl Config {
             okx_secret_key: std::env::var("OKX_SECRET_KEY").unwrap_or_else(|_| "test_secret".to_string()),
             okx_passphrase: std::env::var("OKX_API_PASSPHRASE").unwrap_or_else(|_| "test_passphrase".to_string()),
             okx_project_id: std::env::var("OKX_PROJECT_ID").unwrap_or_else(|_| "test_project".to_string()),
+            admin_api_key: std::env::var("ADMIN_API_KEY").ok().filter(|key| !key.is_empty()),
         }
     }
 }
@@ -637,6 +642,64 @@ pub async fn get_stock_list(config: web::Data<Config>) -> Result<HttpResponse> {
     Ok(HttpResponse::Ok().json(data))
 }
 
+// Token metadata JSON referenced by the on-chain stock mint metadata URI
+pub async fn get_stock_metadata(
+    config: web::Data<Config>,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let symbol = path.into_inner().to_uppercase();
+    let cache_key = format!("stock_metadata_{}", symbol);
+    
+    if let Some(cached_data) = read_cache(&cache_key) {
+        return Ok(HttpResponse::Ok().json(cached_data.data));
+    }
+    
+    let client = Client::new();
+    let url = format!("{}/v2/assets/{}", config.alpaca_base_url, symbol);
+    
+    let response = client
+        .get(&url)
+        .header("APCA-API-KEY-ID", &config.alpaca_api_key)
+        .header("APCA-API-SECRET-KEY", &config.alpaca_secret_key)
+        .send()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    
+    match response.status().as_u16() {
+        200..=299 => {}
+        404 => return Err(actix_web::error::ErrorNotFound(format!("Stock symbol '{}' not found", symbol))),
+        _ => return Err(actix_web::error::ErrorBadGateway("Failed to fetch stock asset")),
+    }
+    
+    let asset: serde_json::Value = response.json().await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    
+    let name = asset.get("name")
+        .and_then(|name| name.as_str())
+        .unwrap_or(&symbol)
+        .to_string();
+    let exchange = asset.get("exchange")
+        .and_then(|exchange| exchange.as_str())
+        .unwrap_or("US")
+        .to_string();
+    
+    let metadata = serde_json::json!({
+        "name": name,
+        "symbol": symbol,
+        "description": format!("Synthetic token tracking {} ({}: {})", name, exchange, symbol),
+        "attributes": [
+            { "trait_type": "exchange", "value": exchange },
+            { "trait_type": "asset_class", "value": "us_equity" }
+        ]
+    });
+    
+    if let Err(e) = write_cache(&cache_key, &metadata) {
+        println!("⚠️ Failed to write metadata cache for {}: {}", symbol, e);
+    }
+    
+    Ok(HttpResponse::Ok().json(metadata))
+}
+
 pub async fn get_top_stocks(config: web::Data<Config>) -> Result<HttpResponse> {
     let cache_key = "top_stocks";
     
@@ -750,6 +813,7 @@ async fn main() -> std::io::Result<()> {
             .route("/api/stock/price/{symbol}", web::get().to(get_stock_price))
             .route("/api/stock/list", web::get().to(get_stock_list))
             .route("/api/stock/top", web::get().to(get_top_stocks))
+            .route("/api/stock/metadata/{symbol}", web::get().to(get_stock_metadata))
             .route("/api/stock/buy", web::post().to(buy_stock_with_usdt))
             .route("/api/account", web::get().to(get_account_info))
             .route("/api/positions", web::get().to(get_positions))
@@ -758,6 +822,9 @@ async fn main() -> std::io::Result<()> {
             .route("/api/crypto/quote", web::get().to(get_swap_quote))
             .route("/api/crypto/swap", web::post().to(swap_crypto_to_usdt))
             .route("/api/crypto/tokens", web::get().to(get_token_list))
+            // Dividend endpoints
+            .route("/api/dividends/distribution", web::post().to(dividends::create_dividend_distribution))
+            .route("/api/dividends/{symbol}/{record_date}/proof/{holder}", web::get().to(dividends::get_dividend_proof))
             // Health check
             .route("/health", web::get().to(|| async { 
                 HttpResponse::Ok().json(serde_json::json!({
@@ -54,6 +54,30 @@ export function useSolanaContract() {
     return vault;
   }, []);
 
+  const getTradingSchedulePDA = useCallback(() => {
+    const [tradingSchedule] = PublicKey.findProgramAddressSync(
+      [Buffer.from("trading_schedule")],
+      PROGRAM_ID
+    );
+    return tradingSchedule;
+  }, []);
+
+  const getUserAccountPDA = useCallback((user: PublicKey) => {
+    const [userAccount] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user_account"), user.toBuffer()],
+      PROGRAM_ID
+    );
+    return userAccount;
+  }, []);
+
+  const getUserEligibilityPDA = useCallback((user: PublicKey) => {
+    const [userEligibility] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user_eligibility"), user.toBuffer()],
+      PROGRAM_ID
+    );
+    return userEligibility;
+  }, []);
+
   const getBuyOrderPDA = useCallback((user: PublicKey, orderId: number) => {
     const [buyOrder] = PublicKey.findProgramAddressSync(
       [
@@ -90,14 +114,21 @@ export function useSolanaContract() {
     const { stockSymbol, solAmount, maxPricePerShare } = params;
     
     try {
-      // Get current order count for PDA derivation
+      // Get the user's order nonce for PDA derivation; it starts at zero
+      // until the first order creates the user account
       const tradingPool = getTradingPoolPDA();
-      const tradingPoolAccount = await program.account.tradingPool.fetch(tradingPool);
-      const currentOrderId = tradingPoolAccount.totalOrders.toNumber();
+      const userAccount = getUserAccountPDA(wallet.publicKey);
+      const userAccountData = await program.account.userAccount.fetchNullable(userAccount);
+      const currentOrderId = userAccountData ? userAccountData.orderNonce.toNumber() : 0;
       
       const buyOrderPDA = getBuyOrderPDA(wallet.publicKey, currentOrderId);
       const tradingPoolVault = getTradingPoolVaultPDA();
       
+      // Pass the KYC grant when the wallet has one; it is required while the
+      // pool enforces eligibility
+      const userEligibility = getUserEligibilityPDA(wallet.publicKey);
+      const userEligibilityData = await program.account.userEligibility.fetchNullable(userEligibility);
+      
       const solAmountLamports = new BN(solAmount * LAMPORTS_PER_SOL);
       const maxPriceLamports = new BN(maxPricePerShare * LAMPORTS_PER_SOL);
 
@@ -105,12 +136,20 @@ export function useSolanaContract() {
         .placeBuyOrder(
           stockSymbol,
           solAmountLamports,
-          maxPriceLamports
+          maxPriceLamports,
+          null
         )
         .accounts({
+          userAccount: userAccount,
           buyOrder: buyOrderPDA,
+          stockMintInfo: getStockMintInfoPDA(stockSymbol),
+          tradingSchedule: getTradingSchedulePDA(),
           tradingPool: tradingPool,
           tradingPoolVault: tradingPoolVault,
+          quoteMintConfig: null,
+          quoteVault: null,
+          userQuoteTokenAccount: null,
+          userEligibility: userEligibilityData ? userEligibility : null,
           user: wallet.publicKey,
           systemProgram: SystemProgram.programId,
         })
@@ -124,7 +163,7 @@ export function useSolanaContract() {
     } catch (error) {
       throw error;
     }
-  }, [program, wallet.publicKey, getTradingPoolPDA, getBuyOrderPDA, getTradingPoolVaultPDA]);
+  }, [program, wallet.publicKey, getTradingPoolPDA, getUserAccountPDA, getUserEligibilityPDA, getBuyOrderPDA, getTradingPoolVaultPDA, getTradingSchedulePDA, getStockMintInfoPDA]);
 
   const getUserStockBalance = useCallback(async (stockSymbol: string) => {
     if (!program || !wallet.publicKey) return 0;
@@ -182,6 +221,9 @@ export function useSolanaContract() {
     
     // PDA helpers
     getTradingPoolPDA,
+    getTradingSchedulePDA,
+    getUserAccountPDA,
+    getUserEligibilityPDA,
     getBuyOrderPDA,
     getStockMintPDA,
   };
@@ -1,852 +1,9020 @@
+// Several handlers and the CPI client generated for them under the `cpi`
+// feature take more arguments than clippy allows.
+#![allow(clippy::too_many_arguments)]
+
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Mint, MintTo, Burn, Transfer};
-use anchor_spl::associated_token::AssociatedToken;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer};
+use anchor_spl::token_2022::{spl_token_2022::onchain, Token2022};
+use anchor_spl::token_interface::{self, TokenInterface, TransferChecked};
+use anchor_spl::associated_token::{
+    get_associated_token_address, get_associated_token_address_with_program_id, AssociatedToken,
+};
+use anchor_spl::metadata::{
+    self, mpl_token_metadata::types::DataV2, CreateMetadataAccountsV3, Metadata,
+    UpdateMetadataAccountsV2,
+};
+use solana_instructions_sysvar::{load_current_index_checked, load_instruction_at_checked};
 
 declare_id!("9MWyubXRFZawmGVE9WqQXCvQnS1YiRx3u35vkeKaNbrL");
 
-#[program]
-pub mod stock_contracts {
+// Code generated by `#[program]` still calls the deprecated `AccountInfo::realloc`,
+// in modules it emits next to the program module, so both live in this one.
+#[allow(deprecated)]
+mod program_module {
     use super::*;
 
-    pub fn initialize_trading_pool(
-        ctx: Context<InitializeTradingPool>,
-        vault_authority: Pubkey,
-        backend_authority: Pubkey,
-    ) -> Result<()> {
-        let trading_pool = &mut ctx.accounts.trading_pool;
-        trading_pool.vault_authority = vault_authority;
-        trading_pool.backend_authority = backend_authority;
-        trading_pool.total_orders = 0;
-        trading_pool.bump = ctx.bumps.trading_pool;
-        
-        Ok(())
-    }
-
-    pub fn create_stock_mint(
-        ctx: Context<CreateStockMint>,
-        stock_symbol: String,
-        decimals: u8,
-    ) -> Result<()> {
-        require!(stock_symbol.len() <= 10, StockTradingError::StockSymbolTooLong);
-        
-        let stock_mint_info = &mut ctx.accounts.stock_mint_info;
-        stock_mint_info.stock_symbol = stock_symbol.clone();
-        stock_mint_info.mint = ctx.accounts.stock_mint.key();
-        stock_mint_info.total_supply = 0;
-        stock_mint_info.bump = ctx.bumps.stock_mint_info;
-
-        emit!(StockMintCreated {
-            stock_symbol,
-            mint: ctx.accounts.stock_mint.key(),
-            decimals,
-        });
-
-        Ok(())
-    }
+    #[program]
+    pub mod stock_contracts {
+        use super::*;
 
-    pub fn place_buy_order(
-        ctx: Context<PlaceBuyOrder>,
-        stock_symbol: String,
-        sol_amount: u64,
-        max_price_per_share: u64,
-    ) -> Result<()> {
-        require!(stock_symbol.len() <= 10, StockTradingError::StockSymbolTooLong);
-        require!(sol_amount > 0, StockTradingError::InvalidAmount);
+        pub fn initialize_trading_pool(
+            ctx: Context<InitializeTradingPool>,
+            vault_authority: Pubkey,
+            backend_authority: Pubkey,
+            withdrawal_delay: i64,
+        ) -> Result<()> {
+            require!(
+                (0..=MAX_WITHDRAWAL_DELAY).contains(&withdrawal_delay),
+                StockTradingError::InvalidWithdrawalDelay
+            );
 
-        let trading_pool = &mut ctx.accounts.trading_pool;
-        let buy_order = &mut ctx.accounts.buy_order;
+            let trading_pool = &mut ctx.accounts.trading_pool;
+            trading_pool.vault_authority = vault_authority;
+            trading_pool.backend_authority = backend_authority;
+            trading_pool.oracle_authority = backend_authority;
+            trading_pool.max_price_deviation_bps = DEFAULT_MAX_PRICE_DEVIATION_BPS;
+            trading_pool.max_price_staleness = DEFAULT_MAX_PRICE_STALENESS;
+            trading_pool.paused = false;
+            trading_pool.fee_authority = vault_authority;
+            trading_pool.fee_bps = 0;
+            trading_pool.multisig = None;
+            trading_pool.pending_vault_authority = None;
+            trading_pool.pending_backend_authority = None;
+            trading_pool.withdrawal_delay = withdrawal_delay;
+            trading_pool.pending_withdrawal = None;
+            trading_pool.reserved_lamports = 0;
+            trading_pool.max_attestation_staleness = DEFAULT_MAX_ATTESTATION_STALENESS;
+            trading_pool.reserve_attestor = vault_authority;
+            trading_pool.compliance_authority = vault_authority;
+            trading_pool.enforce_eligibility = false;
+            trading_pool.bump = ctx.bumps.trading_pool;
 
-        // Transfer SOL from user to trading pool vault
-        let transfer_instruction = anchor_lang::system_program::Transfer {
-            from: ctx.accounts.user.to_account_info(),
-            to: ctx.accounts.trading_pool_vault.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
-            transfer_instruction,
-        );
-        anchor_lang::system_program::transfer(cpi_ctx, sol_amount)?;
-
-        // Initialize buy order
-        buy_order.user = ctx.accounts.user.key();
-        buy_order.stock_symbol = stock_symbol.clone();
-        buy_order.sol_amount = sol_amount;
-        buy_order.max_price_per_share = max_price_per_share;
-        buy_order.order_id = trading_pool.total_orders;
-        buy_order.status = OrderStatus::Pending;
-        buy_order.timestamp = Clock::get()?.unix_timestamp;
-        buy_order.shares_received = 0;
-        buy_order.actual_price_per_share = 0;
-        buy_order.bump = ctx.bumps.buy_order;
-
-        trading_pool.total_orders += 1;
-
-        emit!(BuyOrderPlaced {
-            order_id: buy_order.order_id,
-            user: buy_order.user,
-            stock_symbol: stock_symbol,
-            sol_amount: sol_amount,
-            max_price_per_share: max_price_per_share,
-            timestamp: buy_order.timestamp,
-        });
+            // Fund the fee vault up to rent exemption so small fees can land in it
+            let transfer_instruction = anchor_lang::system_program::Transfer {
+                from: ctx.accounts.payer.to_account_info(),
+                to: ctx.accounts.fee_vault.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                transfer_instruction,
+            );
+            anchor_lang::system_program::transfer(cpi_ctx, Rent::get()?.minimum_balance(0))?;
+        
+            Ok(())
+        }
 
-        Ok(())
-    }
+        pub fn create_stock_mint(
+            ctx: Context<CreateStockMint>,
+            stock_symbol: String,
+            decimals: u8,
+            name: String,
+            uri: String,
+        ) -> Result<()> {
+            list_stock_mint(
+                &mut ctx.accounts.stock_mint_info,
+                ctx.bumps.stock_mint_info,
+                CreateMetadataAccountsV3 {
+                    metadata: ctx.accounts.metadata.to_account_info(),
+                    mint: ctx.accounts.stock_mint.to_account_info(),
+                    mint_authority: ctx.accounts.trading_pool.to_account_info(),
+                    payer: ctx.accounts.vault_authority.to_account_info(),
+                    update_authority: ctx.accounts.trading_pool.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    rent: ctx.accounts.rent.to_account_info(),
+                },
+                ctx.accounts.token_metadata_program.to_account_info(),
+                ctx.accounts.trading_pool.bump,
+                ctx.accounts.token_program.key(),
+                None,
+                stock_symbol,
+                decimals,
+                name,
+                uri,
+            )
+        }
 
-    pub fn fulfill_buy_order(
-        ctx: Context<FulfillBuyOrder>,
-        shares_purchased: u64,
-        price_per_share: u64,
-        total_cost: u64,
-        refund_amount: u64,
-    ) -> Result<()> {
-        let buy_order = &mut ctx.accounts.buy_order;
-        let trading_pool = &ctx.accounts.trading_pool;
-        
-        // Only backend authority can fulfill orders
-        require!(
-            ctx.accounts.backend_authority.key() == trading_pool.backend_authority,
-            StockTradingError::UnauthorizedBackend
-        );
+        /// Creates a Token-2022 stock mint whose transfers are checked by
+        /// `transfer_hook_program`, so shares cannot move to ineligible holders
+        /// outside the program.
+        pub fn create_token_2022_stock_mint(
+            ctx: Context<CreateToken2022StockMint>,
+            stock_symbol: String,
+            decimals: u8,
+            name: String,
+            uri: String,
+        ) -> Result<()> {
+            list_stock_mint(
+                &mut ctx.accounts.stock_mint_info,
+                ctx.bumps.stock_mint_info,
+                CreateMetadataAccountsV3 {
+                    metadata: ctx.accounts.metadata.to_account_info(),
+                    mint: ctx.accounts.stock_mint.to_account_info(),
+                    mint_authority: ctx.accounts.trading_pool.to_account_info(),
+                    payer: ctx.accounts.vault_authority.to_account_info(),
+                    update_authority: ctx.accounts.trading_pool.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    rent: ctx.accounts.rent.to_account_info(),
+                },
+                ctx.accounts.token_metadata_program.to_account_info(),
+                ctx.accounts.trading_pool.bump,
+                ctx.accounts.token_program.key(),
+                Some(ctx.accounts.transfer_hook_program.key()),
+                stock_symbol,
+                decimals,
+                name,
+                uri,
+            )
+        }
 
-        require!(
-            buy_order.status == OrderStatus::Pending,
-            StockTradingError::InvalidOrderStatus
-        );
-        require!(
-            price_per_share <= buy_order.max_price_per_share,
-            StockTradingError::PriceExceedsLimit
-        );
-        require!(
-            total_cost + refund_amount <= buy_order.sol_amount,
-            StockTradingError::InvalidCalculation
-        );
+        pub fn update_stock_metadata(
+            ctx: Context<UpdateStockMetadata>,
+            name: String,
+            uri: String,
+        ) -> Result<()> {
+            require!(name.len() <= MAX_STOCK_NAME_LEN, StockTradingError::StockNameTooLong);
+            require!(uri.len() <= MAX_METADATA_URI_LEN, StockTradingError::MetadataUriTooLong);
 
-        // Mint stock tokens to user
-        if shares_purchased > 0 {
+            let stock_mint_info = &ctx.accounts.stock_mint_info;
             let seeds = &[
                 b"trading_pool".as_ref(),
-                &[trading_pool.bump],
+                &[ctx.accounts.trading_pool.bump],
             ];
             let signer = &[&seeds[..]];
 
-            let cpi_accounts = MintTo {
-                mint: ctx.accounts.stock_mint.to_account_info(),
-                to: ctx.accounts.user_stock_token_account.to_account_info(),
-                authority: ctx.accounts.trading_pool.to_account_info(),
+            let cpi_accounts = UpdateMetadataAccountsV2 {
+                metadata: ctx.accounts.metadata.to_account_info(),
+                update_authority: ctx.accounts.trading_pool.to_account_info(),
             };
-            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_program = ctx.accounts.token_metadata_program.to_account_info();
             let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-            
-            token::mint_to(cpi_ctx, shares_purchased)?;
 
-            // Update stock mint info
-            let stock_mint_info = &mut ctx.accounts.stock_mint_info;
-            stock_mint_info.total_supply = stock_mint_info.total_supply
-                .checked_add(shares_purchased)
-                .ok_or(StockTradingError::Overflow)?;
+            metadata::update_metadata_accounts_v2(
+                cpi_ctx,
+                None,
+                Some(stock_metadata(
+                    name.clone(),
+                    stock_mint_info.stock_symbol.clone(),
+                    uri.clone(),
+                )),
+                None,
+                None,
+            )?;
+
+            emit!(StockMetadataUpdated {
+                stock_symbol: stock_mint_info.stock_symbol.clone(),
+                mint: stock_mint_info.mint,
+                name,
+                uri,
+                updated_by: ctx.accounts.vault_authority.key(),
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
         }
 
-        // Update order status
-        buy_order.status = OrderStatus::Fulfilled;
-        buy_order.shares_received = shares_purchased;
-        buy_order.actual_price_per_share = price_per_share;
+        pub fn initialize_price_feed(
+            ctx: Context<InitializePriceFeed>,
+            stock_symbol: String,
+            quote_mint: Option<Pubkey>,
+        ) -> Result<()> {
+            require!(stock_symbol.len() <= 10, StockTradingError::StockSymbolTooLong);
 
-        // Refund excess SOL if any
-        if refund_amount > 0 {
-            let vault_bump = ctx.bumps.trading_pool_vault;
-            let seeds = &[
-                b"trading_pool_vault".as_ref(),
-                &[vault_bump],
-            ];
-            let signer = &[&seeds[..]];
+            let price_feed = &mut ctx.accounts.price_feed;
+            price_feed.stock_symbol = stock_symbol;
+            price_feed.quote_mint = quote_mint;
+            price_feed.price = 0;
+            price_feed.confidence = 0;
+            price_feed.publish_time = 0;
+            price_feed.bump = ctx.bumps.price_feed;
 
-            let transfer_instruction = anchor_lang::system_program::Transfer {
-                from: ctx.accounts.trading_pool_vault.to_account_info(),
-                to: ctx.accounts.user.to_account_info(),
-            };
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.system_program.to_account_info(),
-                transfer_instruction,
-                signer,
+            Ok(())
+        }
+
+        pub fn update_price_feed(
+            ctx: Context<UpdatePriceFeed>,
+            price: u64,
+            confidence: u64,
+            publish_time: i64,
+        ) -> Result<()> {
+            let price_feed = &mut ctx.accounts.price_feed;
+
+            require!(price > 0, StockTradingError::InvalidAmount);
+            require!(
+                publish_time > price_feed.publish_time
+                    && publish_time <= Clock::get()?.unix_timestamp,
+                StockTradingError::InvalidPublishTime
             );
-            anchor_lang::system_program::transfer(cpi_ctx, refund_amount)?;
+
+            price_feed.price = price;
+            price_feed.confidence = confidence;
+            price_feed.publish_time = publish_time;
+
+            emit!(PriceFeedUpdated {
+                stock_symbol: price_feed.stock_symbol.clone(),
+                quote_mint: price_feed.quote_mint,
+                price,
+                confidence,
+                publish_time,
+            });
+
+            Ok(())
         }
 
-        emit!(BuyOrderFulfilled {
-            order_id: buy_order.order_id,
-            user: buy_order.user,
-            stock_symbol: buy_order.stock_symbol.clone(),
-            shares_purchased,
-            price_per_share,
-            total_cost,
-            refund_amount,
-            timestamp: Clock::get()?.unix_timestamp,
-        });
+        /// Records the shares of a symbol the brokerage holds in custody. The
+        /// instruction right before this one must be an ed25519 program
+        /// instruction in which the pool's `reserve_attestor` signs the
+        /// statement, and that `signature` is stored so third parties can audit
+        /// the backing of the supply over time.
+        pub fn attest_reserves(
+            ctx: Context<AttestReserves>,
+            stock_symbol: String,
+            attested_shares: u64,
+            attested_at: i64,
+            signature: [u8; 64],
+        ) -> Result<()> {
+            let now = Clock::get()?.unix_timestamp;
+            let reserve_attestation = &mut ctx.accounts.reserve_attestation;
+            require!(
+                attested_at > reserve_attestation.attested_at && attested_at <= now,
+                StockTradingError::InvalidAttestationTime
+            );
 
-        Ok(())
-    }
+            let reserve_attestor = ctx.accounts.trading_pool.reserve_attestor;
+            ReserveAttestation::verify_signature(
+                &ctx.accounts.instructions,
+                &reserve_attestor,
+                &ReserveAttestation::message(&stock_symbol, attested_shares, attested_at),
+                &signature,
+            )?;
 
-    pub fn place_sell_order(
-        ctx: Context<PlaceSellOrder>,
-        stock_symbol: String,
-        shares_to_sell: u64,
-        min_price_per_share: u64,
-    ) -> Result<()> {
-        require!(stock_symbol.len() <= 10, StockTradingError::StockSymbolTooLong);
-        require!(shares_to_sell > 0, StockTradingError::InvalidAmount);
+            reserve_attestation.stock_symbol = stock_symbol.clone();
+            reserve_attestation.attested_shares = attested_shares;
+            reserve_attestation.attested_at = attested_at;
+            reserve_attestation.signature = signature;
+            reserve_attestation.attested_by = reserve_attestor;
+            reserve_attestation.bump = ctx.bumps.reserve_attestation;
 
-        let trading_pool = &mut ctx.accounts.trading_pool;
-        let sell_order = &mut ctx.accounts.sell_order;
+            let stock_mint_info = &ctx.accounts.stock_mint_info;
+            emit!(ReservesAttested {
+                stock_symbol,
+                attested_shares,
+                outstanding_shares: stock_mint_info.shares_for(stock_mint_info.total_supply)?,
+                attested_at,
+                signature,
+                attested_by: reserve_attestor,
+                timestamp: now,
+            });
 
-        // Check user has enough tokens
-        require!(
-            ctx.accounts.user_stock_token_account.amount >= shares_to_sell,
-            StockTradingError::InsufficientTokens
-        );
+            Ok(())
+        }
 
-        // Transfer tokens from user to escrow (controlled by trading pool)
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.user_stock_token_account.to_account_info(),
-            to: ctx.accounts.escrow_token_account.to_account_info(),
-            authority: ctx.accounts.user.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        
-        token::transfer(cpi_ctx, shares_to_sell)?;
-
-        // Initialize sell order
-        sell_order.user = ctx.accounts.user.key();
-        sell_order.stock_symbol = stock_symbol.clone();
-        sell_order.shares_to_sell = shares_to_sell;
-        sell_order.min_price_per_share = min_price_per_share;
-        sell_order.order_id = trading_pool.total_orders;
-        sell_order.status = OrderStatus::Pending;
-        sell_order.timestamp = Clock::get()?.unix_timestamp;
-        sell_order.sol_received = 0;
-        sell_order.actual_price_per_share = 0;
-        sell_order.bump = ctx.bumps.sell_order;
-
-        trading_pool.total_orders += 1;
-
-        emit!(SellOrderPlaced {
-            order_id: sell_order.order_id,
-            user: sell_order.user,
-            stock_symbol: stock_symbol,
-            shares_to_sell: shares_to_sell,
-            min_price_per_share: min_price_per_share,
-            timestamp: sell_order.timestamp,
-        });
+        pub fn set_oracle_config(
+            ctx: Context<SetOracleConfig>,
+            oracle_authority: Option<Pubkey>,
+            max_price_deviation_bps: Option<u16>,
+            max_price_staleness: Option<i64>,
+            max_attestation_staleness: Option<i64>,
+            reserve_attestor: Option<Pubkey>,
+        ) -> Result<()> {
+            let trading_pool = &mut ctx.accounts.trading_pool;
+            require!(trading_pool.multisig.is_none(), StockTradingError::MultisigRequired);
 
-        Ok(())
-    }
+            trading_pool.set_oracle_config(
+                oracle_authority,
+                max_price_deviation_bps,
+                max_price_staleness,
+                max_attestation_staleness,
+                reserve_attestor,
+                Clock::get()?.unix_timestamp,
+            )
+        }
 
-    pub fn fulfill_sell_order(
-        ctx: Context<FulfillSellOrder>,
-        shares_sold: u64,
-        price_per_share: u64,
-        total_proceeds: u64,
-        shares_returned: u64,
-    ) -> Result<()> {
-        let sell_order = &mut ctx.accounts.sell_order;
-        let trading_pool = &ctx.accounts.trading_pool;
-        
-        // Only backend authority can fulfill orders
-        require!(
-            ctx.accounts.backend_authority.key() == trading_pool.backend_authority,
-            StockTradingError::UnauthorizedBackend
-        );
+        pub fn set_pause(
+            ctx: Context<SetPause>,
+            paused: Option<bool>,
+            trading_status: Option<TradingStatus>,
+        ) -> Result<()> {
+            let trading_pool = &mut ctx.accounts.trading_pool;
 
-        require!(
-            sell_order.status == OrderStatus::Pending,
-            StockTradingError::InvalidOrderStatus
-        );
-        require!(
-            price_per_share >= sell_order.min_price_per_share,
-            StockTradingError::PriceBelowMinimum
-        );
-        require!(
-            shares_sold + shares_returned == sell_order.shares_to_sell,
-            StockTradingError::InvalidCalculation
-        );
+            if let Some(paused) = paused {
+                trading_pool.paused = paused;
+            }
 
-        let seeds = &[
-            b"trading_pool".as_ref(),
-            &[trading_pool.bump],
-        ];
-        let signer = &[&seeds[..]];
+            let mut stock_symbol = None;
+            if let Some(trading_status) = trading_status {
+                let stock_mint_info = ctx.accounts.stock_mint_info
+                    .as_mut()
+                    .ok_or(StockTradingError::MissingStockMintInfo)?;
+                stock_mint_info.trading_status = trading_status;
+                stock_symbol = Some(stock_mint_info.stock_symbol.clone());
+            }
 
-        // Burn sold tokens from escrow
-        if shares_sold > 0 {
-            let cpi_accounts = Burn {
-                mint: ctx.accounts.stock_mint.to_account_info(),
-                from: ctx.accounts.escrow_token_account.to_account_info(),
-                authority: ctx.accounts.trading_pool.to_account_info(),
-            };
-            let cpi_program = ctx.accounts.token_program.to_account_info();
-            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-            
-            token::burn(cpi_ctx, shares_sold)?;
+            emit!(TradingPauseUpdated {
+                paused: trading_pool.paused,
+                stock_symbol,
+                trading_status,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        /// Replaces the market-hours schedule that order placement is checked
+        /// against. Sessions are in exchange local time, `utc_offset` seconds
+        /// ahead of UTC, so the offset is updated when daylight saving changes.
+        pub fn set_trading_schedule(
+            ctx: Context<SetTradingSchedule>,
+            utc_offset: i32,
+            sessions: [SessionWindow; 7],
+            holidays: Vec<i32>,
+            early_closes: Vec<EarlyClose>,
+            off_hours_policy: OffHoursPolicy,
+        ) -> Result<()> {
+            require!(
+                utc_offset.abs() <= MAX_UTC_OFFSET
+                    && sessions.iter().all(|session| {
+                        session.open_minute <= MINUTES_PER_DAY && session.close_minute <= MINUTES_PER_DAY
+                    })
+                    && holidays.len() <= MAX_SCHEDULE_HOLIDAYS
+                    && early_closes.len() <= MAX_SCHEDULE_EARLY_CLOSES
+                    && early_closes
+                        .iter()
+                        .all(|early_close| early_close.close_minute <= MINUTES_PER_DAY),
+                StockTradingError::InvalidTradingSchedule
+            );
+
+            let now = Clock::get()?.unix_timestamp;
+            let trading_schedule = &mut ctx.accounts.trading_schedule;
+            trading_schedule.utc_offset = utc_offset;
+            trading_schedule.sessions = sessions;
+            trading_schedule.holidays = holidays.clone();
+            trading_schedule.early_closes = early_closes.clone();
+            trading_schedule.off_hours_policy = off_hours_policy;
+            trading_schedule.updated_at = now;
+            trading_schedule.bump = ctx.bumps.trading_schedule;
+
+            emit!(TradingScheduleUpdated {
+                utc_offset,
+                sessions,
+                holidays,
+                early_closes,
+                off_hours_policy,
+                updated_by: ctx.accounts.vault_authority.key(),
+                timestamp: now,
+            });
+
+            Ok(())
+        }
+
+        /// Reports whether the market is in session and when it next opens.
+        pub fn get_market_status(ctx: Context<GetMarketStatus>) -> Result<MarketStatus> {
+            let trading_schedule = &ctx.accounts.trading_schedule;
+            let now = Clock::get()?.unix_timestamp;
+
+            Ok(MarketStatus {
+                is_open: trading_schedule.is_open(now),
+                next_open: trading_schedule.next_open(now),
+                off_hours_policy: trading_schedule.off_hours_policy,
+            })
+        }
+
+        /// Records a split (`numerator > denominator`) or reverse split of the
+        /// underlying stock. Token balances are left untouched: the mint's share
+        /// multiplier changes instead, so every balance, escrowed sell order and
+        /// `total_supply` is restated in shares at once. The symbol must be halted
+        /// while the action is applied so no fill straddles the old and new ratio.
+        pub fn apply_stock_split(
+            ctx: Context<ApplyStockSplit>,
+            numerator: u64,
+            denominator: u64,
+        ) -> Result<()> {
+            require!(numerator > 0 && denominator > 0, StockTradingError::InvalidSplitRatio);
+            require!(numerator != denominator, StockTradingError::InvalidSplitRatio);
 
-            // Update stock mint info
             let stock_mint_info = &mut ctx.accounts.stock_mint_info;
-            stock_mint_info.total_supply = stock_mint_info.total_supply
-                .checked_sub(shares_sold)
-                .ok_or(StockTradingError::Underflow)?;
+            require!(
+                stock_mint_info.trading_status != TradingStatus::Active,
+                StockTradingError::SymbolNotHalted
+            );
+
+            let order_book = &ctx.accounts.order_book;
+            if order_book.owner == &crate::ID {
+                let order_book = OrderBook::try_deserialize(&mut &order_book.data.borrow()[..])?;
+                require!(order_book.is_empty(), StockTradingError::OrderBookNotEmpty);
+            }
+
+            stock_mint_info.apply_split(numerator, denominator)?;
+
+            emit!(StockSplitApplied {
+                stock_symbol: stock_mint_info.stock_symbol.clone(),
+                mint: stock_mint_info.mint,
+                numerator,
+                denominator,
+                split_numerator: stock_mint_info.split_numerator,
+                split_denominator: stock_mint_info.split_denominator,
+                total_supply: stock_mint_info.total_supply,
+                total_shares: stock_mint_info.shares_for(stock_mint_info.total_supply)?,
+                applied_by: ctx.accounts.vault_authority.key(),
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
 
-            // Transfer SOL proceeds to user
-            let vault_bump = ctx.bumps.trading_pool_vault;
-            let vault_seeds = &[
-                b"trading_pool_vault".as_ref(),
-                &[vault_bump],
+        pub fn set_fee_config(
+            ctx: Context<SetFeeConfig>,
+            fee_bps: Option<u16>,
+            fee_authority: Option<Pubkey>,
+        ) -> Result<()> {
+            let trading_pool = &mut ctx.accounts.trading_pool;
+            require!(trading_pool.multisig.is_none(), StockTradingError::MultisigRequired);
+
+            trading_pool.set_fee_config(fee_bps, fee_authority, Clock::get()?.unix_timestamp)
+        }
+
+        pub fn set_stock_fee_override(
+            ctx: Context<SetStockFeeOverride>,
+            fee_bps_override: Option<u16>,
+        ) -> Result<()> {
+            if let Some(fee_bps) = fee_bps_override {
+                require!(fee_bps <= MAX_FEE_BPS, StockTradingError::FeeTooHigh);
+            }
+
+            let stock_mint_info = &mut ctx.accounts.stock_mint_info;
+            stock_mint_info.fee_bps_override = fee_bps_override;
+
+            emit!(StockFeeOverrideUpdated {
+                stock_symbol: stock_mint_info.stock_symbol.clone(),
+                fee_bps_override,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        /// Sets who keeps the KYC allowlist and whether orders are restricted to
+        /// eligible wallets.
+        pub fn set_compliance_config(
+            ctx: Context<SetComplianceConfig>,
+            compliance_authority: Option<Pubkey>,
+            enforce_eligibility: Option<bool>,
+        ) -> Result<()> {
+            let trading_pool = &mut ctx.accounts.trading_pool;
+            require!(trading_pool.multisig.is_none(), StockTradingError::MultisigRequired);
+
+            trading_pool.set_compliance_config(
+                compliance_authority,
+                enforce_eligibility,
+                Clock::get()?.unix_timestamp,
+            );
+            Ok(())
+        }
+
+        /// Approves a wallet after KYC, or renews its grant, for the given
+        /// jurisdiction until `expires_at`.
+        pub fn grant_eligibility(
+            ctx: Context<GrantEligibility>,
+            user: Pubkey,
+            jurisdiction: [u8; 2],
+            expires_at: Option<i64>,
+        ) -> Result<()> {
+            require!(
+                jurisdiction.iter().all(u8::is_ascii_uppercase),
+                StockTradingError::InvalidJurisdiction
+            );
+            let now = Clock::get()?.unix_timestamp;
+            if let Some(expires_at) = expires_at {
+                require!(expires_at > now, StockTradingError::InvalidExpiry);
+            }
+
+            let user_eligibility = &mut ctx.accounts.user_eligibility;
+            user_eligibility.user = user;
+            user_eligibility.status = EligibilityStatus::Approved;
+            user_eligibility.jurisdiction = jurisdiction;
+            user_eligibility.expires_at = expires_at;
+            user_eligibility.updated_by = ctx.accounts.compliance_authority.key();
+            user_eligibility.updated_at = now;
+            user_eligibility.bump = ctx.bumps.user_eligibility;
+
+            emit!(EligibilityGranted {
+                user,
+                jurisdiction,
+                expires_at,
+                granted_by: ctx.accounts.compliance_authority.key(),
+                timestamp: now,
+            });
+
+            Ok(())
+        }
+
+        /// Withdraws a wallet's eligibility. The record is kept for the audit
+        /// trail and can be granted again later.
+        pub fn revoke_eligibility(ctx: Context<RevokeEligibility>) -> Result<()> {
+            let now = Clock::get()?.unix_timestamp;
+            let user_eligibility = &mut ctx.accounts.user_eligibility;
+            user_eligibility.status = EligibilityStatus::Revoked;
+            user_eligibility.updated_by = ctx.accounts.compliance_authority.key();
+            user_eligibility.updated_at = now;
+
+            emit!(EligibilityRevoked {
+                user: user_eligibility.user,
+                revoked_by: ctx.accounts.compliance_authority.key(),
+                timestamp: now,
+            });
+
+            Ok(())
+        }
+
+        pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> Result<()> {
+            // The fee vault always keeps its rent-exempt minimum
+            let available = ctx.accounts.fee_vault
+                .lamports()
+                .saturating_sub(Rent::get()?.minimum_balance(0));
+            require!(amount <= available, StockTradingError::InsufficientFees);
+
+            let seeds = &[
+                b"fee_vault".as_ref(),
+                &[ctx.bumps.fee_vault],
             ];
-            let vault_signer = &[&vault_seeds[..]];
+            let signer = &[&seeds[..]];
 
             let transfer_instruction = anchor_lang::system_program::Transfer {
-                from: ctx.accounts.trading_pool_vault.to_account_info(),
-                to: ctx.accounts.user.to_account_info(),
+                from: ctx.accounts.fee_vault.to_account_info(),
+                to: ctx.accounts.fee_authority.to_account_info(),
             };
             let cpi_ctx = CpiContext::new_with_signer(
                 ctx.accounts.system_program.to_account_info(),
                 transfer_instruction,
-                vault_signer,
+                signer,
             );
-            anchor_lang::system_program::transfer(cpi_ctx, total_proceeds)?;
+            anchor_lang::system_program::transfer(cpi_ctx, amount)?;
+
+            emit!(FeesWithdrawn {
+                authority: ctx.accounts.fee_authority.key(),
+                quote_mint: None,
+                amount,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
         }
 
-        // Return unsold tokens to user
-        if shares_returned > 0 {
-            let cpi_accounts = Transfer {
-                from: ctx.accounts.escrow_token_account.to_account_info(),
-                to: ctx.accounts.user_stock_token_account.to_account_info(),
-                authority: ctx.accounts.trading_pool.to_account_info(),
-            };
-            let cpi_program = ctx.accounts.token_program.to_account_info();
-            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-            
-            token::transfer(cpi_ctx, shares_returned)?;
+        pub fn add_quote_mint(ctx: Context<AddQuoteMint>) -> Result<()> {
+            let quote_mint_config = &mut ctx.accounts.quote_mint_config;
+            quote_mint_config.mint = ctx.accounts.quote_mint.key();
+            quote_mint_config.enabled = true;
+            quote_mint_config.reserved_amount = 0;
+            quote_mint_config.bump = ctx.bumps.quote_mint_config;
+
+            emit!(QuoteMintUpdated {
+                mint: quote_mint_config.mint,
+                enabled: true,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
         }
 
-        // Update order status
-        sell_order.status = OrderStatus::Fulfilled;
-        sell_order.sol_received = total_proceeds;
-        sell_order.actual_price_per_share = price_per_share;
+        pub fn set_quote_mint_enabled(ctx: Context<SetQuoteMintEnabled>, enabled: bool) -> Result<()> {
+            // Disabling only blocks new orders; open orders still settle in the token
+            let quote_mint_config = &mut ctx.accounts.quote_mint_config;
+            quote_mint_config.enabled = enabled;
 
-        emit!(SellOrderFulfilled {
-            order_id: sell_order.order_id,
-            user: sell_order.user,
-            stock_symbol: sell_order.stock_symbol.clone(),
-            shares_sold,
-            price_per_share,
-            total_proceeds,
-            shares_returned,
-            timestamp: Clock::get()?.unix_timestamp,
-        });
+            emit!(QuoteMintUpdated {
+                mint: quote_mint_config.mint,
+                enabled,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
 
-        Ok(())
-    }
+            Ok(())
+        }
 
-    pub fn withdraw_vault_funds(
-        ctx: Context<WithdrawVaultFunds>,
-        amount: u64,
-    ) -> Result<()> {
-        let trading_pool = &ctx.accounts.trading_pool;
-        
-        // Only vault authority can withdraw
-        require!(
-            ctx.accounts.vault_authority.key() == trading_pool.vault_authority,
-            StockTradingError::UnauthorizedVaultAccess
-        );
+        pub fn withdraw_quote_fees(ctx: Context<WithdrawQuoteFees>, amount: u64) -> Result<()> {
+            require!(
+                amount <= ctx.accounts.quote_fee_vault.amount,
+                StockTradingError::InsufficientFees
+            );
 
-        let vault_bump = ctx.bumps.trading_pool_vault;
-        let seeds = &[
-            b"trading_pool_vault".as_ref(),
-            &[vault_bump],
-        ];
-        let signer = &[&seeds[..]];
+            transfer_from_quote_vault(
+                &ctx.accounts.quote_fee_vault,
+                &ctx.accounts.destination.to_account_info(),
+                &ctx.accounts.trading_pool,
+                &ctx.accounts.token_program,
+                amount,
+            )?;
 
-        let transfer_instruction = anchor_lang::system_program::Transfer {
-            from: ctx.accounts.trading_pool_vault.to_account_info(),
-            to: ctx.accounts.vault_authority.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new_with_signer(
-            ctx.accounts.system_program.to_account_info(),
-            transfer_instruction,
-            signer,
-        );
-        anchor_lang::system_program::transfer(cpi_ctx, amount)?;
+            emit!(FeesWithdrawn {
+                authority: ctx.accounts.fee_authority.key(),
+                quote_mint: Some(ctx.accounts.quote_fee_vault.mint),
+                amount,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
 
-        emit!(VaultFundsWithdrawn {
-            authority: ctx.accounts.vault_authority.key(),
-            amount,
-            timestamp: Clock::get()?.unix_timestamp,
-        });
+            Ok(())
+        }
 
-        Ok(())
-    }
+        pub fn place_buy_order(
+            ctx: Context<PlaceBuyOrder>,
+            stock_symbol: String,
+            sol_amount: u64,
+            max_price_per_share: u64,
+            expires_at: Option<i64>,
+        ) -> Result<()> {
+            require!(stock_symbol.len() <= 10, StockTradingError::StockSymbolTooLong);
+            require!(sol_amount > 0, StockTradingError::InvalidAmount);
 
-    pub fn deposit_vault_funds(
-        ctx: Context<DepositVaultFunds>,
-        amount: u64,
-    ) -> Result<()> {
-        let transfer_instruction = anchor_lang::system_program::Transfer {
-            from: ctx.accounts.vault_authority.to_account_info(),
-            to: ctx.accounts.trading_pool_vault.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
-            transfer_instruction,
-        );
-        anchor_lang::system_program::transfer(cpi_ctx, amount)?;
+            // Refuse new orders while trading is halted globally or for this symbol
+            require!(!ctx.accounts.trading_pool.paused, StockTradingError::TradingPaused);
+            require!(
+                ctx.accounts.stock_mint_info.trading_status == TradingStatus::Active,
+                StockTradingError::SymbolNotTradable
+            );
+            ctx.accounts.trading_pool.check_eligibility(
+                ctx.accounts.user_eligibility.as_deref(),
+                ctx.accounts.user.key(),
+            )?;
 
-        emit!(VaultFundsDeposited {
-            authority: ctx.accounts.vault_authority.key(),
-            amount,
-            timestamp: Clock::get()?.unix_timestamp,
-        });
+            let now = Clock::get()?.unix_timestamp;
+            if let Some(expires_at) = expires_at {
+                require!(expires_at > now, StockTradingError::InvalidExpiry);
+            }
 
-        Ok(())
-    }
+            let queued_until = schedule_order(&ctx.accounts.trading_schedule, now)?;
 
-    pub fn update_authorities(
-        ctx: Context<UpdateAuthorities>,
-        new_vault_authority: Option<Pubkey>,
-        new_backend_authority: Option<Pubkey>,
-    ) -> Result<()> {
-        let trading_pool = &mut ctx.accounts.trading_pool;
-        
-        // Only current vault authority can update authorities
-        require!(
-            ctx.accounts.current_vault_authority.key() == trading_pool.vault_authority,
-            StockTradingError::UnauthorizedVaultAccess
-        );
+            let trading_pool = &mut ctx.accounts.trading_pool;
+            let buy_order = &mut ctx.accounts.buy_order;
 
-        if let Some(new_vault_auth) = new_vault_authority {
-            trading_pool.vault_authority = new_vault_auth;
-        }
+            // The escrowed funds belong to the order until they are spent or refunded
+            let quote_mint = match &mut ctx.accounts.quote_mint_config {
+                None => {
+                    // Transfer SOL from user to trading pool vault
+                    let transfer_instruction = anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.user.to_account_info(),
+                        to: ctx.accounts.trading_pool_vault.to_account_info(),
+                    };
+                    let cpi_ctx = CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        transfer_instruction,
+                    );
+                    anchor_lang::system_program::transfer(cpi_ctx, sol_amount)?;
 
-        if let Some(new_backend_auth) = new_backend_authority {
-            trading_pool.backend_authority = new_backend_auth;
-        }
+                    trading_pool.reserve(sol_amount)?;
+                    None
+                }
+                Some(quote_mint_config) => {
+                    require!(quote_mint_config.enabled, StockTradingError::QuoteMintDisabled);
+                    let quote_mint = quote_mint_config.mint;
 
-        emit!(AuthoritiesUpdated {
-            vault_authority: trading_pool.vault_authority,
-            backend_authority: trading_pool.backend_authority,
-            timestamp: Clock::get()?.unix_timestamp,
-        });
+                    // Transfer quote tokens from user to the token's quote vault
+                    let cpi_accounts = Transfer {
+                        from: quote_token_account(&ctx.accounts.user_quote_token_account, quote_mint)?
+                            .to_account_info(),
+                        to: quote_token_account(&ctx.accounts.quote_vault, quote_mint)?
+                            .to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    };
+                    let token_program = ctx.accounts.token_program
+                        .as_ref()
+                        .ok_or(StockTradingError::MissingQuoteAccounts)?;
+                    let cpi_ctx = CpiContext::new(token_program.to_account_info(), cpi_accounts);
+                    token::transfer(cpi_ctx, sol_amount)?;
 
-        Ok(())
-    }
-}
+                    quote_mint_config.reserve(sol_amount)?;
+                    Some(quote_mint)
+                }
+            };
+
+            // Initialize buy order
+            buy_order.user = ctx.accounts.user.key();
+            buy_order.stock_symbol = stock_symbol.clone();
+            buy_order.sol_amount = sol_amount;
+            buy_order.max_price_per_share = max_price_per_share;
+            buy_order.order_id = ctx.accounts.user_account.next_order_id(
+                ctx.accounts.user.key(),
+                ctx.bumps.user_account,
+            )?;
+            buy_order.status = OrderStatus::Pending;
+            buy_order.timestamp = now;
+            buy_order.expires_at = expires_at;
+            buy_order.shares_received = 0;
+            buy_order.sol_spent = 0;
+            buy_order.fees_paid = 0;
+            buy_order.actual_price_per_share = 0;
+            buy_order.quote_mint = quote_mint;
+            buy_order.split_numerator = ctx.accounts.stock_mint_info.split_numerator;
+            buy_order.split_denominator = ctx.accounts.stock_mint_info.split_denominator;
+            buy_order.queued_until = queued_until;
+            buy_order.bump = ctx.bumps.buy_order;
+
+            emit!(BuyOrderPlaced {
+                order_id: buy_order.order_id,
+                user: buy_order.user,
+                stock_symbol,
+                quote_mint,
+                sol_amount,
+                max_price_per_share,
+                expires_at,
+                queued_until,
+                timestamp: buy_order.timestamp,
+            });
+
+            Ok(())
+        }
+
+        pub fn partially_fill_buy_order(
+            ctx: Context<FulfillBuyOrder>,
+            shares_purchased: u64,
+            price_per_share: u64,
+            total_cost: u64,
+        ) -> Result<()> {
+            require!(shares_purchased > 0, StockTradingError::InvalidAmount);
+
+            let fee_amount = ctx.accounts.apply_fill(
+                shares_purchased,
+                price_per_share,
+                total_cost,
+                ctx.bumps.trading_pool_vault,
+            )?;
+
+            let buy_order = &mut ctx.accounts.buy_order;
+            buy_order.status = OrderStatus::PartiallyFilled;
+
+            emit!(BuyOrderPartiallyFilled {
+                order_id: buy_order.order_id,
+                user: buy_order.user,
+                stock_symbol: buy_order.stock_symbol.clone(),
+                shares_purchased,
+                price_per_share,
+                total_cost,
+                fee_amount,
+                total_shares_received: buy_order.shares_received,
+                total_sol_spent: buy_order.sol_spent,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        pub fn fulfill_buy_order(
+            ctx: Context<FulfillBuyOrder>,
+            shares_purchased: u64,
+            price_per_share: u64,
+            total_cost: u64,
+            refund_amount: u64,
+        ) -> Result<()> {
+            ctx.accounts.fulfill(
+                shares_purchased,
+                price_per_share,
+                total_cost,
+                refund_amount,
+                ctx.bumps.trading_pool_vault,
+            )
+        }
+        pub fn place_sell_order<'info>(
+            ctx: Context<'_, '_, '_, 'info, PlaceSellOrder<'info>>,
+            stock_symbol: String,
+            shares_to_sell: u64,
+            min_price_per_share: u64,
+            expires_at: Option<i64>,
+        ) -> Result<()> {
+            require!(stock_symbol.len() <= 10, StockTradingError::StockSymbolTooLong);
+            require!(shares_to_sell > 0, StockTradingError::InvalidAmount);
+
+            // Refuse new orders while trading is halted globally or for this symbol
+            require!(!ctx.accounts.trading_pool.paused, StockTradingError::TradingPaused);
+            require!(
+                ctx.accounts.stock_mint_info.trading_status == TradingStatus::Active,
+                StockTradingError::SymbolNotTradable
+            );
+            ctx.accounts.trading_pool.check_eligibility(
+                ctx.accounts.user_eligibility.as_deref(),
+                ctx.accounts.user.key(),
+            )?;
+
+            let now = Clock::get()?.unix_timestamp;
+            if let Some(expires_at) = expires_at {
+                require!(expires_at > now, StockTradingError::InvalidExpiry);
+            }
+
+            let queued_until = schedule_order(&ctx.accounts.trading_schedule, now)?;
+
+            // Proceeds are paid in SOL unless an allowlisted quote token is given
+            let quote_mint = match &ctx.accounts.quote_mint_config {
+                None => None,
+                Some(quote_mint_config) => {
+                    require!(quote_mint_config.enabled, StockTradingError::QuoteMintDisabled);
+                    Some(quote_mint_config.mint)
+                }
+            };
+
+            let sell_order = &mut ctx.accounts.sell_order;
+
+            // Check user has enough tokens
+            require!(
+                ctx.accounts.user_stock_token_account.amount >= shares_to_sell,
+                StockTradingError::InsufficientTokens
+            );
+
+            // Transfer tokens from user to escrow (controlled by trading pool)
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.user_stock_token_account.to_account_info(),
+                mint: ctx.accounts.stock_mint.to_account_info(),
+                to: ctx.accounts.escrow_token_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts)
+                .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        
+            transfer_stock(cpi_ctx, shares_to_sell, ctx.accounts.stock_mint.decimals)?;
+
+            // Initialize sell order
+            sell_order.user = ctx.accounts.user.key();
+            sell_order.stock_symbol = stock_symbol.clone();
+            sell_order.shares_to_sell = shares_to_sell;
+            sell_order.min_price_per_share = min_price_per_share;
+            sell_order.order_id = ctx.accounts.user_account.next_order_id(
+                ctx.accounts.user.key(),
+                ctx.bumps.user_account,
+            )?;
+            sell_order.status = OrderStatus::Pending;
+            sell_order.timestamp = now;
+            sell_order.expires_at = expires_at;
+            sell_order.shares_sold = 0;
+            sell_order.sol_received = 0;
+            sell_order.fees_paid = 0;
+            sell_order.actual_price_per_share = 0;
+            sell_order.quote_mint = quote_mint;
+            sell_order.split_numerator = ctx.accounts.stock_mint_info.split_numerator;
+            sell_order.split_denominator = ctx.accounts.stock_mint_info.split_denominator;
+            sell_order.queued_until = queued_until;
+            sell_order.bump = ctx.bumps.sell_order;
+
+            emit!(SellOrderPlaced {
+                order_id: sell_order.order_id,
+                user: sell_order.user,
+                stock_symbol,
+                quote_mint,
+                shares_to_sell,
+                min_price_per_share,
+                expires_at,
+                queued_until,
+                timestamp: sell_order.timestamp,
+            });
+
+            Ok(())
+        }
+
+        pub fn partially_fill_sell_order(
+            ctx: Context<FulfillSellOrder>,
+            shares_sold: u64,
+            price_per_share: u64,
+            total_proceeds: u64,
+        ) -> Result<()> {
+            require!(shares_sold > 0, StockTradingError::InvalidAmount);
+
+            let fee_amount = ctx.accounts.apply_fill(
+                shares_sold,
+                price_per_share,
+                total_proceeds,
+                ctx.bumps.trading_pool_vault,
+            )?;
+
+            let sell_order = &mut ctx.accounts.sell_order;
+            require!(
+                sell_order.shares_sold < sell_order.shares_to_sell,
+                StockTradingError::InvalidCalculation
+            );
+            sell_order.status = OrderStatus::PartiallyFilled;
+
+            emit!(SellOrderPartiallyFilled {
+                order_id: sell_order.order_id,
+                user: sell_order.user,
+                stock_symbol: sell_order.stock_symbol.clone(),
+                shares_sold,
+                price_per_share,
+                total_proceeds,
+                fee_amount,
+                total_shares_sold: sell_order.shares_sold,
+                total_sol_received: sell_order.sol_received,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        pub fn fulfill_sell_order<'info>(
+            ctx: Context<'_, '_, '_, 'info, FulfillSellOrder<'info>>,
+            shares_sold: u64,
+            price_per_share: u64,
+            total_proceeds: u64,
+            shares_returned: u64,
+        ) -> Result<()> {
+            ctx.accounts.fulfill(
+                shares_sold,
+                price_per_share,
+                total_proceeds,
+                shares_returned,
+                ctx.bumps.trading_pool_vault,
+                ctx.remaining_accounts,
+            )
+        }
+
+        /// Fulfills many buy orders of one market in a single transaction, with
+        /// the same checks as `fulfill_buy_order`. `remaining_accounts` holds
+        /// `[buy_order, user, user_stock_token_account]` for each fill, followed
+        /// by the user's quote token account for quote orders and the user's
+        /// eligibility while it is enforced. The stock accounts must already
+        /// exist. Any invalid fill fails the whole batch.
+        pub fn fulfill_buy_orders_batch<'info>(
+            ctx: Context<'_, '_, 'info, 'info, FulfillBuyOrdersBatch<'info>>,
+            stock_symbol: String,
+            quote_mint: Option<Pubkey>,
+            fills: Vec<BuyFill>,
+        ) -> Result<()> {
+            let accounts_per_fill = if quote_mint.is_some() { 4 } else { 3 }
+                + usize::from(ctx.accounts.trading_pool.enforce_eligibility);
+            require!(
+                !fills.is_empty()
+                    && ctx.remaining_accounts.len() == fills.len() * accounts_per_fill,
+                StockTradingError::InvalidBatch
+            );
+
+            for (fill, accounts) in fills
+                .iter()
+                .zip(ctx.remaining_accounts.chunks(accounts_per_fill))
+            {
+                let mut order = ctx.accounts.order_accounts(accounts, &stock_symbol, quote_mint)?;
+                order.fulfill(
+                    fill.shares_purchased,
+                    fill.price_per_share,
+                    fill.total_cost,
+                    fill.refund_amount,
+                    ctx.bumps.trading_pool_vault,
+                )?;
+                ctx.accounts.carry_over(&order)?;
+            }
+
+            Ok(())
+        }
+
+        /// Fulfills many sell orders of one market in a single transaction, with
+        /// the same checks as `fulfill_sell_order`. `remaining_accounts` holds
+        /// `[sell_order, user, user_stock_token_account, escrow_token_account]`
+        /// for each fill, then the user's quote token account for quote orders,
+        /// the user's eligibility while it is enforced, then the fill's
+        /// `hook_account_count` transfer hook accounts. Any invalid fill fails
+        /// the whole batch.
+        pub fn fulfill_sell_orders_batch<'info>(
+            ctx: Context<'_, '_, 'info, 'info, FulfillSellOrdersBatch<'info>>,
+            stock_symbol: String,
+            quote_mint: Option<Pubkey>,
+            fills: Vec<SellFill>,
+        ) -> Result<()> {
+            require!(!fills.is_empty(), StockTradingError::InvalidBatch);
+            let accounts_per_fill = if quote_mint.is_some() { 5 } else { 4 }
+                + usize::from(ctx.accounts.trading_pool.enforce_eligibility);
+
+            let mut remaining_accounts = ctx.remaining_accounts;
+            for fill in fills.iter() {
+                let account_count = accounts_per_fill + fill.hook_account_count as usize;
+                require!(
+                    remaining_accounts.len() >= account_count,
+                    StockTradingError::InvalidBatch
+                );
+                let (accounts, rest) = remaining_accounts.split_at(account_count);
+                remaining_accounts = rest;
+                let (accounts, hook_accounts) = accounts.split_at(accounts_per_fill);
+
+                let mut order = ctx.accounts.order_accounts(accounts, &stock_symbol, quote_mint)?;
+                order.fulfill(
+                    fill.shares_sold,
+                    fill.price_per_share,
+                    fill.total_proceeds,
+                    fill.shares_returned,
+                    ctx.bumps.trading_pool_vault,
+                    hook_accounts,
+                )?;
+                ctx.accounts.carry_over(&order)?;
+            }
+            require!(remaining_accounts.is_empty(), StockTradingError::InvalidBatch);
+
+            Ok(())
+        }
+
+        pub fn cancel_buy_order(ctx: Context<CancelBuyOrder>) -> Result<()> {
+            let buy_order = &mut ctx.accounts.buy_order;
+
+            require!(buy_order.is_open(), StockTradingError::InvalidOrderStatus);
+
+            // Refund whatever has not been spent on earlier partial fills
+            let refund_amount = buy_order.remaining_sol()?;
+            match buy_order.quote_mint {
+                None => {
+                    transfer_from_vault(
+                        &ctx.accounts.trading_pool_vault,
+                        &ctx.accounts.user.to_account_info(),
+                        &ctx.accounts.system_program,
+                        ctx.bumps.trading_pool_vault,
+                        refund_amount,
+                    )?;
+                    ctx.accounts.trading_pool.release(refund_amount)?;
+                }
+                Some(quote_mint) => {
+                    let token_program = ctx.accounts.token_program
+                        .as_ref()
+                        .ok_or(StockTradingError::MissingQuoteAccounts)?;
+                    transfer_from_quote_vault(
+                        quote_token_account(&ctx.accounts.quote_vault, quote_mint)?,
+                        &quote_token_account(&ctx.accounts.user_quote_token_account, quote_mint)?
+                            .to_account_info(),
+                        &ctx.accounts.trading_pool,
+                        token_program,
+                        refund_amount,
+                    )?;
+                    quote_mint_config(&mut ctx.accounts.quote_mint_config, quote_mint)?
+                        .release(refund_amount)?;
+                }
+            }
+
+            buy_order.status = OrderStatus::Cancelled;
+
+            emit!(BuyOrderCancelled {
+                order_id: buy_order.order_id,
+                user: buy_order.user,
+                stock_symbol: buy_order.stock_symbol.clone(),
+                refund_amount,
+                cancelled_by: ctx.accounts.user.key(),
+                reason_code: USER_CANCELLED_REASON,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+            emit!(buy_order.receipt(Clock::get()?.unix_timestamp));
+
+            Ok(())
+        }
+
+        pub fn reject_buy_order(ctx: Context<RejectBuyOrder>, reason_code: u16) -> Result<()> {
+            let buy_order = &mut ctx.accounts.buy_order;
+
+            require!(buy_order.is_open(), StockTradingError::InvalidOrderStatus);
+            require!(
+                reason_code != USER_CANCELLED_REASON,
+                StockTradingError::InvalidReasonCode
+            );
+
+            // Refund whatever has not been spent on earlier partial fills
+            let refund_amount = buy_order.remaining_sol()?;
+            match buy_order.quote_mint {
+                None => {
+                    transfer_from_vault(
+                        &ctx.accounts.trading_pool_vault,
+                        &ctx.accounts.user,
+                        &ctx.accounts.system_program,
+                        ctx.bumps.trading_pool_vault,
+                        refund_amount,
+                    )?;
+                    ctx.accounts.trading_pool.release(refund_amount)?;
+                }
+                Some(quote_mint) => {
+                    let token_program = ctx.accounts.token_program
+                        .as_ref()
+                        .ok_or(StockTradingError::MissingQuoteAccounts)?;
+                    transfer_from_quote_vault(
+                        quote_token_account(&ctx.accounts.quote_vault, quote_mint)?,
+                        &quote_token_account(&ctx.accounts.user_quote_token_account, quote_mint)?
+                            .to_account_info(),
+                        &ctx.accounts.trading_pool,
+                        token_program,
+                        refund_amount,
+                    )?;
+                    quote_mint_config(&mut ctx.accounts.quote_mint_config, quote_mint)?
+                        .release(refund_amount)?;
+                }
+            }
+
+            buy_order.status = OrderStatus::Cancelled;
+
+            emit!(BuyOrderCancelled {
+                order_id: buy_order.order_id,
+                user: buy_order.user,
+                stock_symbol: buy_order.stock_symbol.clone(),
+                refund_amount,
+                cancelled_by: ctx.accounts.backend_authority.key(),
+                reason_code,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+            emit!(buy_order.receipt(Clock::get()?.unix_timestamp));
+
+            Ok(())
+        }
+
+        pub fn cancel_sell_order<'info>(
+            ctx: Context<'_, '_, '_, 'info, CancelSellOrder<'info>>,
+        ) -> Result<()> {
+            let sell_order = &mut ctx.accounts.sell_order;
+
+            require!(sell_order.is_open(), StockTradingError::InvalidOrderStatus);
+
+            // Return whatever has not been sold by earlier partial fills
+            let shares_returned = sell_order.remaining_shares()?;
+
+            let seeds = &[
+                b"trading_pool".as_ref(),
+                &[ctx.accounts.trading_pool.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.escrow_token_account.to_account_info(),
+                mint: ctx.accounts.stock_mint.to_account_info(),
+                to: ctx.accounts.user_stock_token_account.to_account_info(),
+                authority: ctx.accounts.trading_pool.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer)
+                .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+
+            transfer_stock(cpi_ctx, shares_returned, ctx.accounts.stock_mint.decimals)?;
+
+            sell_order.status = OrderStatus::Cancelled;
+
+            emit!(SellOrderCancelled {
+                order_id: sell_order.order_id,
+                user: sell_order.user,
+                stock_symbol: sell_order.stock_symbol.clone(),
+                shares_returned,
+                cancelled_by: ctx.accounts.user.key(),
+                reason_code: USER_CANCELLED_REASON,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+            emit!(sell_order.receipt(Clock::get()?.unix_timestamp));
+
+            close_escrow(
+                &ctx.accounts.escrow_token_account,
+                &ctx.accounts.user,
+                &ctx.accounts.trading_pool,
+                &ctx.accounts.token_program,
+            )?;
+
+            Ok(())
+        }
+
+        pub fn reject_sell_order<'info>(
+            ctx: Context<'_, '_, '_, 'info, RejectSellOrder<'info>>,
+            reason_code: u16,
+        ) -> Result<()> {
+            let sell_order = &mut ctx.accounts.sell_order;
+
+            require!(sell_order.is_open(), StockTradingError::InvalidOrderStatus);
+            require!(
+                reason_code != USER_CANCELLED_REASON,
+                StockTradingError::InvalidReasonCode
+            );
+
+            // Return whatever has not been sold by earlier partial fills
+            let shares_returned = sell_order.remaining_shares()?;
+
+            let seeds = &[
+                b"trading_pool".as_ref(),
+                &[ctx.accounts.trading_pool.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.escrow_token_account.to_account_info(),
+                mint: ctx.accounts.stock_mint.to_account_info(),
+                to: ctx.accounts.user_stock_token_account.to_account_info(),
+                authority: ctx.accounts.trading_pool.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer)
+                .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+
+            transfer_stock(cpi_ctx, shares_returned, ctx.accounts.stock_mint.decimals)?;
+
+            sell_order.status = OrderStatus::Cancelled;
+
+            emit!(SellOrderCancelled {
+                order_id: sell_order.order_id,
+                user: sell_order.user,
+                stock_symbol: sell_order.stock_symbol.clone(),
+                shares_returned,
+                cancelled_by: ctx.accounts.backend_authority.key(),
+                reason_code,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+            emit!(sell_order.receipt(Clock::get()?.unix_timestamp));
+
+            close_escrow(
+                &ctx.accounts.escrow_token_account,
+                &ctx.accounts.user,
+                &ctx.accounts.trading_pool,
+                &ctx.accounts.token_program,
+            )?;
+
+            Ok(())
+        }
+
+        pub fn reclaim_expired_buy_order(ctx: Context<ReclaimExpiredBuyOrder>) -> Result<()> {
+            let buy_order = &ctx.accounts.buy_order;
+            let now = Clock::get()?.unix_timestamp;
+
+            require!(buy_order.is_open(), StockTradingError::InvalidOrderStatus);
+            require!(buy_order.is_expired(now), StockTradingError::OrderNotExpired);
+
+            // Refund the unspent funds; the order account itself is closed to the user
+            let amount_returned = buy_order.remaining_sol()?;
+            match buy_order.quote_mint {
+                None => {
+                    transfer_from_vault(
+                        &ctx.accounts.trading_pool_vault,
+                        &ctx.accounts.user,
+                        &ctx.accounts.system_program,
+                        ctx.bumps.trading_pool_vault,
+                        amount_returned,
+                    )?;
+                    ctx.accounts.trading_pool.release(amount_returned)?;
+                }
+                Some(quote_mint) => {
+                    let token_program = ctx.accounts.token_program
+                        .as_ref()
+                        .ok_or(StockTradingError::MissingQuoteAccounts)?;
+                    transfer_from_quote_vault(
+                        quote_token_account(&ctx.accounts.quote_vault, quote_mint)?,
+                        &quote_token_account(&ctx.accounts.user_quote_token_account, quote_mint)?
+                            .to_account_info(),
+                        &ctx.accounts.trading_pool,
+                        token_program,
+                        amount_returned,
+                    )?;
+                    quote_mint_config(&mut ctx.accounts.quote_mint_config, quote_mint)?
+                        .release(amount_returned)?;
+                }
+            }
+
+            emit!(ExpiredOrderReclaimed {
+                order_id: buy_order.order_id,
+                user: buy_order.user,
+                stock_symbol: buy_order.stock_symbol.clone(),
+                side: OrderSide::Buy,
+                amount_returned,
+                reclaimed_by: ctx.accounts.cranker.key(),
+                timestamp: now,
+            });
+            let mut receipt = buy_order.receipt(now);
+            receipt.status = OrderStatus::Expired;
+            emit!(receipt);
+
+            Ok(())
+        }
+
+        pub fn reclaim_expired_sell_order<'info>(
+            ctx: Context<'_, '_, '_, 'info, ReclaimExpiredSellOrder<'info>>,
+        ) -> Result<()> {
+            let sell_order = &ctx.accounts.sell_order;
+            let now = Clock::get()?.unix_timestamp;
+
+            require!(sell_order.is_open(), StockTradingError::InvalidOrderStatus);
+            require!(sell_order.is_expired(now), StockTradingError::OrderNotExpired);
+
+            let amount_returned = sell_order.remaining_shares()?;
+
+            let seeds = &[
+                b"trading_pool".as_ref(),
+                &[ctx.accounts.trading_pool.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.escrow_token_account.to_account_info(),
+                mint: ctx.accounts.stock_mint.to_account_info(),
+                to: ctx.accounts.user_stock_token_account.to_account_info(),
+                authority: ctx.accounts.trading_pool.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer)
+                .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+
+            transfer_stock(cpi_ctx, amount_returned, ctx.accounts.stock_mint.decimals)?;
+
+            emit!(ExpiredOrderReclaimed {
+                order_id: sell_order.order_id,
+                user: sell_order.user,
+                stock_symbol: sell_order.stock_symbol.clone(),
+                side: OrderSide::Sell,
+                amount_returned,
+                reclaimed_by: ctx.accounts.cranker.key(),
+                timestamp: now,
+            });
+            let mut receipt = sell_order.receipt(now);
+            receipt.status = OrderStatus::Expired;
+            emit!(receipt);
+
+            close_escrow(
+                &ctx.accounts.escrow_token_account,
+                &ctx.accounts.user,
+                &ctx.accounts.trading_pool,
+                &ctx.accounts.token_program,
+            )?;
+
+            Ok(())
+        }
+
+        pub fn set_withdrawal_delay(
+            ctx: Context<SetWithdrawalDelay>,
+            withdrawal_delay: i64,
+        ) -> Result<()> {
+            let trading_pool = &mut ctx.accounts.trading_pool;
+
+            // The delay can only grow, so users can rely on the notice period they observed
+            require!(
+                withdrawal_delay >= trading_pool.withdrawal_delay
+                    && withdrawal_delay <= MAX_WITHDRAWAL_DELAY,
+                StockTradingError::InvalidWithdrawalDelay
+            );
+            trading_pool.withdrawal_delay = withdrawal_delay;
+
+            emit!(WithdrawalDelayUpdated {
+                withdrawal_delay,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        pub fn queue_vault_withdrawal(
+            ctx: Context<QueueVaultWithdrawal>,
+            amount: u64,
+            quote_mint: Option<Pubkey>,
+        ) -> Result<()> {
+            let trading_pool = &mut ctx.accounts.trading_pool;
+            require!(trading_pool.multisig.is_none(), StockTradingError::MultisigRequired);
+
+            // Quote tokens go to the vault authority's associated token account
+            let vault_authority = ctx.accounts.vault_authority.key();
+            let destination = match quote_mint {
+                None => vault_authority,
+                Some(mint) => get_associated_token_address(&vault_authority, &mint),
+            };
+            trading_pool.queue_withdrawal(
+                amount,
+                quote_mint,
+                destination,
+                vault_authority,
+                Clock::get()?.unix_timestamp,
+            )
+        }
+
+        pub fn execute_vault_withdrawal(ctx: Context<ExecuteVaultWithdrawal>) -> Result<()> {
+            let trading_pool = &mut ctx.accounts.trading_pool;
+            let withdrawal = trading_pool.pending_withdrawal
+                .take()
+                .ok_or(StockTradingError::NoPendingWithdrawal)?;
+
+            let now = Clock::get()?.unix_timestamp;
+            require!(
+                now >= withdrawal.executable_at,
+                StockTradingError::WithdrawalTimelockActive
+            );
+            require_keys_eq!(
+                ctx.accounts.destination.key(),
+                withdrawal.destination,
+                StockTradingError::InvalidDestination
+            );
+
+            // Funds backing open buy orders and the vault's rent reserve stay put
+            match withdrawal.quote_mint {
+                None => {
+                    require!(
+                        withdrawal.amount <= trading_pool.free_lamports(&ctx.accounts.trading_pool_vault)?,
+                        StockTradingError::InsufficientVaultFunds
+                    );
+
+                    transfer_from_vault(
+                        &ctx.accounts.trading_pool_vault,
+                        &ctx.accounts.destination.to_account_info(),
+                        &ctx.accounts.system_program,
+                        ctx.bumps.trading_pool_vault,
+                        withdrawal.amount,
+                    )?;
+                }
+                Some(quote_mint) => {
+                    let quote_vault = quote_token_account(&ctx.accounts.quote_vault, quote_mint)?;
+                    let free_amount = quote_mint_config(&mut ctx.accounts.quote_mint_config, quote_mint)?
+                        .free_amount(quote_vault);
+                    require!(
+                        withdrawal.amount <= free_amount,
+                        StockTradingError::InsufficientVaultFunds
+                    );
+
+                    let token_program = ctx.accounts.token_program
+                        .as_ref()
+                        .ok_or(StockTradingError::MissingQuoteAccounts)?;
+                    transfer_from_quote_vault(
+                        quote_vault,
+                        &ctx.accounts.destination.to_account_info(),
+                        trading_pool,
+                        token_program,
+                        withdrawal.amount,
+                    )?;
+                }
+            }
+
+            emit!(VaultFundsWithdrawn {
+                authority: withdrawal.queued_by,
+                quote_mint: withdrawal.quote_mint,
+                amount: withdrawal.amount,
+                timestamp: now,
+            });
+
+            Ok(())
+        }
+
+        pub fn cancel_vault_withdrawal(ctx: Context<CancelVaultWithdrawal>) -> Result<()> {
+            let trading_pool = &mut ctx.accounts.trading_pool;
+            require!(trading_pool.multisig.is_none(), StockTradingError::MultisigRequired);
+
+            trading_pool.cancel_withdrawal(
+                ctx.accounts.vault_authority.key(),
+                Clock::get()?.unix_timestamp,
+            )
+        }
+
+        pub fn deposit_vault_funds(
+            ctx: Context<DepositVaultFunds>,
+            amount: u64,
+        ) -> Result<()> {
+            let transfer_instruction = anchor_lang::system_program::Transfer {
+                from: ctx.accounts.vault_authority.to_account_info(),
+                to: ctx.accounts.trading_pool_vault.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                transfer_instruction,
+            );
+            anchor_lang::system_program::transfer(cpi_ctx, amount)?;
+
+            emit!(VaultFundsDeposited {
+                authority: ctx.accounts.vault_authority.key(),
+                amount,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        pub fn get_vault_balances(ctx: Context<GetVaultBalances>) -> Result<VaultBalances> {
+            let trading_pool = &ctx.accounts.trading_pool;
+            let vault = &ctx.accounts.trading_pool_vault;
+
+            let balances = VaultBalances {
+                total_lamports: vault.lamports(),
+                reserved_lamports: trading_pool.reserved_lamports,
+                rent_exempt_minimum: Rent::get()?.minimum_balance(vault.data_len()),
+                free_lamports: trading_pool.free_lamports(vault)?,
+            };
+
+            emit!(VaultBalancesReported {
+                total_lamports: balances.total_lamports,
+                reserved_lamports: balances.reserved_lamports,
+                rent_exempt_minimum: balances.rent_exempt_minimum,
+                free_lamports: balances.free_lamports,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(balances)
+        }
+
+        pub fn propose_authority_transfer(
+            ctx: Context<ProposeAuthorityTransfer>,
+            new_vault_authority: Option<Pubkey>,
+            new_backend_authority: Option<Pubkey>,
+        ) -> Result<()> {
+            let trading_pool = &mut ctx.accounts.trading_pool;
+            require!(trading_pool.multisig.is_none(), StockTradingError::MultisigRequired);
+
+            trading_pool.propose_authorities(
+                new_vault_authority,
+                new_backend_authority,
+                ctx.accounts.vault_authority.key(),
+                Clock::get()?.unix_timestamp,
+            );
+
+            Ok(())
+        }
+
+        pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+            let trading_pool = &mut ctx.accounts.trading_pool;
+            let new_authority = ctx.accounts.new_authority.key();
+            let mut accepted = false;
+
+            if trading_pool.pending_vault_authority == Some(new_authority) {
+                trading_pool.vault_authority = new_authority;
+                trading_pool.pending_vault_authority = None;
+                accepted = true;
+            }
+
+            if trading_pool.pending_backend_authority == Some(new_authority) {
+                trading_pool.backend_authority = new_authority;
+                trading_pool.pending_backend_authority = None;
+                accepted = true;
+            }
+
+            require!(accepted, StockTradingError::NoPendingAuthority);
+
+            emit!(AuthoritiesUpdated {
+                vault_authority: trading_pool.vault_authority,
+                backend_authority: trading_pool.backend_authority,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        pub fn create_multisig(
+            ctx: Context<CreateMultisig>,
+            owners: Vec<Pubkey>,
+            threshold: u8,
+        ) -> Result<()> {
+            let multisig = &mut ctx.accounts.multisig;
+            multisig.set_owners(owners.clone(), threshold)?;
+            multisig.proposal_count = 0;
+            multisig.min_proposal_id = 0;
+            multisig.bump = ctx.bumps.multisig;
+
+            // From now on withdrawals and authority changes go through proposals
+            ctx.accounts.trading_pool.multisig = Some(multisig.key());
+
+            emit!(MultisigCreated {
+                multisig: multisig.key(),
+                owners,
+                threshold,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        pub fn propose_multisig_action(
+            ctx: Context<ProposeMultisigAction>,
+            action: MultisigAction,
+        ) -> Result<()> {
+            let multisig = &mut ctx.accounts.multisig;
+            let owner_index = multisig.owner_index(&ctx.accounts.proposer.key())?;
+
+            let proposal = &mut ctx.accounts.proposal;
+            proposal.multisig = multisig.key();
+            proposal.proposal_id = multisig.proposal_count;
+            proposal.proposer = ctx.accounts.proposer.key();
+            proposal.action = action.clone();
+            // The proposer implicitly approves
+            proposal.approvals = 1 << owner_index;
+            proposal.executed = false;
+            proposal.created_at = Clock::get()?.unix_timestamp;
+            proposal.bump = ctx.bumps.proposal;
+
+            multisig.proposal_count = multisig.proposal_count
+                .checked_add(1)
+                .ok_or(StockTradingError::Overflow)?;
+
+            emit!(MultisigProposalCreated {
+                multisig: multisig.key(),
+                proposal_id: proposal.proposal_id,
+                proposer: proposal.proposer,
+                action,
+                timestamp: proposal.created_at,
+            });
+
+            Ok(())
+        }
+
+        pub fn approve_multisig_proposal(ctx: Context<ApproveMultisigProposal>) -> Result<()> {
+            let multisig = &ctx.accounts.multisig;
+            let proposal = &mut ctx.accounts.proposal;
+            let owner_index = multisig.owner_index(&ctx.accounts.owner.key())?;
+
+            require!(!proposal.executed, StockTradingError::ProposalAlreadyExecuted);
+            require!(
+                proposal.proposal_id >= multisig.min_proposal_id,
+                StockTradingError::StaleProposal
+            );
+            require!(
+                proposal.approvals & (1 << owner_index) == 0,
+                StockTradingError::ProposalAlreadyApproved
+            );
+
+            proposal.approvals |= 1 << owner_index;
+
+            emit!(MultisigProposalApproved {
+                multisig: multisig.key(),
+                proposal_id: proposal.proposal_id,
+                owner: ctx.accounts.owner.key(),
+                approvals: proposal.approval_count(),
+                threshold: multisig.threshold,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        pub fn execute_multisig_proposal(ctx: Context<ExecuteMultisigProposal>) -> Result<()> {
+            let multisig = &mut ctx.accounts.multisig;
+            let proposal = &mut ctx.accounts.proposal;
+            multisig.check_executable(proposal)?;
+
+            let timestamp = Clock::get()?.unix_timestamp;
+            let trading_pool = &mut ctx.accounts.trading_pool;
+            match proposal.action.clone() {
+                MultisigAction::WithdrawVaultFunds { amount, quote_mint, destination } => {
+                    trading_pool.queue_withdrawal(
+                        amount,
+                        quote_mint,
+                        destination,
+                        multisig.key(),
+                        timestamp,
+                    )?;
+                }
+                MultisigAction::CancelVaultWithdrawal => {
+                    trading_pool.cancel_withdrawal(multisig.key(), timestamp)?;
+                }
+                MultisigAction::UpdateAuthorities {
+                    new_vault_authority,
+                    new_backend_authority,
+                } => {
+                    trading_pool.propose_authorities(
+                        new_vault_authority,
+                        new_backend_authority,
+                        multisig.key(),
+                        timestamp,
+                    );
+                }
+                // Needs the distribution accounts, see `execute_distribution_proposal`
+                MultisigAction::CreateDistribution { .. } => {
+                    return err!(StockTradingError::InvalidProposalAction);
+                }
+                MultisigAction::SetOracleConfig {
+                    oracle_authority,
+                    max_price_deviation_bps,
+                    max_price_staleness,
+                    max_attestation_staleness,
+                    reserve_attestor,
+                } => {
+                    trading_pool.set_oracle_config(
+                        oracle_authority,
+                        max_price_deviation_bps,
+                        max_price_staleness,
+                        max_attestation_staleness,
+                        reserve_attestor,
+                        timestamp,
+                    )?;
+                }
+                MultisigAction::SetFeeConfig { fee_bps, fee_authority } => {
+                    trading_pool.set_fee_config(fee_bps, fee_authority, timestamp)?;
+                }
+                MultisigAction::SetComplianceConfig {
+                    compliance_authority,
+                    enforce_eligibility,
+                } => {
+                    trading_pool.set_compliance_config(
+                        compliance_authority,
+                        enforce_eligibility,
+                        timestamp,
+                    );
+                }
+                MultisigAction::SetOwners { owners, threshold } => {
+                    multisig.set_owners(owners.clone(), threshold)?;
+                    // Approvals are indexed by owner position, so pending
+                    // proposals cannot carry over to the new owner set
+                    multisig.min_proposal_id = multisig.proposal_count;
+
+                    emit!(MultisigOwnersUpdated {
+                        multisig: multisig.key(),
+                        owners,
+                        threshold,
+                        timestamp,
+                    });
+                }
+            }
+
+            proposal.executed = true;
+
+            emit!(MultisigProposalExecuted {
+                multisig: multisig.key(),
+                proposal_id: proposal.proposal_id,
+                executor: ctx.accounts.executor.key(),
+                timestamp,
+            });
+
+            Ok(())
+        }
+
+        /// Executes an approved `CreateDistribution` proposal. `params` must repeat
+        /// the proposed distribution, which sizes and seeds its account.
+        pub fn execute_distribution_proposal(
+            ctx: Context<ExecuteDistributionProposal>,
+            params: DistributionParams,
+        ) -> Result<()> {
+            let multisig = &ctx.accounts.multisig;
+            let proposal = &mut ctx.accounts.proposal;
+            multisig.check_executable(proposal)?;
+            require!(
+                proposal.action == MultisigAction::CreateDistribution { params: params.clone() },
+                StockTradingError::InvalidProposalAction
+            );
+
+            open_distribution(
+                &mut ctx.accounts.distribution,
+                &mut ctx.accounts.trading_pool,
+                &ctx.accounts.trading_pool_vault,
+                &mut ctx.accounts.quote_mint_config,
+                &ctx.accounts.quote_vault,
+                params,
+                ctx.bumps.distribution,
+            )?;
+
+            proposal.executed = true;
+
+            emit!(MultisigProposalExecuted {
+                multisig: multisig.key(),
+                proposal_id: proposal.proposal_id,
+                executor: ctx.accounts.executor.key(),
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        /// Publishes a dividend for holders of `stock_symbol` as of `record_date`.
+        /// The backend builds a Merkle tree of `(index, holder, amount)` leaves from
+        /// its holder snapshot; `total_amount` is earmarked in the SOL vault or the
+        /// quote vault until it is claimed or the distribution expires.
+        pub fn create_distribution(
+            ctx: Context<CreateDistribution>,
+            stock_symbol: String,
+            record_date: i64,
+            merkle_root: [u8; 32],
+            total_amount: u64,
+            claimant_count: u32,
+            expires_at: i64,
+            quote_mint: Option<Pubkey>,
+        ) -> Result<()> {
+            let trading_pool = &mut ctx.accounts.trading_pool;
+            require!(trading_pool.multisig.is_none(), StockTradingError::MultisigRequired);
+
+            open_distribution(
+                &mut ctx.accounts.distribution,
+                trading_pool,
+                &ctx.accounts.trading_pool_vault,
+                &mut ctx.accounts.quote_mint_config,
+                &ctx.accounts.quote_vault,
+                DistributionParams {
+                    stock_symbol,
+                    record_date,
+                    merkle_root,
+                    total_amount,
+                    claimant_count,
+                    expires_at,
+                    quote_mint,
+                },
+                ctx.bumps.distribution,
+            )
+        }
+
+        pub fn claim_dividend(
+            ctx: Context<ClaimDividend>,
+            index: u32,
+            amount: u64,
+            proof: Vec<[u8; 32]>,
+        ) -> Result<()> {
+            let distribution = &mut ctx.accounts.distribution;
+            let holder = ctx.accounts.holder.key();
+            let now = Clock::get()?.unix_timestamp;
+
+            require!(now < distribution.expires_at, StockTradingError::DistributionExpired);
+            require!(
+                now >= distribution.claimable_at,
+                StockTradingError::DistributionNotClaimable
+            );
+            require!(
+                index < distribution.claimant_count,
+                StockTradingError::InvalidMerkleProof
+            );
+            require!(
+                !distribution.is_claimed(index),
+                StockTradingError::DividendAlreadyClaimed
+            );
+            require!(
+                verify_merkle_proof(&proof, distribution.merkle_root, dividend_leaf(index, &holder, amount)),
+                StockTradingError::InvalidMerkleProof
+            );
+
+            distribution.set_claimed(index);
+            distribution.claimed_amount = distribution.claimed_amount
+                .checked_add(amount)
+                .ok_or(StockTradingError::Overflow)?;
+            require!(
+                distribution.claimed_amount <= distribution.total_amount,
+                StockTradingError::InvalidCalculation
+            );
+
+            match distribution.quote_mint {
+                None => {
+                    transfer_from_vault(
+                        &ctx.accounts.trading_pool_vault,
+                        &ctx.accounts.holder.to_account_info(),
+                        &ctx.accounts.system_program,
+                        ctx.bumps.trading_pool_vault,
+                        amount,
+                    )?;
+                    ctx.accounts.trading_pool.release(amount)?;
+                }
+                Some(quote_mint) => {
+                    let token_program = ctx.accounts.token_program
+                        .as_ref()
+                        .ok_or(StockTradingError::MissingQuoteAccounts)?;
+                    transfer_from_quote_vault(
+                        quote_token_account(&ctx.accounts.quote_vault, quote_mint)?,
+                        &quote_token_account(&ctx.accounts.holder_quote_token_account, quote_mint)?
+                            .to_account_info(),
+                        &ctx.accounts.trading_pool,
+                        token_program,
+                        amount,
+                    )?;
+                    quote_mint_config(&mut ctx.accounts.quote_mint_config, quote_mint)?
+                        .release(amount)?;
+                }
+            }
+
+            emit!(DividendClaimed {
+                stock_symbol: distribution.stock_symbol.clone(),
+                record_date: distribution.record_date,
+                quote_mint: distribution.quote_mint,
+                holder,
+                index,
+                amount,
+                timestamp: now,
+            });
+
+            Ok(())
+        }
+
+        /// Releases whatever was not claimed by the expiry back to the vault's free
+        /// balance. The distribution is kept as a tombstone rather than closed, so
+        /// it cannot be created again for the same record date and its proofs
+        /// replayed.
+        pub fn reclaim_expired_distribution(ctx: Context<ReclaimExpiredDistribution>) -> Result<()> {
+            let distribution = &mut ctx.accounts.distribution;
+            let now = Clock::get()?.unix_timestamp;
+
+            require!(now >= distribution.expires_at, StockTradingError::DistributionNotExpired);
+            require!(!distribution.reclaimed, StockTradingError::DistributionReclaimed);
+            distribution.reclaimed = true;
+
+            let amount_returned = distribution.unclaimed_amount()?;
+            match distribution.quote_mint {
+                None => ctx.accounts.trading_pool.release(amount_returned)?,
+                Some(quote_mint) => {
+                    quote_mint_config(&mut ctx.accounts.quote_mint_config, quote_mint)?
+                        .release(amount_returned)?;
+                }
+            }
+
+            emit!(UnclaimedDividendsReclaimed {
+                stock_symbol: distribution.stock_symbol.clone(),
+                record_date: distribution.record_date,
+                quote_mint: distribution.quote_mint,
+                amount_returned,
+                reclaimed_by: ctx.accounts.cranker.key(),
+                timestamp: now,
+            });
+
+            Ok(())
+        }
+
+        /// Opens the peer-to-peer order book of a stock, with the token account
+        /// that escrows the shares of its asks.
+        pub fn create_order_book(ctx: Context<CreateOrderBook>, stock_symbol: String) -> Result<()> {
+            let order_book = &mut ctx.accounts.order_book;
+            order_book.stock_symbol = stock_symbol.clone();
+            order_book.bids = Vec::new();
+            order_book.asks = Vec::new();
+            order_book.next_sequence = 0;
+            order_book.bump = ctx.bumps.order_book;
+
+            emit!(OrderBookCreated {
+                stock_symbol,
+                mint: ctx.accounts.stock_mint.key(),
+                book_escrow: ctx.accounts.book_escrow.key(),
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        /// Rests a bid on the order book. The SOL it costs at its limit price is
+        /// escrowed in the vault and stays reserved until the bid is matched,
+        /// cancelled or routed to the brokerage. On a full book it evicts the
+        /// worst bid, refunding `evicted_owner`, if it bids more.
+        pub fn place_bid(ctx: Context<PlaceBid>, shares: u64, price_per_share: u64) -> Result<()> {
+            require!(shares > 0 && price_per_share > 0, StockTradingError::InvalidAmount);
+            require!(!ctx.accounts.trading_pool.paused, StockTradingError::TradingPaused);
+            require!(
+                ctx.accounts.stock_mint_info.trading_status == TradingStatus::Active,
+                StockTradingError::SymbolNotTradable
+            );
+            ctx.accounts.trading_pool.check_eligibility(
+                ctx.accounts.user_eligibility.as_deref(),
+                ctx.accounts.user.key(),
+            )?;
+
+            let lamports = calculate_notional(
+                shares,
+                price_per_share,
+                &ctx.accounts.stock_mint_info,
+                Rounding::Up,
+            )?;
+            require!(lamports >= OrderBook::MIN_ORDER_LAMPORTS, StockTradingError::BookOrderTooSmall);
+
+            let transfer_instruction = anchor_lang::system_program::Transfer {
+                from: ctx.accounts.user.to_account_info(),
+                to: ctx.accounts.trading_pool_vault.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                transfer_instruction,
+            );
+            anchor_lang::system_program::transfer(cpi_ctx, lamports)?;
+            ctx.accounts.trading_pool.reserve(lamports)?;
+
+            let now = Clock::get()?.unix_timestamp;
+            let order_book = &mut ctx.accounts.order_book;
+            let sequence = order_book.next_sequence()?;
+            let evicted = order_book.insert(OrderSide::Buy, BookOrder {
+                sequence,
+                owner: ctx.accounts.user.key(),
+                price_per_share,
+                shares_remaining: shares,
+                lamports_remaining: lamports,
+                placed_at: now,
+            })?;
+
+            emit!(BookOrderPlaced {
+                stock_symbol: order_book.stock_symbol.clone(),
+                side: OrderSide::Buy,
+                sequence,
+                owner: ctx.accounts.user.key(),
+                price_per_share,
+                shares,
+                timestamp: now,
+            });
+
+            if let Some(evicted) = evicted {
+                let evicted_owner = ctx.accounts.evicted_owner
+                    .as_ref()
+                    .ok_or(StockTradingError::InvalidRefundAccount)?;
+                refund_bid(
+                    &evicted,
+                    evicted_owner,
+                    &mut ctx.accounts.trading_pool,
+                    &ctx.accounts.trading_pool_vault,
+                    &ctx.accounts.system_program,
+                    ctx.bumps.trading_pool_vault,
+                )?;
+
+                emit!(BookOrderCancelled {
+                    stock_symbol: ctx.accounts.order_book.stock_symbol.clone(),
+                    side: OrderSide::Buy,
+                    sequence: evicted.sequence,
+                    owner: evicted.owner,
+                    shares_remaining: evicted.shares_remaining,
+                    cancelled_by: ctx.accounts.user.key(),
+                    timestamp: now,
+                });
+            }
+
+            Ok(())
+        }
+
+        /// Rests an ask on the order book, escrowing its shares in the book escrow.
+        /// On a full book it evicts the worst ask, returning its shares to
+        /// `evicted_owner_stock_token_account`, if it asks less.
+        /// `remaining_accounts` holds the transfer hook accounts of both
+        /// transfers.
+        pub fn place_ask<'info>(
+            ctx: Context<'_, '_, '_, 'info, PlaceAsk<'info>>,
+            shares: u64,
+            price_per_share: u64,
+        ) -> Result<()> {
+            require!(shares > 0 && price_per_share > 0, StockTradingError::InvalidAmount);
+            require!(!ctx.accounts.trading_pool.paused, StockTradingError::TradingPaused);
+            require!(
+                ctx.accounts.stock_mint_info.trading_status == TradingStatus::Active,
+                StockTradingError::SymbolNotTradable
+            );
+            ctx.accounts.trading_pool.check_eligibility(
+                ctx.accounts.user_eligibility.as_deref(),
+                ctx.accounts.user.key(),
+            )?;
+            require!(
+                ctx.accounts.user_stock_token_account.amount >= shares,
+                StockTradingError::InsufficientTokens
+            );
+            let notional = calculate_notional(
+                shares,
+                price_per_share,
+                &ctx.accounts.stock_mint_info,
+                Rounding::Down,
+            )?;
+            require!(notional >= OrderBook::MIN_ORDER_LAMPORTS, StockTradingError::BookOrderTooSmall);
+
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.user_stock_token_account.to_account_info(),
+                mint: ctx.accounts.stock_mint.to_account_info(),
+                to: ctx.accounts.book_escrow.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts)
+                .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        
+            transfer_stock(cpi_ctx, shares, ctx.accounts.stock_mint.decimals)?;
+
+            let now = Clock::get()?.unix_timestamp;
+            let order_book = &mut ctx.accounts.order_book;
+            let sequence = order_book.next_sequence()?;
+            let evicted = order_book.insert(OrderSide::Sell, BookOrder {
+                sequence,
+                owner: ctx.accounts.user.key(),
+                price_per_share,
+                shares_remaining: shares,
+                lamports_remaining: 0,
+                placed_at: now,
+            })?;
+
+            emit!(BookOrderPlaced {
+                stock_symbol: order_book.stock_symbol.clone(),
+                side: OrderSide::Sell,
+                sequence,
+                owner: ctx.accounts.user.key(),
+                price_per_share,
+                shares,
+                timestamp: now,
+            });
+
+            if let Some(evicted) = evicted {
+                let evicted_owner_stock_token_account = ctx.accounts.evicted_owner_stock_token_account
+                    .as_ref()
+                    .ok_or(StockTradingError::InvalidRefundAccount)?;
+                return_ask_shares(
+                    &evicted,
+                    evicted_owner_stock_token_account,
+                    &ctx.accounts.book_escrow,
+                    &ctx.accounts.stock_mint,
+                    &ctx.accounts.trading_pool,
+                    &ctx.accounts.token_program,
+                    ctx.remaining_accounts,
+                )?;
+
+                emit!(BookOrderCancelled {
+                    stock_symbol: ctx.accounts.order_book.stock_symbol.clone(),
+                    side: OrderSide::Sell,
+                    sequence: evicted.sequence,
+                    owner: evicted.owner,
+                    shares_remaining: evicted.shares_remaining,
+                    cancelled_by: ctx.accounts.user.key(),
+                    timestamp: now,
+                });
+            }
+
+            Ok(())
+        }
+
+        /// Takes a bid off the book and refunds its remaining escrow.
+        pub fn cancel_bid(ctx: Context<CancelBid>, sequence: u64) -> Result<()> {
+            let bid = ctx.accounts.order_book.remove(OrderSide::Buy, sequence)?;
+            require_keys_eq!(bid.owner, ctx.accounts.user.key(), StockTradingError::UnauthorizedUser);
+
+            if bid.lamports_remaining > 0 {
+                transfer_from_vault(
+                    &ctx.accounts.trading_pool_vault,
+                    &ctx.accounts.user.to_account_info(),
+                    &ctx.accounts.system_program,
+                    ctx.bumps.trading_pool_vault,
+                    bid.lamports_remaining,
+                )?;
+            }
+            ctx.accounts.trading_pool.release(bid.lamports_remaining)?;
+
+            emit!(BookOrderCancelled {
+                stock_symbol: ctx.accounts.order_book.stock_symbol.clone(),
+                side: OrderSide::Buy,
+                sequence,
+                owner: bid.owner,
+                shares_remaining: bid.shares_remaining,
+                cancelled_by: ctx.accounts.user.key(),
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        /// Takes an ask off the book and returns its unsold shares.
+        pub fn cancel_ask<'info>(
+            ctx: Context<'_, '_, '_, 'info, CancelAsk<'info>>,
+            sequence: u64,
+        ) -> Result<()> {
+            let ask = ctx.accounts.order_book.remove(OrderSide::Sell, sequence)?;
+            require_keys_eq!(ask.owner, ctx.accounts.user.key(), StockTradingError::UnauthorizedUser);
+
+            let seeds = &[
+                b"trading_pool".as_ref(),
+                &[ctx.accounts.trading_pool.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.book_escrow.to_account_info(),
+                mint: ctx.accounts.stock_mint.to_account_info(),
+                to: ctx.accounts.user_stock_token_account.to_account_info(),
+                authority: ctx.accounts.trading_pool.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer)
+                .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        
+            transfer_stock(cpi_ctx, ask.shares_remaining, ctx.accounts.stock_mint.decimals)?;
+
+            emit!(BookOrderCancelled {
+                stock_symbol: ctx.accounts.order_book.stock_symbol.clone(),
+                side: OrderSide::Sell,
+                sequence,
+                owner: ask.owner,
+                shares_remaining: ask.shares_remaining,
+                cancelled_by: ctx.accounts.user.key(),
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        /// Crosses the best bid with the best ask while their prices overlap, up
+        /// to `max_matches` times, settling each trade directly between the two
+        /// users. Anyone may crank it. `remaining_accounts` holds
+        /// `[buyer, buyer_stock_token_account, seller]` for each match in turn,
+        /// then the buyer's eligibility while it is enforced, each followed by
+        /// `hook_account_count` transfer hook accounts. A best bid whose buyer
+        /// can no longer receive the shares is evicted and refunded in place of
+        /// a match, so it cannot hold up the rest of the book.
+        pub fn match_orders<'info>(
+            ctx: Context<'_, '_, 'info, 'info, MatchOrders<'info>>,
+            max_matches: u8,
+            hook_account_count: u8,
+        ) -> Result<()> {
+            require!(!ctx.accounts.trading_pool.paused, StockTradingError::TradingPaused);
+            require!(
+                ctx.accounts.stock_mint_info.trading_status == TradingStatus::Active,
+                StockTradingError::SymbolNotTradable
+            );
+
+            let accounts_per_match = 3
+                + usize::from(ctx.accounts.trading_pool.enforce_eligibility)
+                + hook_account_count as usize;
+            let mut remaining_accounts = ctx.remaining_accounts;
+            let mut matches = 0;
+            while matches < max_matches && ctx.accounts.order_book.is_crossed() {
+                require!(
+                    remaining_accounts.len() >= accounts_per_match,
+                    StockTradingError::InvalidMatchAccount
+                );
+                let (accounts, rest) = remaining_accounts.split_at(accounts_per_match);
+                remaining_accounts = rest;
+
+                if ctx.accounts.can_receive_shares(accounts)? {
+                    ctx.accounts.settle_match(accounts, ctx.bumps.trading_pool_vault)?;
+                } else {
+                    ctx.accounts.evict_best_bid(&accounts[0], ctx.bumps.trading_pool_vault)?;
+                }
+                matches += 1;
+            }
+            require!(matches > 0, StockTradingError::NoCrossingOrders);
+
+            Ok(())
+        }
+
+        /// Moves the unmatched rest of a bid to the brokerage path, as a buy order
+        /// for the bid's remaining escrow at its limit price. The escrowed SOL
+        /// stays reserved for the new order.
+        pub fn route_bid_to_brokerage(ctx: Context<RouteBidToBrokerage>, sequence: u64) -> Result<()> {
+            require!(!ctx.accounts.trading_pool.paused, StockTradingError::TradingPaused);
+            require!(
+                ctx.accounts.stock_mint_info.trading_status == TradingStatus::Active,
+                StockTradingError::SymbolNotTradable
+            );
+
+            let bid = ctx.accounts.order_book.remove(OrderSide::Buy, sequence)?;
+            require_keys_eq!(bid.owner, ctx.accounts.user.key(), StockTradingError::UnauthorizedUser);
+
+            let now = Clock::get()?.unix_timestamp;
+            let stock_symbol = ctx.accounts.order_book.stock_symbol.clone();
+            let buy_order = &mut ctx.accounts.buy_order;
+            buy_order.user = ctx.accounts.user.key();
+            buy_order.stock_symbol = stock_symbol.clone();
+            buy_order.sol_amount = bid.lamports_remaining;
+            buy_order.max_price_per_share = bid.price_per_share;
+            buy_order.order_id = ctx.accounts.user_account.next_order_id(
+                ctx.accounts.user.key(),
+                ctx.bumps.user_account,
+            )?;
+            buy_order.status = OrderStatus::Pending;
+            buy_order.timestamp = now;
+            buy_order.expires_at = None;
+            buy_order.shares_received = 0;
+            buy_order.sol_spent = 0;
+            buy_order.fees_paid = 0;
+            buy_order.actual_price_per_share = 0;
+            buy_order.quote_mint = None;
+            buy_order.split_numerator = ctx.accounts.stock_mint_info.split_numerator;
+            buy_order.split_denominator = ctx.accounts.stock_mint_info.split_denominator;
+            buy_order.queued_until = None;
+            buy_order.bump = ctx.bumps.buy_order;
+
+            emit!(BookOrderRouted {
+                stock_symbol: stock_symbol.clone(),
+                side: OrderSide::Buy,
+                sequence,
+                owner: bid.owner,
+                order_id: buy_order.order_id,
+                timestamp: now,
+            });
+            emit!(BuyOrderPlaced {
+                order_id: buy_order.order_id,
+                user: buy_order.user,
+                stock_symbol,
+                quote_mint: None,
+                sol_amount: buy_order.sol_amount,
+                max_price_per_share: buy_order.max_price_per_share,
+                expires_at: None,
+                queued_until: None,
+                timestamp: now,
+            });
+
+            Ok(())
+        }
+
+        /// Moves the unmatched rest of an ask to the brokerage path, as a sell
+        /// order for its remaining shares at its limit price. The shares move from
+        /// the book escrow to the new order's escrow.
+        pub fn route_ask_to_brokerage<'info>(
+            ctx: Context<'_, '_, '_, 'info, RouteAskToBrokerage<'info>>,
+            sequence: u64,
+        ) -> Result<()> {
+            require!(!ctx.accounts.trading_pool.paused, StockTradingError::TradingPaused);
+            require!(
+                ctx.accounts.stock_mint_info.trading_status == TradingStatus::Active,
+                StockTradingError::SymbolNotTradable
+            );
+
+            let ask = ctx.accounts.order_book.remove(OrderSide::Sell, sequence)?;
+            require_keys_eq!(ask.owner, ctx.accounts.user.key(), StockTradingError::UnauthorizedUser);
+
+            let seeds = &[
+                b"trading_pool".as_ref(),
+                &[ctx.accounts.trading_pool.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.book_escrow.to_account_info(),
+                mint: ctx.accounts.stock_mint.to_account_info(),
+                to: ctx.accounts.escrow_token_account.to_account_info(),
+                authority: ctx.accounts.trading_pool.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer)
+                .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        
+            transfer_stock(cpi_ctx, ask.shares_remaining, ctx.accounts.stock_mint.decimals)?;
+
+            let now = Clock::get()?.unix_timestamp;
+            let stock_symbol = ctx.accounts.order_book.stock_symbol.clone();
+            let sell_order = &mut ctx.accounts.sell_order;
+            sell_order.user = ctx.accounts.user.key();
+            sell_order.stock_symbol = stock_symbol.clone();
+            sell_order.shares_to_sell = ask.shares_remaining;
+            sell_order.min_price_per_share = ask.price_per_share;
+            sell_order.order_id = ctx.accounts.user_account.next_order_id(
+                ctx.accounts.user.key(),
+                ctx.bumps.user_account,
+            )?;
+            sell_order.status = OrderStatus::Pending;
+            sell_order.timestamp = now;
+            sell_order.expires_at = None;
+            sell_order.shares_sold = 0;
+            sell_order.sol_received = 0;
+            sell_order.fees_paid = 0;
+            sell_order.actual_price_per_share = 0;
+            sell_order.quote_mint = None;
+            sell_order.split_numerator = ctx.accounts.stock_mint_info.split_numerator;
+            sell_order.split_denominator = ctx.accounts.stock_mint_info.split_denominator;
+            sell_order.queued_until = None;
+            sell_order.bump = ctx.bumps.sell_order;
+
+            emit!(BookOrderRouted {
+                stock_symbol: stock_symbol.clone(),
+                side: OrderSide::Sell,
+                sequence,
+                owner: ask.owner,
+                order_id: sell_order.order_id,
+                timestamp: now,
+            });
+            emit!(SellOrderPlaced {
+                order_id: sell_order.order_id,
+                user: sell_order.user,
+                stock_symbol,
+                quote_mint: None,
+                shares_to_sell: sell_order.shares_to_sell,
+                min_price_per_share: sell_order.min_price_per_share,
+                expires_at: None,
+                queued_until: None,
+                timestamp: now,
+            });
+
+            Ok(())
+        }
+
+        /// Takes an order its owner can no longer cancel or have matched off the
+        /// book and refunds it. `remaining_accounts` holds the owner of a bid
+        /// or the owner's associated stock token account for an ask, then any
+        /// transfer hook accounts returning the shares needs.
+        pub fn force_cancel_book_order<'info>(
+            ctx: Context<'_, '_, '_, 'info, AdminCancelBookOrders<'info>>,
+            side: OrderSide,
+            sequence: u64,
+        ) -> Result<()> {
+            let (refund_account, hook_accounts) = ctx.remaining_accounts
+                .split_first()
+                .ok_or(StockTradingError::InvalidRefundAccount)?;
+            let order = ctx.accounts.order_book.remove(side, sequence)?;
+            ctx.accounts.refund(
+                side,
+                &order,
+                refund_account,
+                hook_accounts,
+                ctx.bumps.trading_pool_vault,
+            )
+        }
+
+        /// Empties the book of a halted symbol, bids first and up to
+        /// `max_orders` orders at a time, refunding every owner, so that
+        /// `apply_stock_split` can go ahead. `remaining_accounts` holds the
+        /// refund account of each order in turn, as for
+        /// `force_cancel_book_order`, then the transfer hook accounts of all
+        /// returned asks.
+        pub fn flush_order_book<'info>(
+            ctx: Context<'_, '_, '_, 'info, AdminCancelBookOrders<'info>>,
+            max_orders: u8,
+        ) -> Result<()> {
+            require!(
+                ctx.accounts.stock_mint_info.trading_status != TradingStatus::Active,
+                StockTradingError::SymbolNotHalted
+            );
+
+            let order_book = &ctx.accounts.order_book;
+            let order_count = usize::from(max_orders).min(order_book.bids.len() + order_book.asks.len());
+            require!(
+                ctx.remaining_accounts.len() >= order_count,
+                StockTradingError::InvalidRefundAccount
+            );
+            let (refund_accounts, hook_accounts) = ctx.remaining_accounts.split_at(order_count);
+
+            for refund_account in refund_accounts {
+                let order_book = &mut ctx.accounts.order_book;
+                let (side, order) = if order_book.bids.is_empty() {
+                    (OrderSide::Sell, order_book.asks.remove(0))
+                } else {
+                    (OrderSide::Buy, order_book.bids.remove(0))
+                };
+                ctx.accounts.refund(
+                    side,
+                    &order,
+                    refund_account,
+                    hook_accounts,
+                    ctx.bumps.trading_pool_vault,
+                )?;
+            }
+
+            Ok(())
+        }
+
+        /// Opens the constant-product pool of a stock against SOL, with its LP
+        /// mint and share vault. Swap fees of `fee_bps` stay in the pool, so they
+        /// accrue to liquidity providers.
+        pub fn create_amm_pool(
+            ctx: Context<CreateAmmPool>,
+            stock_symbol: String,
+            fee_bps: u16,
+        ) -> Result<()> {
+            require!(fee_bps <= MAX_FEE_BPS, StockTradingError::FeeTooHigh);
+
+            let amm_pool = &mut ctx.accounts.amm_pool;
+            amm_pool.stock_symbol = stock_symbol.clone();
+            amm_pool.lp_mint = ctx.accounts.lp_mint.key();
+            amm_pool.stock_reserve = 0;
+            amm_pool.sol_reserve = 0;
+            amm_pool.fee_bps = fee_bps;
+            amm_pool.bump = ctx.bumps.amm_pool;
+
+            emit!(AmmPoolCreated {
+                stock_symbol,
+                lp_mint: ctx.accounts.lp_mint.key(),
+                stock_vault: ctx.accounts.amm_stock_vault.key(),
+                fee_bps,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        /// Adds shares and SOL at the pool's current ratio and mints LP tokens for
+        /// them. The first deposit sets the price and uses `max_sol_amount` in full.
+        pub fn deposit_liquidity<'info>(
+            ctx: Context<'_, '_, '_, 'info, DepositLiquidity<'info>>,
+            stock_amount: u64,
+            max_sol_amount: u64,
+            min_lp_tokens: u64,
+        ) -> Result<()> {
+            require!(stock_amount > 0 && max_sol_amount > 0, StockTradingError::InvalidAmount);
+            require!(!ctx.accounts.trading_pool.paused, StockTradingError::TradingPaused);
+            require!(
+                ctx.accounts.stock_mint_info.trading_status == TradingStatus::Active,
+                StockTradingError::SymbolNotTradable
+            );
+            ctx.accounts.trading_pool.check_eligibility(
+                ctx.accounts.user_eligibility.as_deref(),
+                ctx.accounts.user.key(),
+            )?;
+
+            let (sol_amount, lp_tokens) = ctx.accounts.amm_pool.deposit_amounts(
+                stock_amount,
+                max_sol_amount,
+                ctx.accounts.lp_mint.supply,
+            )?;
+            require!(
+                lp_tokens > 0 && lp_tokens >= min_lp_tokens,
+                StockTradingError::SlippageExceeded
+            );
+
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.user_stock_token_account.to_account_info(),
+                mint: ctx.accounts.stock_mint.to_account_info(),
+                to: ctx.accounts.amm_stock_vault.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts)
+                .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        
+            transfer_stock(cpi_ctx, stock_amount, ctx.accounts.stock_mint.decimals)?;
+
+            // The pool's SOL is held in the vault and stays reserved for it
+            let transfer_instruction = anchor_lang::system_program::Transfer {
+                from: ctx.accounts.user.to_account_info(),
+                to: ctx.accounts.trading_pool_vault.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                transfer_instruction,
+            );
+            anchor_lang::system_program::transfer(cpi_ctx, sol_amount)?;
+            ctx.accounts.trading_pool.reserve(sol_amount)?;
+
+            let seeds = &[
+                b"trading_pool".as_ref(),
+                &[ctx.accounts.trading_pool.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = token_interface::MintTo {
+                mint: ctx.accounts.lp_mint.to_account_info(),
+                to: ctx.accounts.user_lp_token_account.to_account_info(),
+                authority: ctx.accounts.trading_pool.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        
+            token_interface::mint_to(cpi_ctx, lp_tokens)?;
+
+            let amm_pool = &mut ctx.accounts.amm_pool;
+            amm_pool.stock_reserve = amm_pool.stock_reserve
+                .checked_add(stock_amount)
+                .ok_or(StockTradingError::Overflow)?;
+            amm_pool.sol_reserve = amm_pool.sol_reserve
+                .checked_add(sol_amount)
+                .ok_or(StockTradingError::Overflow)?;
+
+            emit!(LiquidityDeposited {
+                stock_symbol: amm_pool.stock_symbol.clone(),
+                provider: ctx.accounts.user.key(),
+                stock_amount,
+                sol_amount,
+                lp_tokens,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        /// Burns LP tokens for their share of both reserves. Allowed while trading
+        /// is halted, so liquidity can always leave.
+        pub fn withdraw_liquidity<'info>(
+            ctx: Context<'_, '_, '_, 'info, WithdrawLiquidity<'info>>,
+            lp_tokens: u64,
+            min_stock_amount: u64,
+            min_sol_amount: u64,
+        ) -> Result<()> {
+            require!(lp_tokens > 0, StockTradingError::InvalidAmount);
+
+            let (stock_amount, sol_amount) = ctx.accounts.amm_pool.withdrawal_amounts(
+                lp_tokens,
+                ctx.accounts.lp_mint.supply,
+            )?;
+            require!(
+                stock_amount >= min_stock_amount && sol_amount >= min_sol_amount,
+                StockTradingError::SlippageExceeded
+            );
+
+            let cpi_accounts = token_interface::Burn {
+                mint: ctx.accounts.lp_mint.to_account_info(),
+                from: ctx.accounts.user_lp_token_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        
+            token_interface::burn(cpi_ctx, lp_tokens)?;
+
+            let seeds = &[
+                b"trading_pool".as_ref(),
+                &[ctx.accounts.trading_pool.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.amm_stock_vault.to_account_info(),
+                mint: ctx.accounts.stock_mint.to_account_info(),
+                to: ctx.accounts.user_stock_token_account.to_account_info(),
+                authority: ctx.accounts.trading_pool.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer)
+                .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        
+            transfer_stock(cpi_ctx, stock_amount, ctx.accounts.stock_mint.decimals)?;
+
+            if sol_amount > 0 {
+                transfer_from_vault(
+                    &ctx.accounts.trading_pool_vault,
+                    &ctx.accounts.user.to_account_info(),
+                    &ctx.accounts.system_program,
+                    ctx.bumps.trading_pool_vault,
+                    sol_amount,
+                )?;
+            }
+            ctx.accounts.trading_pool.release(sol_amount)?;
+
+            let amm_pool = &mut ctx.accounts.amm_pool;
+            amm_pool.stock_reserve = amm_pool.stock_reserve
+                .checked_sub(stock_amount)
+                .ok_or(StockTradingError::Underflow)?;
+            amm_pool.sol_reserve = amm_pool.sol_reserve
+                .checked_sub(sol_amount)
+                .ok_or(StockTradingError::Underflow)?;
+
+            emit!(LiquidityWithdrawn {
+                stock_symbol: amm_pool.stock_symbol.clone(),
+                provider: ctx.accounts.user.key(),
+                stock_amount,
+                sol_amount,
+                lp_tokens,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        /// Trades against the pool at any hour: `OrderSide::Buy` pays `amount_in`
+        /// lamports for shares and `OrderSide::Sell` sells `amount_in` base units
+        /// of shares for SOL. The swap fee is taken from the input.
+        pub fn swap<'info>(
+            ctx: Context<'_, '_, '_, 'info, Swap<'info>>,
+            side: OrderSide,
+            amount_in: u64,
+            min_amount_out: u64,
+        ) -> Result<()> {
+            require!(amount_in > 0, StockTradingError::InvalidAmount);
+            require!(!ctx.accounts.trading_pool.paused, StockTradingError::TradingPaused);
+            require!(
+                ctx.accounts.stock_mint_info.trading_status == TradingStatus::Active,
+                StockTradingError::SymbolNotTradable
+            );
+            ctx.accounts.trading_pool.check_eligibility(
+                ctx.accounts.user_eligibility.as_deref(),
+                ctx.accounts.user.key(),
+            )?;
+
+            let (amount_out, fee_amount) = ctx.accounts.amm_pool.swap_amounts(side, amount_in)?;
+            require!(
+                amount_out > 0 && amount_out >= min_amount_out,
+                StockTradingError::SlippageExceeded
+            );
+
+            let seeds = &[
+                b"trading_pool".as_ref(),
+                &[ctx.accounts.trading_pool.bump],
+            ];
+            let signer = &[&seeds[..]];
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+
+            match side {
+                OrderSide::Buy => {
+                    let transfer_instruction = anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.user.to_account_info(),
+                        to: ctx.accounts.trading_pool_vault.to_account_info(),
+                    };
+                    let cpi_ctx = CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        transfer_instruction,
+                    );
+                    anchor_lang::system_program::transfer(cpi_ctx, amount_in)?;
+                    ctx.accounts.trading_pool.reserve(amount_in)?;
+
+                    let cpi_accounts = TransferChecked {
+                        from: ctx.accounts.amm_stock_vault.to_account_info(),
+                        mint: ctx.accounts.stock_mint.to_account_info(),
+                        to: ctx.accounts.user_stock_token_account.to_account_info(),
+                        authority: ctx.accounts.trading_pool.to_account_info(),
+                    };
+                    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer)
+                        .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+                    transfer_stock(cpi_ctx, amount_out, ctx.accounts.stock_mint.decimals)?;
+
+                    let amm_pool = &mut ctx.accounts.amm_pool;
+                    amm_pool.sol_reserve = amm_pool.sol_reserve
+                        .checked_add(amount_in)
+                        .ok_or(StockTradingError::Overflow)?;
+                    amm_pool.stock_reserve = amm_pool.stock_reserve
+                        .checked_sub(amount_out)
+                        .ok_or(StockTradingError::Underflow)?;
+                }
+                OrderSide::Sell => {
+                    let cpi_accounts = TransferChecked {
+                        from: ctx.accounts.user_stock_token_account.to_account_info(),
+                        mint: ctx.accounts.stock_mint.to_account_info(),
+                        to: ctx.accounts.amm_stock_vault.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    };
+                    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts)
+                        .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+                    transfer_stock(cpi_ctx, amount_in, ctx.accounts.stock_mint.decimals)?;
+
+                    transfer_from_vault(
+                        &ctx.accounts.trading_pool_vault,
+                        &ctx.accounts.user.to_account_info(),
+                        &ctx.accounts.system_program,
+                        ctx.bumps.trading_pool_vault,
+                        amount_out,
+                    )?;
+                    ctx.accounts.trading_pool.release(amount_out)?;
+
+                    let amm_pool = &mut ctx.accounts.amm_pool;
+                    amm_pool.stock_reserve = amm_pool.stock_reserve
+                        .checked_add(amount_in)
+                        .ok_or(StockTradingError::Overflow)?;
+                    amm_pool.sol_reserve = amm_pool.sol_reserve
+                        .checked_sub(amount_out)
+                        .ok_or(StockTradingError::Underflow)?;
+                }
+            }
+
+            let amm_pool = &ctx.accounts.amm_pool;
+            emit!(AmmSwapped {
+                stock_symbol: amm_pool.stock_symbol.clone(),
+                user: ctx.accounts.user.key(),
+                side,
+                amount_in,
+                amount_out,
+                fee_amount,
+                stock_reserve: amm_pool.stock_reserve,
+                sol_reserve: amm_pool.sol_reserve,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        /// Escrows SOL for a buy order that is only placed once the price feed
+        /// crosses `trigger_price` in `trigger_direction`.
+        pub fn place_conditional_buy_order(
+            ctx: Context<PlaceConditionalBuyOrder>,
+            stock_symbol: String,
+            sol_amount: u64,
+            trigger_price: u64,
+            trigger_direction: TriggerDirection,
+            max_price_per_share: u64,
+        ) -> Result<()> {
+            require!(stock_symbol.len() <= 10, StockTradingError::StockSymbolTooLong);
+            require!(sol_amount > 0, StockTradingError::InvalidAmount);
+            require!(trigger_price > 0, StockTradingError::InvalidTriggerPrice);
+
+            require!(!ctx.accounts.trading_pool.paused, StockTradingError::TradingPaused);
+            require!(
+                ctx.accounts.stock_mint_info.trading_status == TradingStatus::Active,
+                StockTradingError::SymbolNotTradable
+            );
+            ctx.accounts.trading_pool.check_eligibility(
+                ctx.accounts.user_eligibility.as_deref(),
+                ctx.accounts.user.key(),
+            )?;
+
+            // The SOL stays reserved in the vault until the order is triggered or cancelled
+            let transfer_instruction = anchor_lang::system_program::Transfer {
+                from: ctx.accounts.user.to_account_info(),
+                to: ctx.accounts.trading_pool_vault.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                transfer_instruction,
+            );
+            anchor_lang::system_program::transfer(cpi_ctx, sol_amount)?;
+            ctx.accounts.trading_pool.reserve(sol_amount)?;
+
+            let order_id = ctx.accounts.user_account.next_order_id(
+                ctx.accounts.user.key(),
+                ctx.bumps.user_account,
+            )?;
+            ctx.accounts.conditional_order.initialize(
+                ctx.accounts.user.key(),
+                stock_symbol,
+                order_id,
+                OrderSide::Buy,
+                sol_amount,
+                trigger_price,
+                trigger_direction,
+                max_price_per_share,
+                &ctx.accounts.stock_mint_info,
+                ctx.bumps.conditional_order,
+            )?;
+
+            emit!(ctx.accounts.conditional_order.placed_event());
+
+            Ok(())
+        }
+
+        /// Escrows shares for a sell order that is only placed once the price
+        /// feed crosses `trigger_price` in `trigger_direction`.
+        pub fn place_conditional_sell_order<'info>(
+            ctx: Context<'_, '_, '_, 'info, PlaceConditionalSellOrder<'info>>,
+            stock_symbol: String,
+            shares_to_sell: u64,
+            trigger_price: u64,
+            trigger_direction: TriggerDirection,
+            min_price_per_share: u64,
+        ) -> Result<()> {
+            require!(stock_symbol.len() <= 10, StockTradingError::StockSymbolTooLong);
+            require!(shares_to_sell > 0, StockTradingError::InvalidAmount);
+            require!(trigger_price > 0, StockTradingError::InvalidTriggerPrice);
+
+            require!(!ctx.accounts.trading_pool.paused, StockTradingError::TradingPaused);
+            require!(
+                ctx.accounts.stock_mint_info.trading_status == TradingStatus::Active,
+                StockTradingError::SymbolNotTradable
+            );
+            ctx.accounts.trading_pool.check_eligibility(
+                ctx.accounts.user_eligibility.as_deref(),
+                ctx.accounts.user.key(),
+            )?;
+            require!(
+                ctx.accounts.user_stock_token_account.amount >= shares_to_sell,
+                StockTradingError::InsufficientTokens
+            );
+
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.user_stock_token_account.to_account_info(),
+                mint: ctx.accounts.stock_mint.to_account_info(),
+                to: ctx.accounts.conditional_escrow.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts)
+                .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+
+            transfer_stock(cpi_ctx, shares_to_sell, ctx.accounts.stock_mint.decimals)?;
+
+            let order_id = ctx.accounts.user_account.next_order_id(
+                ctx.accounts.user.key(),
+                ctx.bumps.user_account,
+            )?;
+            ctx.accounts.conditional_order.initialize(
+                ctx.accounts.user.key(),
+                stock_symbol,
+                order_id,
+                OrderSide::Sell,
+                shares_to_sell,
+                trigger_price,
+                trigger_direction,
+                min_price_per_share,
+                &ctx.accounts.stock_mint_info,
+                ctx.bumps.conditional_order,
+            )?;
+
+            emit!(ctx.accounts.conditional_order.placed_event());
+
+            Ok(())
+        }
+
+        /// Permissionless crank that turns a conditional buy order whose trigger
+        /// the price feed has crossed into a buy order for the escrowed SOL. The
+        /// cranker pays for the new order and receives the conditional order's
+        /// rent.
+        pub fn trigger_conditional_buy_order(ctx: Context<TriggerConditionalBuyOrder>) -> Result<()> {
+            let conditional_order = &ctx.accounts.conditional_order;
+            require!(
+                conditional_order.side == OrderSide::Buy,
+                StockTradingError::InvalidOrderSide
+            );
+            require!(!ctx.accounts.trading_pool.paused, StockTradingError::TradingPaused);
+            require!(
+                ctx.accounts.stock_mint_info.trading_status == TradingStatus::Active,
+                StockTradingError::SymbolNotTradable
+            );
+
+            let price = ctx.accounts.price_feed.current_price(&ctx.accounts.trading_pool)?;
+            require!(
+                conditional_order.is_triggered(price, &ctx.accounts.stock_mint_info)?,
+                StockTradingError::TriggerNotReached
+            );
+
+            let now = Clock::get()?.unix_timestamp;
+            let queued_until = schedule_order(&ctx.accounts.trading_schedule, now)?;
+
+            // The escrowed SOL keeps its vault reservation, now on behalf of the buy order
+            let buy_order = &mut ctx.accounts.buy_order;
+            buy_order.user = conditional_order.user;
+            buy_order.stock_symbol = conditional_order.stock_symbol.clone();
+            buy_order.sol_amount = conditional_order.amount;
+            buy_order.max_price_per_share = conditional_order.limit_price;
+            buy_order.order_id = ctx.accounts.user_account.next_order_id(
+                conditional_order.user,
+                ctx.bumps.user_account,
+            )?;
+            buy_order.status = OrderStatus::Pending;
+            buy_order.timestamp = now;
+            buy_order.expires_at = None;
+            buy_order.shares_received = 0;
+            buy_order.sol_spent = 0;
+            buy_order.fees_paid = 0;
+            buy_order.actual_price_per_share = 0;
+            buy_order.quote_mint = None;
+            buy_order.split_numerator = conditional_order.split_numerator;
+            buy_order.split_denominator = conditional_order.split_denominator;
+            buy_order.queued_until = queued_until;
+            buy_order.bump = ctx.bumps.buy_order;
+
+            emit!(ConditionalOrderTriggered {
+                order_id: conditional_order.order_id,
+                user: conditional_order.user,
+                stock_symbol: conditional_order.stock_symbol.clone(),
+                side: OrderSide::Buy,
+                trigger_price: conditional_order.trigger_price,
+                price,
+                triggered_order_id: buy_order.order_id,
+                triggered_by: ctx.accounts.cranker.key(),
+                timestamp: now,
+            });
+            emit!(BuyOrderPlaced {
+                order_id: buy_order.order_id,
+                user: buy_order.user,
+                stock_symbol: buy_order.stock_symbol.clone(),
+                quote_mint: None,
+                sol_amount: buy_order.sol_amount,
+                max_price_per_share: buy_order.max_price_per_share,
+                expires_at: None,
+                queued_until,
+                timestamp: now,
+            });
+
+            Ok(())
+        }
+
+        /// Permissionless crank that turns a conditional sell order whose trigger
+        /// the price feed has crossed into a sell order for the escrowed shares.
+        /// The cranker pays for the new order and its escrow and receives the
+        /// conditional order's accounts' rent.
+        pub fn trigger_conditional_sell_order<'info>(
+            ctx: Context<'_, '_, '_, 'info, TriggerConditionalSellOrder<'info>>,
+        ) -> Result<()> {
+            let conditional_order = &ctx.accounts.conditional_order;
+            require!(
+                conditional_order.side == OrderSide::Sell,
+                StockTradingError::InvalidOrderSide
+            );
+            require!(!ctx.accounts.trading_pool.paused, StockTradingError::TradingPaused);
+            require!(
+                ctx.accounts.stock_mint_info.trading_status == TradingStatus::Active,
+                StockTradingError::SymbolNotTradable
+            );
+
+            let price = ctx.accounts.price_feed.current_price(&ctx.accounts.trading_pool)?;
+            require!(
+                conditional_order.is_triggered(price, &ctx.accounts.stock_mint_info)?,
+                StockTradingError::TriggerNotReached
+            );
+
+            let now = Clock::get()?.unix_timestamp;
+            let queued_until = schedule_order(&ctx.accounts.trading_schedule, now)?;
+
+            let seeds = &[
+                b"trading_pool".as_ref(),
+                &[ctx.accounts.trading_pool.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.conditional_escrow.to_account_info(),
+                mint: ctx.accounts.stock_mint.to_account_info(),
+                to: ctx.accounts.escrow_token_account.to_account_info(),
+                authority: ctx.accounts.trading_pool.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer)
+                .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+
+            transfer_stock(cpi_ctx, conditional_order.amount, ctx.accounts.stock_mint.decimals)?;
+
+            let sell_order = &mut ctx.accounts.sell_order;
+            sell_order.user = conditional_order.user;
+            sell_order.stock_symbol = conditional_order.stock_symbol.clone();
+            sell_order.shares_to_sell = conditional_order.amount;
+            sell_order.min_price_per_share = conditional_order.limit_price;
+            sell_order.order_id = ctx.accounts.user_account.next_order_id(
+                conditional_order.user,
+                ctx.bumps.user_account,
+            )?;
+            sell_order.status = OrderStatus::Pending;
+            sell_order.timestamp = now;
+            sell_order.expires_at = None;
+            sell_order.shares_sold = 0;
+            sell_order.sol_received = 0;
+            sell_order.fees_paid = 0;
+            sell_order.actual_price_per_share = 0;
+            sell_order.quote_mint = None;
+            sell_order.split_numerator = conditional_order.split_numerator;
+            sell_order.split_denominator = conditional_order.split_denominator;
+            sell_order.queued_until = queued_until;
+            sell_order.bump = ctx.bumps.sell_order;
+
+            emit!(ConditionalOrderTriggered {
+                order_id: conditional_order.order_id,
+                user: conditional_order.user,
+                stock_symbol: conditional_order.stock_symbol.clone(),
+                side: OrderSide::Sell,
+                trigger_price: conditional_order.trigger_price,
+                price,
+                triggered_order_id: sell_order.order_id,
+                triggered_by: ctx.accounts.cranker.key(),
+                timestamp: now,
+            });
+            emit!(SellOrderPlaced {
+                order_id: sell_order.order_id,
+                user: sell_order.user,
+                stock_symbol: sell_order.stock_symbol.clone(),
+                quote_mint: None,
+                shares_to_sell: sell_order.shares_to_sell,
+                min_price_per_share: sell_order.min_price_per_share,
+                expires_at: None,
+                queued_until,
+                timestamp: now,
+            });
+
+            close_escrow(
+                &ctx.accounts.conditional_escrow,
+                &ctx.accounts.cranker,
+                &ctx.accounts.trading_pool,
+                &ctx.accounts.token_program,
+            )?;
+
+            Ok(())
+        }
+
+        /// Refunds the SOL of an untriggered conditional buy order. Allowed at
+        /// any time, including while trading is paused.
+        pub fn cancel_conditional_buy_order(ctx: Context<CancelConditionalBuyOrder>) -> Result<()> {
+            let conditional_order = &ctx.accounts.conditional_order;
+            require!(
+                conditional_order.side == OrderSide::Buy,
+                StockTradingError::InvalidOrderSide
+            );
+
+            transfer_from_vault(
+                &ctx.accounts.trading_pool_vault,
+                &ctx.accounts.user.to_account_info(),
+                &ctx.accounts.system_program,
+                ctx.bumps.trading_pool_vault,
+                conditional_order.amount,
+            )?;
+            ctx.accounts.trading_pool.release(conditional_order.amount)?;
+
+            emit!(ConditionalOrderCancelled {
+                order_id: conditional_order.order_id,
+                user: conditional_order.user,
+                stock_symbol: conditional_order.stock_symbol.clone(),
+                side: OrderSide::Buy,
+                amount_returned: conditional_order.amount,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            Ok(())
+        }
+
+        /// Returns the shares of an untriggered conditional sell order. Allowed
+        /// at any time, including while trading is paused.
+        pub fn cancel_conditional_sell_order<'info>(
+            ctx: Context<'_, '_, '_, 'info, CancelConditionalSellOrder<'info>>,
+        ) -> Result<()> {
+            let conditional_order = &ctx.accounts.conditional_order;
+            require!(
+                conditional_order.side == OrderSide::Sell,
+                StockTradingError::InvalidOrderSide
+            );
+
+            let seeds = &[
+                b"trading_pool".as_ref(),
+                &[ctx.accounts.trading_pool.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.conditional_escrow.to_account_info(),
+                mint: ctx.accounts.stock_mint.to_account_info(),
+                to: ctx.accounts.user_stock_token_account.to_account_info(),
+                authority: ctx.accounts.trading_pool.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer)
+                .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+
+            transfer_stock(cpi_ctx, conditional_order.amount, ctx.accounts.stock_mint.decimals)?;
+
+            emit!(ConditionalOrderCancelled {
+                order_id: conditional_order.order_id,
+                user: conditional_order.user,
+                stock_symbol: conditional_order.stock_symbol.clone(),
+                side: OrderSide::Sell,
+                amount_returned: conditional_order.amount,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            close_escrow(
+                &ctx.accounts.conditional_escrow,
+                &ctx.accounts.user,
+                &ctx.accounts.trading_pool,
+                &ctx.accounts.token_program,
+            )?;
+
+            Ok(())
+        }
+    }
+}
+
+pub use program_module::*;
+
+/// Reason code recorded when the order's own user cancels it.
+pub const USER_CANCELLED_REASON: u16 = 0;
+
+pub const BPS_DENOMINATOR: u16 = 10_000;
+
+/// Upper bound on protocol fees, pool-wide or per symbol.
+pub const MAX_FEE_BPS: u16 = 1_000;
+
+/// Fill prices may deviate from the price feed by at most 2% by default.
+pub const DEFAULT_MAX_PRICE_DEVIATION_BPS: u16 = 200;
+
+/// Price feed updates older than a minute are rejected by default.
+pub const DEFAULT_MAX_PRICE_STALENESS: i64 = 60;
+
+/// Minting requires a reserve attestation from the last day by default.
+pub const DEFAULT_MAX_ATTESTATION_STALENESS: i64 = 86_400;
+
+/// Length of a one-signature ed25519 program instruction header: the count,
+/// a padding byte and seven u16 offsets.
+pub const ED25519_HEADER_LEN: usize = 2 + 14;
+
+/// Vault withdrawals wait at most 30 days, so a mistyped delay cannot lock
+/// the vault for years.
+pub const MAX_WITHDRAWAL_DELAY: i64 = 30 * SECONDS_PER_DAY;
+
+/// Stock mints can be divided down to a billionth of a share.
+pub const MAX_STOCK_DECIMALS: u8 = 9;
+
+/// Longest company name the token metadata program accepts.
+pub const MAX_STOCK_NAME_LEN: usize = 32;
+
+/// Longest metadata URI the token metadata program accepts.
+pub const MAX_METADATA_URI_LEN: usize = 200;
+
+/// Keeps a distribution's claimed bitmap within the 10 KiB an account can be
+/// created with.
+pub const MAX_DISTRIBUTION_CLAIMANTS: u32 = 65_536;
+
+/// LP tokens use SOL's precision.
+pub const AMM_LP_DECIMALS: u8 = 9;
+
+pub const SECONDS_PER_DAY: i64 = 86_400;
+
+pub const MINUTES_PER_DAY: u16 = 1_440;
+
+/// Exchange time zones range from UTC-12 to UTC+14.
+pub const MAX_UTC_OFFSET: i32 = 14 * 3_600;
+
+/// Roughly two years of exchange holidays.
+pub const MAX_SCHEDULE_HOLIDAYS: usize = 32;
+
+pub const MAX_SCHEDULE_EARLY_CLOSES: usize = 16;
+
+/// Queued orders wait for a session at most two weeks out.
+pub const SCHEDULE_LOOKAHEAD_DAYS: i64 = 14;
+
+/// Applies the trading schedule, once one is set, to an order placed at
+/// `now`. Outside market hours the order is rejected or queued for the next
+/// open, as the schedule's policy says; returns the open it is queued for.
+fn schedule_order(trading_schedule: &AccountInfo, now: i64) -> Result<Option<i64>> {
+    if trading_schedule.owner != &crate::ID {
+        return Ok(None);
+    }
+    let trading_schedule =
+        TradingSchedule::try_deserialize(&mut &trading_schedule.data.borrow()[..])?;
+    if trading_schedule.is_open(now) {
+        return Ok(None);
+    }
+
+    match trading_schedule.off_hours_policy {
+        OffHoursPolicy::Reject => err!(StockTradingError::MarketClosed),
+        OffHoursPolicy::Queue => trading_schedule
+            .next_open(now)
+            .map(Some)
+            .ok_or(error!(StockTradingError::MarketClosed)),
+    }
+}
+
+/// Earmarks a dividend in the SOL vault or the quote vault and records it.
+/// Claims only open once the pool's withdrawal delay has passed, so holders
+/// can check the published root before any funds leave.
+fn open_distribution<'info>(
+    distribution: &mut Account<'info, Distribution>,
+    trading_pool: &mut Account<'info, TradingPool>,
+    trading_pool_vault: &AccountInfo<'info>,
+    quote_mint_config: &mut Option<Account<'info, QuoteMintConfig>>,
+    quote_vault: &Option<Account<'info, TokenAccount>>,
+    params: DistributionParams,
+    bump: u8,
+) -> Result<()> {
+    let DistributionParams {
+        stock_symbol,
+        record_date,
+        merkle_root,
+        total_amount,
+        claimant_count,
+        expires_at,
+        quote_mint,
+    } = params;
+    require!(total_amount > 0, StockTradingError::InvalidAmount);
+    require!(
+        claimant_count > 0 && claimant_count <= MAX_DISTRIBUTION_CLAIMANTS,
+        StockTradingError::InvalidClaimantCount
+    );
+
+    let now = Clock::get()?.unix_timestamp;
+    let claimable_at = now
+        .checked_add(trading_pool.withdrawal_delay)
+        .ok_or(StockTradingError::Overflow)?;
+    require!(record_date <= now, StockTradingError::InvalidRecordDate);
+    require!(expires_at > claimable_at, StockTradingError::InvalidExpiry);
+
+    // Dividends can only be funded from the free balance
+    match quote_mint {
+        None => {
+            require!(
+                total_amount <= trading_pool.free_lamports(trading_pool_vault)?,
+                StockTradingError::InsufficientVaultFunds
+            );
+            trading_pool.reserve(total_amount)?;
+        }
+        Some(quote_mint) => {
+            let quote_vault = quote_token_account(quote_vault, quote_mint)?;
+            let quote_mint_config = crate::quote_mint_config(quote_mint_config, quote_mint)?;
+            require!(
+                total_amount <= quote_mint_config.free_amount(quote_vault),
+                StockTradingError::InsufficientVaultFunds
+            );
+            quote_mint_config.reserve(total_amount)?;
+        }
+    }
+
+    distribution.stock_symbol = stock_symbol.clone();
+    distribution.record_date = record_date;
+    distribution.quote_mint = quote_mint;
+    distribution.merkle_root = merkle_root;
+    distribution.total_amount = total_amount;
+    distribution.claimed_amount = 0;
+    distribution.claimant_count = claimant_count;
+    distribution.claimable_at = claimable_at;
+    distribution.expires_at = expires_at;
+    distribution.reclaimed = false;
+    distribution.claimed_bitmap = vec![0; Distribution::bitmap_len(claimant_count)];
+    distribution.bump = bump;
+
+    emit!(DistributionCreated {
+        stock_symbol,
+        record_date,
+        quote_mint,
+        merkle_root,
+        total_amount,
+        claimant_count,
+        claimable_at,
+        expires_at,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Transfers lamports out of the trading pool vault PDA.
+fn transfer_from_vault<'info>(
+    trading_pool_vault: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    vault_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let seeds = &[
+        b"trading_pool_vault".as_ref(),
+        &[vault_bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let transfer_instruction = anchor_lang::system_program::Transfer {
+        from: trading_pool_vault.clone(),
+        to: to.clone(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(
+        system_program.to_account_info(),
+        transfer_instruction,
+        signer,
+    );
+    anchor_lang::system_program::transfer(cpi_ctx, amount)
+}
+
+/// Refunds the SOL a book bid still escrows to its owner and releases it from
+/// the vault's reservation.
+fn refund_bid<'info>(
+    bid: &BookOrder,
+    owner: &AccountInfo<'info>,
+    trading_pool: &mut Account<'info, TradingPool>,
+    trading_pool_vault: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    vault_bump: u8,
+) -> Result<()> {
+    require_keys_eq!(owner.key(), bid.owner, StockTradingError::InvalidRefundAccount);
+
+    if bid.lamports_remaining > 0 {
+        transfer_from_vault(
+            trading_pool_vault,
+            owner,
+            system_program,
+            vault_bump,
+            bid.lamports_remaining,
+        )?;
+    }
+    trading_pool.release(bid.lamports_remaining)
+}
+
+/// Returns the unsold shares of a book ask from the book escrow to its owner's
+/// associated token account, with `hook_accounts` for a transfer hook.
+fn return_ask_shares<'info>(
+    ask: &BookOrder,
+    owner_stock_token_account: &AccountInfo<'info>,
+    book_escrow: &InterfaceAccount<'info, token_interface::TokenAccount>,
+    stock_mint: &InterfaceAccount<'info, token_interface::Mint>,
+    trading_pool: &Account<'info, TradingPool>,
+    token_program: &Interface<'info, TokenInterface>,
+    hook_accounts: &[AccountInfo<'info>],
+) -> Result<()> {
+    require_keys_eq!(
+        owner_stock_token_account.key(),
+        get_associated_token_address_with_program_id(
+            &ask.owner,
+            &stock_mint.key(),
+            &token_program.key(),
+        ),
+        StockTradingError::InvalidRefundAccount
+    );
+
+    let seeds = &[
+        b"trading_pool".as_ref(),
+        &[trading_pool.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_accounts = TransferChecked {
+        from: book_escrow.to_account_info(),
+        mint: stock_mint.to_account_info(),
+        to: owner_stock_token_account.clone(),
+        authority: trading_pool.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer)
+        .with_remaining_accounts(hook_accounts.to_vec());
+
+    transfer_stock(cpi_ctx, ask.shares_remaining, stock_mint.decimals)
+}
+
+/// Transfers quote tokens out of a token vault owned by the trading pool.
+fn transfer_from_quote_vault<'info>(
+    quote_vault: &Account<'info, TokenAccount>,
+    to: &AccountInfo<'info>,
+    trading_pool: &Account<'info, TradingPool>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let seeds = &[
+        b"trading_pool".as_ref(),
+        &[trading_pool.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_accounts = Transfer {
+        from: quote_vault.to_account_info(),
+        to: to.clone(),
+        authority: trading_pool.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    token::transfer(cpi_ctx, amount)
+}
+
+/// Closes an emptied per-order escrow, returning its rent to `destination`.
+fn close_escrow<'info>(
+    escrow_token_account: &InterfaceAccount<'info, token_interface::TokenAccount>,
+    destination: &AccountInfo<'info>,
+    trading_pool: &Account<'info, TradingPool>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    let seeds = &[
+        b"trading_pool".as_ref(),
+        &[trading_pool.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_accounts = token_interface::CloseAccount {
+        account: escrow_token_account.to_account_info(),
+        destination: destination.clone(),
+        authority: trading_pool.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    token_interface::close_account(cpi_ctx)
+}
+
+/// Unwraps one of the optional token accounts passed for token-quoted
+/// orders and checks that it holds the order's quote mint.
+fn quote_token_account<'a, 'info>(
+    account: &'a Option<Account<'info, TokenAccount>>,
+    quote_mint: Pubkey,
+) -> Result<&'a Account<'info, TokenAccount>> {
+    let account = account.as_ref().ok_or(StockTradingError::MissingQuoteAccounts)?;
+    require_keys_eq!(account.mint, quote_mint, StockTradingError::InvalidQuoteMint);
+    Ok(account)
+}
+
+/// Unwraps the optional allowlist entry passed for token-quoted orders.
+fn quote_mint_config<'a, 'info>(
+    config: &'a mut Option<Account<'info, QuoteMintConfig>>,
+    quote_mint: Pubkey,
+) -> Result<&'a mut Account<'info, QuoteMintConfig>> {
+    let config = config.as_mut().ok_or(StockTradingError::MissingQuoteAccounts)?;
+    require_keys_eq!(config.mint, quote_mint, StockTradingError::InvalidQuoteMint);
+    Ok(config)
+}
+
+/// PDA seed for a quote asset. SOL contributes an empty seed, so SOL price
+/// feeds keep the address they had before token quotes existed.
+pub fn quote_seed(quote_mint: &Option<Pubkey>) -> &[u8] {
+    quote_mint.as_ref().map_or(&[], |mint| mint.as_ref())
+}
+
+/// Leaf of a dividend Merkle tree. Leaves and inner nodes carry distinct
+/// prefixes so an inner node can never be passed off as a leaf.
+pub fn dividend_leaf(index: u32, holder: &Pubkey, amount: u64) -> [u8; 32] {
+    hashv(&[&[0], &index.to_le_bytes(), holder.as_ref(), &amount.to_le_bytes()]).to_bytes()
+}
+
+/// Walks `proof` from `leaf` up to the root. Each pair is hashed in sorted
+/// order, so proofs carry no left/right flags.
+fn verify_merkle_proof(proof: &[[u8; 32]], root: [u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        let (left, right) = if node <= *sibling { (&node, sibling) } else { (sibling, &node) };
+        hashv(&[&[1], left, right]).to_bytes()
+    });
+    computed == root
+}
+
+/// Checks the arguments shared by the stock mint constructors.
+fn validate_stock_listing(stock_symbol: &str, decimals: u8, name: &str, uri: &str) -> Result<()> {
+    require!(stock_symbol.len() <= 10, StockTradingError::StockSymbolTooLong);
+    require!(decimals <= MAX_STOCK_DECIMALS, StockTradingError::InvalidDecimals);
+    require!(name.len() <= MAX_STOCK_NAME_LEN, StockTradingError::StockNameTooLong);
+    require!(uri.len() <= MAX_METADATA_URI_LEN, StockTradingError::MetadataUriTooLong);
+    Ok(())
+}
+
+/// Lists a new stock mint under either token program: records its info,
+/// creates its token metadata and announces it.
+fn list_stock_mint<'info>(
+    stock_mint_info: &mut StockMintInfo,
+    stock_mint_info_bump: u8,
+    metadata_accounts: CreateMetadataAccountsV3<'info>,
+    token_metadata_program: AccountInfo<'info>,
+    trading_pool_bump: u8,
+    token_program: Pubkey,
+    transfer_hook_program: Option<Pubkey>,
+    stock_symbol: String,
+    decimals: u8,
+    name: String,
+    uri: String,
+) -> Result<()> {
+    validate_stock_listing(&stock_symbol, decimals, &name, &uri)?;
+
+    let mint = metadata_accounts.mint.key();
+    stock_mint_info.initialize(stock_symbol.clone(), mint, decimals, stock_mint_info_bump);
+
+    create_stock_metadata(
+        metadata_accounts,
+        token_metadata_program,
+        trading_pool_bump,
+        stock_metadata(name.clone(), stock_symbol.clone(), uri.clone()),
+    )?;
+
+    emit!(StockMintCreated {
+        stock_symbol,
+        mint,
+        decimals,
+        name,
+        uri,
+        token_program,
+        transfer_hook_program,
+    });
+
+    Ok(())
+}
+
+/// Creates the token metadata account of a new stock mint. Wallets and
+/// explorers read the name, ticker and URI from it; the trading pool signs as
+/// mint authority and stays its update authority.
+fn create_stock_metadata<'info>(
+    cpi_accounts: CreateMetadataAccountsV3<'info>,
+    token_metadata_program: AccountInfo<'info>,
+    trading_pool_bump: u8,
+    data: DataV2,
+) -> Result<()> {
+    let seeds = &[
+        b"trading_pool".as_ref(),
+        &[trading_pool_bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_ctx = CpiContext::new_with_signer(token_metadata_program, cpi_accounts, signer);
+    metadata::create_metadata_accounts_v3(cpi_ctx, data, true, true, None)
+}
+
+/// Moves stock tokens under either token program. Anchor's `transfer_checked`
+/// does not forward a Token-2022 transfer hook's accounts, so they are
+/// resolved from the context's remaining accounts: the hook program, its
+/// extra-account-metas account and every account listed there.
+fn transfer_stock<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, TransferChecked<'info>>,
+    amount: u64,
+    decimals: u8,
+) -> Result<()> {
+    onchain::invoke_transfer_checked(
+        ctx.program.key,
+        ctx.accounts.from,
+        ctx.accounts.mint,
+        ctx.accounts.to,
+        ctx.accounts.authority,
+        &ctx.remaining_accounts,
+        amount,
+        decimals,
+        ctx.signer_seeds,
+    )
+    .map_err(Into::into)
+}
+
+/// Token metadata for a stock mint. The ticker doubles as the token symbol;
+/// stock tokens carry no royalties, creators or collection.
+fn stock_metadata(name: String, symbol: String, uri: String) -> DataV2 {
+    DataV2 {
+        name,
+        symbol,
+        uri,
+        seller_fee_basis_points: 0,
+        creators: None,
+        collection: None,
+        uses: None,
+    }
+}
+
+/// Direction in which a notional that is not a whole quote unit is rounded.
+#[derive(Clone, Copy)]
+enum Rounding {
+    Up,
+    Down,
+}
+
+/// Value of `amount` base units of a stock mint at `price_per_share` in the
+/// order's quote asset. Prices are quote base units (lamports for SOL) per
+/// whole share, so the mint's decimals and split multiplier both apply. Fills
+/// must quote this value to the unit: buys round up and sells round down, so
+/// a fractional remainder always stays with the pool.
+fn calculate_notional(
+    amount: u64,
+    price_per_share: u64,
+    stock_mint_info: &StockMintInfo,
+    rounding: Rounding,
+) -> Result<u64> {
+    let value = (amount as u128)
+        .checked_mul(price_per_share as u128)
+        .and_then(|value| value.checked_mul(stock_mint_info.split_numerator as u128))
+        .ok_or(StockTradingError::Overflow)?;
+    let scale = 10u128
+        .checked_pow(stock_mint_info.decimals as u32)
+        .and_then(|scale| scale.checked_mul(stock_mint_info.split_denominator as u128))
+        .ok_or(StockTradingError::Overflow)?;
+    let notional = match rounding {
+        Rounding::Up => value.div_ceil(scale),
+        Rounding::Down => value / scale,
+    };
+    u64::try_from(notional).map_err(|_| error!(StockTradingError::Overflow))
+}
+
+/// Whether `price_per_share` at the mint's current split ratio is on the
+/// `upper` or lower side of `limit_price`, which was quoted per share at the
+/// order's ratio. Both sides are compared per token unit, so a limit set
+/// before a split keeps its meaning afterwards.
+fn within_price_limit(
+    price_per_share: u64,
+    stock_mint_info: &StockMintInfo,
+    limit_price: u64,
+    order_ratio: (u64, u64),
+    upper: bool,
+) -> Result<bool> {
+    let (order_numerator, order_denominator) = order_ratio;
+    let price = (price_per_share as u128)
+        .checked_mul(stock_mint_info.split_numerator as u128)
+        .and_then(|price| price.checked_mul(order_denominator as u128))
+        .ok_or(StockTradingError::Overflow)?;
+    let limit = (limit_price as u128)
+        .checked_mul(order_numerator as u128)
+        .and_then(|limit| limit.checked_mul(stock_mint_info.split_denominator as u128))
+        .ok_or(StockTradingError::Overflow)?;
+    Ok(if upper { price <= limit } else { price >= limit })
+}
+
+/// `a * b / c` in 128-bit arithmetic.
+fn mul_div(a: u64, b: u64, c: u64, rounding: Rounding) -> Result<u64> {
+    require!(c > 0, StockTradingError::InvalidCalculation);
+    let product = (a as u128)
+        .checked_mul(b as u128)
+        .ok_or(StockTradingError::Overflow)?;
+    let quotient = match rounding {
+        Rounding::Up => product.div_ceil(c as u128),
+        Rounding::Down => product / c as u128,
+    };
+    u64::try_from(quotient).map_err(|_| error!(StockTradingError::Overflow))
+}
+
+/// Integer square root, rounded down.
+fn isqrt(value: u128) -> Result<u64> {
+    if value < 2 {
+        return Ok(value as u64);
+    }
+    let mut x = value;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    u64::try_from(x).map_err(|_| error!(StockTradingError::Overflow))
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Protocol fee on `amount`, rounded down in the user's favour.
+fn calculate_fee(amount: u64, fee_bps: u16) -> Result<u64> {
+    let fee = (amount as u128)
+        .checked_mul(fee_bps as u128)
+        .ok_or(StockTradingError::Overflow)?
+        / BPS_DENOMINATOR as u128;
+    u64::try_from(fee).map_err(|_| error!(StockTradingError::Overflow))
+}
+
+/// Volume-weighted average of an existing fill and a new execution, rounded down.
+fn weighted_average_price(
+    average_price: u64,
+    filled_quantity: u64,
+    price: u64,
+    quantity: u64,
+) -> Result<u64> {
+    let total_quantity = (filled_quantity as u128)
+        .checked_add(quantity as u128)
+        .ok_or(StockTradingError::Overflow)?;
+    if total_quantity == 0 {
+        return Ok(average_price);
+    }
+
+    let notional = (average_price as u128)
+        .checked_mul(filled_quantity as u128)
+        .and_then(|filled| {
+            (price as u128)
+                .checked_mul(quantity as u128)
+                .and_then(|new| filled.checked_add(new))
+        })
+        .ok_or(StockTradingError::Overflow)?;
+
+    u64::try_from(notional / total_quantity).map_err(|_| error!(StockTradingError::Overflow))
+}
 
 // Context structs
 #[derive(Accounts)]
-pub struct InitializeTradingPool<'info> {
+pub struct InitializeTradingPool<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + TradingPool::LEN,
+        seeds = [b"trading_pool"],
+        bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault that holds SOL
+    #[account(
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    /// CHECK: This is the fee vault that collects protocol fees
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(stock_symbol: String, decimals: u8)]
+pub struct CreateStockMint<'info> {
+    #[account(
+        init,
+        payer = vault_authority,
+        mint::decimals = decimals,
+        mint::authority = trading_pool,
+        seeds = [b"stock_mint", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = vault_authority,
+        space = 8 + StockMintInfo::LEN,
+        seeds = [b"stock_mint_info", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: Metadata PDA of the stock mint, created by the token metadata program
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), stock_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    pub metadata: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub vault_authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(stock_symbol: String, decimals: u8)]
+pub struct CreateToken2022StockMint<'info> {
+    #[account(
+        init,
+        payer = vault_authority,
+        mint::decimals = decimals,
+        mint::authority = trading_pool,
+        mint::token_program = token_program,
+        extensions::transfer_hook::authority = trading_pool,
+        extensions::transfer_hook::program_id = transfer_hook_program,
+        seeds = [b"stock_mint", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        init,
+        payer = vault_authority,
+        space = 8 + StockMintInfo::LEN,
+        seeds = [b"stock_mint_info", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: Metadata PDA of the stock mint, created by the token metadata program
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), stock_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    pub metadata: UncheckedAccount<'info>,
+    
+    /// CHECK: Program Token-2022 invokes on every transfer of the mint
+    #[account(executable)]
+    pub transfer_hook_program: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub vault_authority: Signer<'info>,
+    pub token_program: Program<'info, Token2022>,
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateStockMetadata<'info> {
+    #[account(
+        seeds = [b"stock_mint_info", stock_mint_info.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    /// CHECK: Metadata PDA of the stock mint, owned by the token metadata program
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), stock_mint_info.mint.as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    pub metadata: UncheckedAccount<'info>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub vault_authority: Signer<'info>,
+    pub token_metadata_program: Program<'info, Metadata>,
+}
+
+#[derive(Accounts)]
+#[instruction(stock_symbol: String, quote_mint: Option<Pubkey>)]
+pub struct InitializePriceFeed<'info> {
+    #[account(
+        init,
+        payer = vault_authority,
+        space = 8 + PriceFeed::LEN,
+        seeds = [b"price_feed", stock_symbol.as_bytes(), quote_seed(&quote_mint)],
+        bump
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub vault_authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePriceFeed<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"price_feed",
+            price_feed.stock_symbol.as_bytes(),
+            quote_seed(&price_feed.quote_mint)
+        ],
+        bump = price_feed.bump
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = oracle_authority @ StockTradingError::UnauthorizedOracle
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub oracle_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(stock_symbol: String)]
+pub struct AttestReserves<'info> {
+    #[account(
+        init_if_needed,
+        payer = backend_authority,
+        space = 8 + ReserveAttestation::LEN,
+        seeds = [b"reserve_attestation", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub reserve_attestation: Account<'info, ReserveAttestation>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = backend_authority @ StockTradingError::UnauthorizedBackend
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub backend_authority: Signer<'info>,
+    
+    /// CHECK: Instructions sysvar, read to find the reserve attestor's ed25519 signature
+    #[account(address = solana_instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetOracleConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub vault_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPause<'info> {
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(
+        mut,
+        seeds = [b"stock_mint_info", stock_mint_info.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Option<Account<'info, StockMintInfo>>,
+    
+    pub vault_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetTradingSchedule<'info> {
+    #[account(
+        init_if_needed,
+        payer = vault_authority,
+        space = 8 + TradingSchedule::LEN,
+        seeds = [b"trading_schedule"],
+        bump
+    )]
+    pub trading_schedule: Account<'info, TradingSchedule>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub vault_authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetMarketStatus<'info> {
+    #[account(
+        seeds = [b"trading_schedule"],
+        bump = trading_schedule.bump
+    )]
+    pub trading_schedule: Account<'info, TradingSchedule>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyStockSplit<'info> {
+    #[account(
+        mut,
+        seeds = [b"stock_mint_info", stock_mint_info.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    /// CHECK: Order book of the symbol, which may not exist. Book orders are
+    /// priced per pre-split share, so an existing book must be empty; see
+    /// `flush_order_book`.
+    #[account(
+        seeds = [b"order_book", stock_mint_info.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub order_book: UncheckedAccount<'info>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub vault_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetFeeConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub vault_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetStockFeeOverride<'info> {
+    #[account(
+        mut,
+        seeds = [b"stock_mint_info", stock_mint_info.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub vault_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetComplianceConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub vault_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(user: Pubkey)]
+pub struct GrantEligibility<'info> {
+    #[account(
+        init_if_needed,
+        payer = compliance_authority,
+        space = 8 + UserEligibility::LEN,
+        seeds = [b"user_eligibility", user.as_ref()],
+        bump
+    )]
+    pub user_eligibility: Account<'info, UserEligibility>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = compliance_authority @ StockTradingError::UnauthorizedCompliance
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub compliance_authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeEligibility<'info> {
+    #[account(
+        mut,
+        seeds = [b"user_eligibility", user_eligibility.user.as_ref()],
+        bump = user_eligibility.bump
+    )]
+    pub user_eligibility: Account<'info, UserEligibility>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = compliance_authority @ StockTradingError::UnauthorizedCompliance
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub compliance_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = fee_authority @ StockTradingError::UnauthorizedFeeAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the fee vault
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub fee_authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AddQuoteMint<'info> {
+    #[account(
+        init,
+        payer = vault_authority,
+        space = 8 + QuoteMintConfig::LEN,
+        seeds = [b"quote_mint", quote_mint.key().as_ref()],
+        bump
+    )]
+    pub quote_mint_config: Account<'info, QuoteMintConfig>,
+    
+    pub quote_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = vault_authority,
+        token::mint = quote_mint,
+        token::authority = trading_pool,
+        seeds = [b"quote_vault", quote_mint.key().as_ref()],
+        bump
+    )]
+    pub quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        init,
+        payer = vault_authority,
+        token::mint = quote_mint,
+        token::authority = trading_pool,
+        seeds = [b"quote_fee_vault", quote_mint.key().as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub vault_authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetQuoteMintEnabled<'info> {
+    #[account(
+        mut,
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Account<'info, QuoteMintConfig>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub vault_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawQuoteFees<'info> {
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = fee_authority @ StockTradingError::UnauthorizedFeeAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_fee_vault", quote_fee_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = quote_fee_vault.mint
+    )]
+    pub destination: Account<'info, TokenAccount>,
+    
+    pub fee_authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(stock_symbol: String)]
+pub struct PlaceBuyOrder<'info> {
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserAccount::LEN,
+        seeds = [b"user_account", user.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = 8 + BuyOrder::LEN,
+        seeds = [
+            b"buy_order",
+            user.key().as_ref(),
+            user_account.order_nonce.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub buy_order: Account<'info, BuyOrder>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    /// CHECK: Trading schedule, which may not exist. Order placement is only
+    /// restricted to market hours once one is set.
+    #[account(
+        seeds = [b"trading_schedule"],
+        bump
+    )]
+    pub trading_schedule: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault that receives SOL
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    // Quote token accounts, omitted for SOL orders
+    #[account(
+        mut,
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Option<Account<'info, QuoteMintConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_vault", quote_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        token::authority = user
+    )]
+    pub user_quote_token_account: Option<Account<'info, TokenAccount>>,
+    
+    /// KYC grant of the user, required while eligibility is enforced
+    #[account(
+        seeds = [b"user_eligibility", user.key().as_ref()],
+        bump = user_eligibility.bump
+    )]
+    pub user_eligibility: Option<Account<'info, UserEligibility>>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FulfillBuyOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"buy_order",
+            buy_order.user.as_ref(),
+            buy_order.order_id.to_le_bytes().as_ref()
+        ],
+        bump = buy_order.bump
+    )]
+    pub buy_order: Account<'info, BuyOrder>,
+    
+    #[account(
+        mut,
+        seeds = [b"stock_mint", buy_order.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"stock_mint_info", buy_order.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        seeds = [
+            b"price_feed",
+            buy_order.stock_symbol.as_bytes(),
+            quote_seed(&buy_order.quote_mint)
+        ],
+        bump = price_feed.bump
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+    
+    #[account(
+        seeds = [b"reserve_attestation", buy_order.stock_symbol.as_bytes()],
+        bump = reserve_attestation.bump
+    )]
+    pub reserve_attestation: Account<'info, ReserveAttestation>,
+    
+    #[account(
+        init_if_needed,
+        payer = backend_authority,
+        associated_token::mint = stock_mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_stock_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    /// CHECK: This is the fee vault
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+    
+    // Quote token accounts, omitted for SOL orders
+    #[account(
+        mut,
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Option<Account<'info, QuoteMintConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_vault", quote_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_fee_vault", quote_fee_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        token::authority = buy_order.user
+    )]
+    pub user_quote_token_account: Option<Account<'info, TokenAccount>>,
+    
+    pub quote_token_program: Option<Program<'info, Token>>,
+    
+    /// KYC grant of the user, required while eligibility is enforced
+    #[account(
+        seeds = [b"user_eligibility", buy_order.user.as_ref()],
+        bump = user_eligibility.bump
+    )]
+    pub user_eligibility: Option<Account<'info, UserEligibility>>,
+    
+    /// CHECK: User account to receive refund, checked against the order
+    #[account(
+        mut,
+        address = buy_order.user @ StockTradingError::UnauthorizedUser
+    )]
+    pub user: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub backend_authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> FulfillBuyOrder<'info> {
+    /// Validates a broker execution against the order, mints the purchased
+    /// shares and folds the execution into the order's running totals.
+    /// Returns the protocol fee charged on the execution.
+    fn apply_fill(
+        &mut self,
+        shares_purchased: u64,
+        price_per_share: u64,
+        total_cost: u64,
+        vault_bump: u8,
+    ) -> Result<u64> {
+        let trading_pool = &self.trading_pool;
+        let buy_order = &mut self.buy_order;
+
+        // Only backend authority can fulfill orders
+        require!(
+            self.backend_authority.key() == trading_pool.backend_authority,
+            StockTradingError::UnauthorizedBackend
+        );
+        trading_pool.check_eligibility(self.user_eligibility.as_deref(), buy_order.user)?;
+        require!(!trading_pool.paused, StockTradingError::TradingPaused);
+
+        require!(buy_order.is_open(), StockTradingError::InvalidOrderStatus);
+        require!(
+            !buy_order.is_expired(Clock::get()?.unix_timestamp),
+            StockTradingError::OrderExpired
+        );
+        require!(
+            !buy_order.is_queued(Clock::get()?.unix_timestamp),
+            StockTradingError::OrderQueued
+        );
+        require!(
+            within_price_limit(
+                price_per_share,
+                &self.stock_mint_info,
+                buy_order.max_price_per_share,
+                (buy_order.split_numerator, buy_order.split_denominator),
+                true,
+            )?,
+            StockTradingError::PriceExceedsLimit
+        );
+        self.price_feed.check_price(price_per_share, trading_pool)?;
+        require!(
+            total_cost == calculate_notional(
+                shares_purchased,
+                price_per_share,
+                &self.stock_mint_info,
+                Rounding::Up,
+            )?,
+            StockTradingError::NotionalMismatch
+        );
+
+        // The fee is charged on top of the cost, out of the order's escrowed SOL
+        let fee_amount = calculate_fee(
+            total_cost,
+            self.stock_mint_info.effective_fee_bps(trading_pool),
+        )?;
+        let sol_spent = buy_order.sol_spent
+            .checked_add(total_cost)
+            .ok_or(StockTradingError::Overflow)?;
+        let fees_paid = buy_order.fees_paid
+            .checked_add(fee_amount)
+            .ok_or(StockTradingError::Overflow)?;
+        require!(
+            sol_spent
+                .checked_add(fees_paid)
+                .ok_or(StockTradingError::Overflow)?
+                <= buy_order.sol_amount,
+            StockTradingError::InvalidCalculation
+        );
+
+        // Mint stock tokens to user
+        if shares_purchased > 0 {
+            let seeds = &[
+                b"trading_pool".as_ref(),
+                &[trading_pool.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = token_interface::MintTo {
+                mint: self.stock_mint.to_account_info(),
+                to: self.user_stock_token_account.to_account_info(),
+                authority: trading_pool.to_account_info(),
+            };
+            let cpi_program = self.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            
+            token_interface::mint_to(cpi_ctx, shares_purchased)?;
+
+            // Update stock mint info
+            let stock_mint_info = &mut self.stock_mint_info;
+            stock_mint_info.total_supply = stock_mint_info.total_supply
+                .checked_add(shares_purchased)
+                .ok_or(StockTradingError::Overflow)?;
+            self.reserve_attestation.check_backing(stock_mint_info, trading_pool)?;
+
+            buy_order.actual_price_per_share = weighted_average_price(
+                buy_order.actual_price_per_share,
+                buy_order.shares_received,
+                price_per_share,
+                shares_purchased,
+            )?;
+            buy_order.shares_received = buy_order.shares_received
+                .checked_add(shares_purchased)
+                .ok_or(StockTradingError::Overflow)?;
+        }
+
+        // Spent funds become free balance and the fee leaves the vault
+        let released = total_cost
+            .checked_add(fee_amount)
+            .ok_or(StockTradingError::Overflow)?;
+        match buy_order.quote_mint {
+            None => {
+                if fee_amount > 0 {
+                    transfer_from_vault(
+                        &self.trading_pool_vault,
+                        &self.fee_vault,
+                        &self.system_program,
+                        vault_bump,
+                        fee_amount,
+                    )?;
+                }
+                self.trading_pool.release(released)?;
+            }
+            Some(quote_mint) => {
+                if fee_amount > 0 {
+                    let token_program = self.quote_token_program
+                        .as_ref()
+                        .ok_or(StockTradingError::MissingQuoteAccounts)?;
+                    transfer_from_quote_vault(
+                        quote_token_account(&self.quote_vault, quote_mint)?,
+                        &quote_token_account(&self.quote_fee_vault, quote_mint)?.to_account_info(),
+                        trading_pool,
+                        token_program,
+                        fee_amount,
+                    )?;
+                }
+                quote_mint_config(&mut self.quote_mint_config, quote_mint)?.release(released)?;
+            }
+        }
+
+        buy_order.sol_spent = sol_spent;
+        buy_order.fees_paid = fees_paid;
+
+        Ok(fee_amount)
+    }
+
+    /// Applies the final execution, refunds the rest of the escrowed funds
+    /// and closes the order.
+    fn fulfill(
+        &mut self,
+        shares_purchased: u64,
+        price_per_share: u64,
+        total_cost: u64,
+        refund_amount: u64,
+        vault_bump: u8,
+    ) -> Result<()> {
+        let fee_amount = self.apply_fill(
+            shares_purchased,
+            price_per_share,
+            total_cost,
+            vault_bump,
+        )?;
+
+        let buy_order = &mut self.buy_order;
+        let remaining_sol = buy_order.remaining_sol()?;
+        require!(
+            refund_amount <= remaining_sol,
+            StockTradingError::InvalidCalculation
+        );
+
+        // Update order status
+        buy_order.status = OrderStatus::Fulfilled;
+
+        // Refund excess funds if any. Anything else left on a completed order
+        // is kept as free balance.
+        match buy_order.quote_mint {
+            None => {
+                if refund_amount > 0 {
+                    transfer_from_vault(
+                        &self.trading_pool_vault,
+                        &self.user,
+                        &self.system_program,
+                        vault_bump,
+                        refund_amount,
+                    )?;
+                }
+                self.trading_pool.release(remaining_sol)?;
+            }
+            Some(quote_mint) => {
+                if refund_amount > 0 {
+                    let token_program = self.quote_token_program
+                        .as_ref()
+                        .ok_or(StockTradingError::MissingQuoteAccounts)?;
+                    transfer_from_quote_vault(
+                        quote_token_account(&self.quote_vault, quote_mint)?,
+                        &quote_token_account(&self.user_quote_token_account, quote_mint)?
+                            .to_account_info(),
+                        &self.trading_pool,
+                        token_program,
+                        refund_amount,
+                    )?;
+                }
+                quote_mint_config(&mut self.quote_mint_config, quote_mint)?
+                    .release(remaining_sol)?;
+            }
+        }
+
+        emit!(BuyOrderFulfilled {
+            order_id: buy_order.order_id,
+            user: buy_order.user,
+            stock_symbol: buy_order.stock_symbol.clone(),
+            shares_purchased,
+            price_per_share,
+            total_cost,
+            refund_amount,
+            fee_amount,
+            total_shares_received: buy_order.shares_received,
+            average_price_per_share: buy_order.actual_price_per_share,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        emit!(buy_order.receipt(Clock::get()?.unix_timestamp));
+
+        // The order is terminal, so its rent goes back to the user
+        self.buy_order.close(self.user.to_account_info())?;
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(stock_symbol: String)]
+pub struct PlaceSellOrder<'info> {
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserAccount::LEN,
+        seeds = [b"user_account", user.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = 8 + SellOrder::LEN,
+        seeds = [
+            b"sell_order",
+            user.key().as_ref(),
+            user_account.order_nonce.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+    
+    #[account(
+        seeds = [b"stock_mint", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    /// CHECK: Trading schedule, which may not exist. Order placement is only
+    /// restricted to market hours once one is set.
+    #[account(
+        seeds = [b"trading_schedule"],
+        bump
+    )]
+    pub trading_schedule: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        associated_token::mint = stock_mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_stock_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        init,
+        payer = user,
+        token::mint = stock_mint,
+        token::authority = trading_pool,
+        token::token_program = token_program,
+        seeds = [b"escrow", sell_order.key().as_ref()],
+        bump
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// Allowlisted quote token to receive proceeds in, omitted for SOL
+    #[account(
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Option<Account<'info, QuoteMintConfig>>,
+    
+    /// KYC grant of the user, required while eligibility is enforced
+    #[account(
+        seeds = [b"user_eligibility", user.key().as_ref()],
+        bump = user_eligibility.bump
+    )]
+    pub user_eligibility: Option<Account<'info, UserEligibility>>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FulfillSellOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sell_order",
+            sell_order.user.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref()
+        ],
+        bump = sell_order.bump
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+    
+    #[account(
+        mut,
+        seeds = [b"stock_mint", sell_order.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"stock_mint_info", sell_order.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        seeds = [
+            b"price_feed",
+            sell_order.stock_symbol.as_bytes(),
+            quote_seed(&sell_order.quote_mint)
+        ],
+        bump = price_feed.bump
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+    
+    #[account(
+        mut,
+        associated_token::mint = stock_mint,
+        associated_token::authority = sell_order.user,
+        associated_token::token_program = token_program
+    )]
+    pub user_stock_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = stock_mint,
+        token::authority = trading_pool,
+        token::token_program = token_program,
+        seeds = [b"escrow", sell_order.key().as_ref()],
+        bump
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    /// CHECK: This is the fee vault
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+    
+    // Quote token accounts, omitted for SOL orders
+    #[account(
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Option<Account<'info, QuoteMintConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_vault", quote_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_fee_vault", quote_fee_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        token::authority = sell_order.user
+    )]
+    pub user_quote_token_account: Option<Account<'info, TokenAccount>>,
+    
+    pub quote_token_program: Option<Program<'info, Token>>,
+    
+    /// KYC grant of the user, required while eligibility is enforced
+    #[account(
+        seeds = [b"user_eligibility", sell_order.user.as_ref()],
+        bump = user_eligibility.bump
+    )]
+    pub user_eligibility: Option<Account<'info, UserEligibility>>,
+    
+    /// CHECK: User account to receive SOL, checked against the order
+    #[account(
+        mut,
+        address = sell_order.user @ StockTradingError::UnauthorizedUser
+    )]
+    pub user: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub backend_authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> FulfillSellOrder<'info> {
+    /// Validates a broker execution against the order, burns the sold shares
+    /// from escrow and pays the proceeds out of the vault. Returns the
+    /// protocol fee withheld from the proceeds.
+    fn apply_fill(
+        &mut self,
+        shares_sold: u64,
+        price_per_share: u64,
+        total_proceeds: u64,
+        vault_bump: u8,
+    ) -> Result<u64> {
+        let trading_pool = &self.trading_pool;
+        let sell_order = &mut self.sell_order;
+
+        // Only backend authority can fulfill orders
+        require!(
+            self.backend_authority.key() == trading_pool.backend_authority,
+            StockTradingError::UnauthorizedBackend
+        );
+        trading_pool.check_eligibility(self.user_eligibility.as_deref(), sell_order.user)?;
+        require!(!trading_pool.paused, StockTradingError::TradingPaused);
+
+        require!(sell_order.is_open(), StockTradingError::InvalidOrderStatus);
+        require!(
+            !sell_order.is_expired(Clock::get()?.unix_timestamp),
+            StockTradingError::OrderExpired
+        );
+        require!(
+            !sell_order.is_queued(Clock::get()?.unix_timestamp),
+            StockTradingError::OrderQueued
+        );
+        require!(
+            within_price_limit(
+                price_per_share,
+                &self.stock_mint_info,
+                sell_order.min_price_per_share,
+                (sell_order.split_numerator, sell_order.split_denominator),
+                false,
+            )?,
+            StockTradingError::PriceBelowMinimum
+        );
+        self.price_feed.check_price(price_per_share, trading_pool)?;
+        require!(
+            total_proceeds == calculate_notional(
+                shares_sold,
+                price_per_share,
+                &self.stock_mint_info,
+                Rounding::Down,
+            )?,
+            StockTradingError::NotionalMismatch
+        );
+
+        let total_shares_sold = sell_order.shares_sold
+            .checked_add(shares_sold)
+            .ok_or(StockTradingError::Overflow)?;
+        require!(
+            total_shares_sold <= sell_order.shares_to_sell,
+            StockTradingError::InvalidCalculation
+        );
+
+        // The fee is withheld from the proceeds
+        let fee_amount = calculate_fee(
+            total_proceeds,
+            self.stock_mint_info.effective_fee_bps(trading_pool),
+        )?;
+
+        // Burn sold tokens from escrow
+        if shares_sold > 0 {
+            let seeds = &[
+                b"trading_pool".as_ref(),
+                &[trading_pool.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = token_interface::Burn {
+                mint: self.stock_mint.to_account_info(),
+                from: self.escrow_token_account.to_account_info(),
+                authority: trading_pool.to_account_info(),
+            };
+            let cpi_program = self.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            
+            token_interface::burn(cpi_ctx, shares_sold)?;
+
+            // Update stock mint info
+            let stock_mint_info = &mut self.stock_mint_info;
+            stock_mint_info.total_supply = stock_mint_info.total_supply
+                .checked_sub(shares_sold)
+                .ok_or(StockTradingError::Underflow)?;
+
+            // Proceeds can only come out of the free balance, never out of
+            // funds reserved for open buy orders. They are paid net of fees.
+            let net_proceeds = total_proceeds
+                .checked_sub(fee_amount)
+                .ok_or(StockTradingError::Underflow)?;
+            match sell_order.quote_mint {
+                None => {
+                    require!(
+                        total_proceeds <= trading_pool.free_lamports(&self.trading_pool_vault)?,
+                        StockTradingError::InsufficientVaultFunds
+                    );
+
+                    transfer_from_vault(
+                        &self.trading_pool_vault,
+                        &self.user,
+                        &self.system_program,
+                        vault_bump,
+                        net_proceeds,
+                    )?;
+
+                    if fee_amount > 0 {
+                        transfer_from_vault(
+                            &self.trading_pool_vault,
+                            &self.fee_vault,
+                            &self.system_program,
+                            vault_bump,
+                            fee_amount,
+                        )?;
+                    }
+                }
+                Some(quote_mint) => {
+                    let quote_vault = quote_token_account(&self.quote_vault, quote_mint)?;
+                    let token_program = self.quote_token_program
+                        .as_ref()
+                        .ok_or(StockTradingError::MissingQuoteAccounts)?;
+                    let quote_mint_config = self.quote_mint_config
+                        .as_ref()
+                        .ok_or(StockTradingError::MissingQuoteAccounts)?;
+                    require_keys_eq!(
+                        quote_mint_config.mint,
+                        quote_mint,
+                        StockTradingError::InvalidQuoteMint
+                    );
+                    require!(
+                        total_proceeds <= quote_mint_config.free_amount(quote_vault),
+                        StockTradingError::InsufficientVaultFunds
+                    );
+
+                    transfer_from_quote_vault(
+                        quote_vault,
+                        &quote_token_account(&self.user_quote_token_account, quote_mint)?
+                            .to_account_info(),
+                        trading_pool,
+                        token_program,
+                        net_proceeds,
+                    )?;
+
+                    if fee_amount > 0 {
+                        transfer_from_quote_vault(
+                            quote_vault,
+                            &quote_token_account(&self.quote_fee_vault, quote_mint)?
+                                .to_account_info(),
+                            trading_pool,
+                            token_program,
+                            fee_amount,
+                        )?;
+                    }
+                }
+            }
+
+            sell_order.actual_price_per_share = weighted_average_price(
+                sell_order.actual_price_per_share,
+                sell_order.shares_sold,
+                price_per_share,
+                shares_sold,
+            )?;
+            sell_order.sol_received = sell_order.sol_received
+                .checked_add(net_proceeds)
+                .ok_or(StockTradingError::Overflow)?;
+            sell_order.fees_paid = sell_order.fees_paid
+                .checked_add(fee_amount)
+                .ok_or(StockTradingError::Overflow)?;
+        }
+
+        sell_order.shares_sold = total_shares_sold;
+
+        Ok(fee_amount)
+    }
+
+    /// Applies the final execution, returns the unsold shares and closes the
+    /// order and its escrow. `hook_accounts` are passed to the transfer hook
+    /// of the stock mint, if any.
+    fn fulfill(
+        &mut self,
+        shares_sold: u64,
+        price_per_share: u64,
+        total_proceeds: u64,
+        shares_returned: u64,
+        vault_bump: u8,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let fee_amount = self.apply_fill(
+            shares_sold,
+            price_per_share,
+            total_proceeds,
+            vault_bump,
+        )?;
+
+        let sell_order = &self.sell_order;
+        require!(
+            sell_order.shares_sold
+                .checked_add(shares_returned)
+                .ok_or(StockTradingError::Overflow)?
+                == sell_order.shares_to_sell,
+            StockTradingError::InvalidCalculation
+        );
+
+        // Return unsold tokens to user
+        if shares_returned > 0 {
+            let seeds = &[
+                b"trading_pool".as_ref(),
+                &[self.trading_pool.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = TransferChecked {
+                from: self.escrow_token_account.to_account_info(),
+                mint: self.stock_mint.to_account_info(),
+                to: self.user_stock_token_account.to_account_info(),
+                authority: self.trading_pool.to_account_info(),
+            };
+            let cpi_program = self.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer)
+                .with_remaining_accounts(hook_accounts.to_vec());
+            
+            transfer_stock(cpi_ctx, shares_returned, self.stock_mint.decimals)?;
+        }
+
+        // Update order status
+        let sell_order = &mut self.sell_order;
+        sell_order.status = OrderStatus::Fulfilled;
+
+        emit!(SellOrderFulfilled {
+            order_id: sell_order.order_id,
+            user: sell_order.user,
+            stock_symbol: sell_order.stock_symbol.clone(),
+            shares_sold,
+            price_per_share,
+            total_proceeds,
+            shares_returned,
+            fee_amount,
+            total_shares_sold: sell_order.shares_sold,
+            total_sol_received: sell_order.sol_received,
+            average_price_per_share: sell_order.actual_price_per_share,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        emit!(sell_order.receipt(Clock::get()?.unix_timestamp));
+
+        // Every escrowed share is now burned or returned; the escrow and the
+        // order are closed and their rent goes back to the user
+        close_escrow(
+            &self.escrow_token_account,
+            &self.user,
+            &self.trading_pool,
+            &self.token_program,
+        )?;
+        self.sell_order.close(self.user.to_account_info())?;
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(stock_symbol: String, quote_mint: Option<Pubkey>)]
+pub struct FulfillBuyOrdersBatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"stock_mint", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"stock_mint_info", stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        seeds = [b"price_feed", stock_symbol.as_bytes(), quote_seed(&quote_mint)],
+        bump = price_feed.bump
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+    
+    #[account(
+        seeds = [b"reserve_attestation", stock_symbol.as_bytes()],
+        bump = reserve_attestation.bump
+    )]
+    pub reserve_attestation: Account<'info, ReserveAttestation>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    /// CHECK: This is the fee vault
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+    
+    // Quote token accounts, omitted for SOL orders
+    #[account(
+        mut,
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Option<Account<'info, QuoteMintConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_vault", quote_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_fee_vault", quote_fee_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: Option<Account<'info, TokenAccount>>,
+    
+    pub quote_token_program: Option<Program<'info, Token>>,
+    
+    #[account(mut)]
+    pub backend_authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> FulfillBuyOrdersBatch<'info> {
+    /// Builds the single-order context for one fill of the batch out of its
+    /// remaining accounts. Orders are owned by this program, so a successful
+    /// deserialization proves the order account is genuine.
+    fn order_accounts(
+        &self,
+        accounts: &'info [AccountInfo<'info>],
+        stock_symbol: &str,
+        quote_mint: Option<Pubkey>,
+    ) -> Result<FulfillBuyOrder<'info>> {
+        let buy_order = Account::<BuyOrder>::try_from(&accounts[0])?;
+        require!(
+            buy_order.stock_symbol == stock_symbol && buy_order.quote_mint == quote_mint,
+            StockTradingError::BatchMarketMismatch
+        );
+        require_keys_eq!(accounts[1].key(), buy_order.user, StockTradingError::UnauthorizedUser);
+
+        let user_stock_token_account =
+            InterfaceAccount::<token_interface::TokenAccount>::try_from(&accounts[2])?;
+        require_keys_eq!(
+            user_stock_token_account.key(),
+            get_associated_token_address_with_program_id(
+                &buy_order.user,
+                &self.stock_mint.key(),
+                &self.token_program.key(),
+            ),
+            StockTradingError::InvalidBatchAccount
+        );
+
+        let user_quote_token_account = match quote_mint {
+            None => None,
+            Some(_) => {
+                let account = Account::<TokenAccount>::try_from(&accounts[3])?;
+                require_keys_eq!(
+                    account.owner,
+                    buy_order.user,
+                    StockTradingError::InvalidBatchAccount
+                );
+                Some(account)
+            }
+        };
+
+        // The user's eligibility grant comes last while it is enforced
+        let user_eligibility = match self.trading_pool.enforce_eligibility {
+            false => None,
+            true => Some(Account::<UserEligibility>::try_from(&accounts[accounts.len() - 1])?),
+        };
+
+        Ok(FulfillBuyOrder {
+            buy_order,
+            stock_mint: self.stock_mint.clone(),
+            stock_mint_info: self.stock_mint_info.clone(),
+            price_feed: self.price_feed.clone(),
+            reserve_attestation: self.reserve_attestation.clone(),
+            user_stock_token_account,
+            trading_pool: self.trading_pool.clone(),
+            trading_pool_vault: self.trading_pool_vault.clone(),
+            fee_vault: self.fee_vault.clone(),
+            quote_mint_config: self.quote_mint_config.clone(),
+            quote_vault: self.quote_vault.clone(),
+            quote_fee_vault: self.quote_fee_vault.clone(),
+            user_quote_token_account,
+            quote_token_program: self.quote_token_program.clone(),
+            user_eligibility,
+            user: accounts[1].clone(),
+            backend_authority: self.backend_authority.clone(),
+            token_program: self.token_program.clone(),
+            associated_token_program: self.associated_token_program.clone(),
+            system_program: self.system_program.clone(),
+        })
+    }
+
+    /// Carries the shared state updated by a fill over to the next one. The
+    /// quote vault is reloaded since the fill paid fees and refunds out of it.
+    fn carry_over(&mut self, order: &FulfillBuyOrder<'info>) -> Result<()> {
+        self.stock_mint_info.set_inner((*order.stock_mint_info).clone());
+        self.trading_pool.set_inner((*order.trading_pool).clone());
+        if let (Some(quote_mint_config), Some(updated)) =
+            (&mut self.quote_mint_config, &order.quote_mint_config)
+        {
+            quote_mint_config.set_inner((**updated).clone());
+        }
+        if let Some(quote_vault) = &mut self.quote_vault {
+            quote_vault.reload()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(stock_symbol: String, quote_mint: Option<Pubkey>)]
+pub struct FulfillSellOrdersBatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"stock_mint", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"stock_mint_info", stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        seeds = [b"price_feed", stock_symbol.as_bytes(), quote_seed(&quote_mint)],
+        bump = price_feed.bump
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    /// CHECK: This is the fee vault
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+    
+    // Quote token accounts, omitted for SOL orders
+    #[account(
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Option<Account<'info, QuoteMintConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_vault", quote_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_fee_vault", quote_fee_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: Option<Account<'info, TokenAccount>>,
+    
+    pub quote_token_program: Option<Program<'info, Token>>,
+    
+    #[account(mut)]
+    pub backend_authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> FulfillSellOrdersBatch<'info> {
+    /// Builds the single-order context for one fill of the batch out of its
+    /// remaining accounts. Orders are owned by this program, so a successful
+    /// deserialization proves the order account is genuine.
+    fn order_accounts(
+        &self,
+        accounts: &'info [AccountInfo<'info>],
+        stock_symbol: &str,
+        quote_mint: Option<Pubkey>,
+    ) -> Result<FulfillSellOrder<'info>> {
+        let sell_order = Account::<SellOrder>::try_from(&accounts[0])?;
+        require!(
+            sell_order.stock_symbol == stock_symbol && sell_order.quote_mint == quote_mint,
+            StockTradingError::BatchMarketMismatch
+        );
+        require_keys_eq!(accounts[1].key(), sell_order.user, StockTradingError::UnauthorizedUser);
+
+        let user_stock_token_account =
+            InterfaceAccount::<token_interface::TokenAccount>::try_from(&accounts[2])?;
+        require_keys_eq!(
+            user_stock_token_account.key(),
+            get_associated_token_address_with_program_id(
+                &sell_order.user,
+                &self.stock_mint.key(),
+                &self.token_program.key(),
+            ),
+            StockTradingError::InvalidBatchAccount
+        );
+
+        let escrow_token_account =
+            InterfaceAccount::<token_interface::TokenAccount>::try_from(&accounts[3])?;
+        let (escrow, _) = Pubkey::find_program_address(
+            &[b"escrow", sell_order.key().as_ref()],
+            &crate::ID,
+        );
+        require_keys_eq!(
+            escrow_token_account.key(),
+            escrow,
+            StockTradingError::InvalidBatchAccount
+        );
+
+        let user_quote_token_account = match quote_mint {
+            None => None,
+            Some(_) => {
+                let account = Account::<TokenAccount>::try_from(&accounts[4])?;
+                require_keys_eq!(
+                    account.owner,
+                    sell_order.user,
+                    StockTradingError::InvalidBatchAccount
+                );
+                Some(account)
+            }
+        };
+
+        // The user's eligibility grant comes last while it is enforced
+        let user_eligibility = match self.trading_pool.enforce_eligibility {
+            false => None,
+            true => Some(Account::<UserEligibility>::try_from(&accounts[accounts.len() - 1])?),
+        };
+
+        Ok(FulfillSellOrder {
+            sell_order,
+            stock_mint: self.stock_mint.clone(),
+            stock_mint_info: self.stock_mint_info.clone(),
+            price_feed: self.price_feed.clone(),
+            user_stock_token_account,
+            escrow_token_account,
+            trading_pool: self.trading_pool.clone(),
+            trading_pool_vault: self.trading_pool_vault.clone(),
+            fee_vault: self.fee_vault.clone(),
+            quote_mint_config: self.quote_mint_config.clone(),
+            quote_vault: self.quote_vault.clone(),
+            quote_fee_vault: self.quote_fee_vault.clone(),
+            user_quote_token_account,
+            quote_token_program: self.quote_token_program.clone(),
+            user_eligibility,
+            user: accounts[1].clone(),
+            backend_authority: self.backend_authority.clone(),
+            token_program: self.token_program.clone(),
+            system_program: self.system_program.clone(),
+        })
+    }
+
+    /// Carries the shared state updated by a fill over to the next one. The
+    /// quote vault is reloaded since the fill paid proceeds out of it.
+    fn carry_over(&mut self, order: &FulfillSellOrder<'info>) -> Result<()> {
+        self.stock_mint_info.set_inner((*order.stock_mint_info).clone());
+        if let Some(quote_vault) = &mut self.quote_vault {
+            quote_vault.reload()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct CancelBuyOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"buy_order",
+            buy_order.user.as_ref(),
+            buy_order.order_id.to_le_bytes().as_ref()
+        ],
+        bump = buy_order.bump,
+        has_one = user @ StockTradingError::UnauthorizedUser,
+        close = user
+    )]
+    pub buy_order: Account<'info, BuyOrder>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    // Quote token accounts, omitted for SOL orders
+    #[account(
+        mut,
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Option<Account<'info, QuoteMintConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_vault", quote_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        token::authority = user
+    )]
+    pub user_quote_token_account: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RejectBuyOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"buy_order",
+            buy_order.user.as_ref(),
+            buy_order.order_id.to_le_bytes().as_ref()
+        ],
+        bump = buy_order.bump,
+        has_one = user @ StockTradingError::UnauthorizedUser,
+        close = user
+    )]
+    pub buy_order: Account<'info, BuyOrder>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = backend_authority @ StockTradingError::UnauthorizedBackend
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    // Quote token accounts, omitted for SOL orders
+    #[account(
+        mut,
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Option<Account<'info, QuoteMintConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_vault", quote_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        token::authority = user
+    )]
+    pub user_quote_token_account: Option<Account<'info, TokenAccount>>,
+    
+    /// CHECK: User account to receive refund, checked against the order
+    #[account(mut)]
+    pub user: AccountInfo<'info>,
+    
+    pub backend_authority: Signer<'info>,
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelSellOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sell_order",
+            sell_order.user.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref()
+        ],
+        bump = sell_order.bump,
+        has_one = user @ StockTradingError::UnauthorizedUser,
+        close = user
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+    
+    #[account(
+        seeds = [b"stock_mint", sell_order.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        associated_token::mint = stock_mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_stock_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = stock_mint,
+        token::authority = trading_pool,
+        token::token_program = token_program,
+        seeds = [b"escrow", sell_order.key().as_ref()],
+        bump
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct RejectSellOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sell_order",
+            sell_order.user.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref()
+        ],
+        bump = sell_order.bump,
+        has_one = user @ StockTradingError::UnauthorizedUser,
+        close = user
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+    
+    #[account(
+        seeds = [b"stock_mint", sell_order.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        associated_token::mint = stock_mint,
+        associated_token::authority = sell_order.user,
+        associated_token::token_program = token_program
+    )]
+    pub user_stock_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = stock_mint,
+        token::authority = trading_pool,
+        token::token_program = token_program,
+        seeds = [b"escrow", sell_order.key().as_ref()],
+        bump
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = backend_authority @ StockTradingError::UnauthorizedBackend
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: User account to receive escrow and order rent, checked against the order
+    #[account(mut)]
+    pub user: AccountInfo<'info>,
+    
+    pub backend_authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimExpiredBuyOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"buy_order",
+            buy_order.user.as_ref(),
+            buy_order.order_id.to_le_bytes().as_ref()
+        ],
+        bump = buy_order.bump,
+        has_one = user @ StockTradingError::UnauthorizedUser,
+        close = user
+    )]
+    pub buy_order: Account<'info, BuyOrder>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    // Quote token accounts, omitted for SOL orders
+    #[account(
+        mut,
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Option<Account<'info, QuoteMintConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_vault", quote_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        token::authority = user
+    )]
+    pub user_quote_token_account: Option<Account<'info, TokenAccount>>,
+    
+    /// CHECK: User account to receive refund and order rent, checked against the order
+    #[account(mut)]
+    pub user: AccountInfo<'info>,
+    
+    /// Anyone may crank an expired order
+    pub cranker: Signer<'info>,
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimExpiredSellOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sell_order",
+            sell_order.user.as_ref(),
+            sell_order.order_id.to_le_bytes().as_ref()
+        ],
+        bump = sell_order.bump,
+        has_one = user @ StockTradingError::UnauthorizedUser,
+        close = user
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+    
+    #[account(
+        seeds = [b"stock_mint", sell_order.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        associated_token::mint = stock_mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_stock_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = stock_mint,
+        token::authority = trading_pool,
+        token::token_program = token_program,
+        seeds = [b"escrow", sell_order.key().as_ref()],
+        bump
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: User account to receive escrow and order rent, checked against the order
+    #[account(mut)]
+    pub user: AccountInfo<'info>,
+    
+    /// Anyone may crank an expired order
+    pub cranker: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SetWithdrawalDelay<'info> {
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub vault_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct QueueVaultWithdrawal<'info> {
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub vault_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteVaultWithdrawal<'info> {
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    /// CHECK: Withdrawal destination, checked against the queued withdrawal
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+    
+    // Quote token accounts, omitted for SOL withdrawals
+    #[account(
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Option<Account<'info, QuoteMintConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_vault", quote_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_vault: Option<Account<'info, TokenAccount>>,
+    
+    /// Anyone may execute a withdrawal once its delay has passed
+    pub executor: Signer<'info>,
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelVaultWithdrawal<'info> {
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub vault_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DepositVaultFunds<'info> {
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub vault_authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetVaultBalances<'info> {
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAuthorityTransfer<'info> {
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub vault_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// The pending vault or backend authority
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateMultisig<'info> {
+    #[account(
+        init,
+        payer = vault_authority,
+        space = 8 + Multisig::LEN,
+        seeds = [b"multisig", trading_pool.key().as_ref()],
+        bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub vault_authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeMultisigAction<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig", trading_pool.key().as_ref()],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+    
+    #[account(
+        init,
+        payer = proposer,
+        space = 8 + MultisigProposal::LEN,
+        seeds = [
+            b"multisig_proposal",
+            multisig.key().as_ref(),
+            multisig.proposal_count.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub proposal: Account<'info, MultisigProposal>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveMultisigProposal<'info> {
+    #[account(
+        seeds = [b"multisig", trading_pool.key().as_ref()],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+    
+    #[account(
+        mut,
+        seeds = [
+            b"multisig_proposal",
+            multisig.key().as_ref(),
+            proposal.proposal_id.to_le_bytes().as_ref()
+        ],
+        bump = proposal.bump,
+        has_one = multisig
+    )]
+    pub proposal: Account<'info, MultisigProposal>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteMultisigProposal<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig", trading_pool.key().as_ref()],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+    
+    #[account(
+        mut,
+        seeds = [
+            b"multisig_proposal",
+            multisig.key().as_ref(),
+            proposal.proposal_id.to_le_bytes().as_ref()
+        ],
+        bump = proposal.bump,
+        has_one = multisig
+    )]
+    pub proposal: Account<'info, MultisigProposal>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// Anyone may execute an approved proposal
+    pub executor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: DistributionParams)]
+pub struct ExecuteDistributionProposal<'info> {
+    #[account(
+        seeds = [b"multisig", trading_pool.key().as_ref()],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+    
+    #[account(
+        mut,
+        seeds = [
+            b"multisig_proposal",
+            multisig.key().as_ref(),
+            proposal.proposal_id.to_le_bytes().as_ref()
+        ],
+        bump = proposal.bump,
+        has_one = multisig
+    )]
+    pub proposal: Account<'info, MultisigProposal>,
+    
+    #[account(
+        init,
+        payer = executor,
+        space = 8 + Distribution::space(params.claimant_count),
+        seeds = [
+            b"distribution",
+            params.stock_symbol.as_bytes(),
+            params.record_date.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub distribution: Account<'info, Distribution>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", params.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    // Quote token accounts, omitted for SOL dividends
+    #[account(
+        mut,
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Option<Account<'info, QuoteMintConfig>>,
+    
+    #[account(
+        seeds = [b"quote_vault", quote_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_vault: Option<Account<'info, TokenAccount>>,
+    
+    /// Anyone may execute an approved proposal, paying for the distribution
+    #[account(mut)]
+    pub executor: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    stock_symbol: String,
+    record_date: i64,
+    merkle_root: [u8; 32],
+    total_amount: u64,
+    claimant_count: u32
+)]
+pub struct CreateDistribution<'info> {
+    #[account(
+        init,
+        payer = vault_authority,
+        space = 8 + Distribution::space(claimant_count),
+        seeds = [b"distribution", stock_symbol.as_bytes(), record_date.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub distribution: Account<'info, Distribution>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    // Quote token accounts, omitted for SOL dividends
+    #[account(
+        mut,
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Option<Account<'info, QuoteMintConfig>>,
+    
+    #[account(
+        seeds = [b"quote_vault", quote_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub vault_authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimDividend<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"distribution",
+            distribution.stock_symbol.as_bytes(),
+            distribution.record_date.to_le_bytes().as_ref()
+        ],
+        bump = distribution.bump
+    )]
+    pub distribution: Account<'info, Distribution>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    // Quote token accounts, omitted for SOL dividends
+    #[account(
+        mut,
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Option<Account<'info, QuoteMintConfig>>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_vault", quote_vault.mint.as_ref()],
+        bump
+    )]
+    pub quote_vault: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        token::authority = holder
+    )]
+    pub holder_quote_token_account: Option<Account<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub holder: Signer<'info>,
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimExpiredDistribution<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"distribution",
+            distribution.stock_symbol.as_bytes(),
+            distribution.record_date.to_le_bytes().as_ref()
+        ],
+        bump = distribution.bump
+    )]
+    pub distribution: Account<'info, Distribution>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    // Omitted for SOL dividends
+    #[account(
+        mut,
+        seeds = [b"quote_mint", quote_mint_config.mint.as_ref()],
+        bump = quote_mint_config.bump
+    )]
+    pub quote_mint_config: Option<Account<'info, QuoteMintConfig>>,
+    
+    /// Anyone may crank an expired distribution
+    pub cranker: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(stock_symbol: String)]
+pub struct CreateOrderBook<'info> {
+    #[account(
+        init,
+        payer = vault_authority,
+        space = 8 + OrderBook::LEN,
+        seeds = [b"order_book", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        seeds = [b"stock_mint", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        init,
+        payer = vault_authority,
+        token::mint = stock_mint,
+        token::authority = trading_pool,
+        token::token_program = token_program,
+        seeds = [b"book_escrow", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub book_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub vault_authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceBid<'info> {
+    #[account(
+        mut,
+        seeds = [b"order_book", order_book.stock_symbol.as_bytes()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", order_book.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    /// KYC grant of the user, required while eligibility is enforced
+    #[account(
+        seeds = [b"user_eligibility", user.key().as_ref()],
+        bump = user_eligibility.bump
+    )]
+    pub user_eligibility: Option<Account<'info, UserEligibility>>,
+    
+    /// CHECK: Owner of the worst bid, refunded when a full book evicts it;
+    /// checked against that bid
+    #[account(mut)]
+    pub evicted_owner: Option<UncheckedAccount<'info>>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceAsk<'info> {
+    #[account(
+        mut,
+        seeds = [b"order_book", order_book.stock_symbol.as_bytes()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        seeds = [b"stock_mint", order_book.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", order_book.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        mut,
+        associated_token::mint = stock_mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_stock_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"book_escrow", order_book.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub book_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// KYC grant of the user, required while eligibility is enforced
+    #[account(
+        seeds = [b"user_eligibility", user.key().as_ref()],
+        bump = user_eligibility.bump
+    )]
+    pub user_eligibility: Option<Account<'info, UserEligibility>>,
+    
+    /// CHECK: Associated stock token account of the owner of the worst ask,
+    /// which gets its shares back when a full book evicts it; checked against
+    /// that ask
+    #[account(mut)]
+    pub evicted_owner_stock_token_account: Option<UncheckedAccount<'info>>,
+    
+    pub user: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBid<'info> {
+    #[account(
+        mut,
+        seeds = [b"order_book", order_book.stock_symbol.as_bytes()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAsk<'info> {
+    #[account(
+        mut,
+        seeds = [b"order_book", order_book.stock_symbol.as_bytes()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        seeds = [b"stock_mint", order_book.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        associated_token::mint = stock_mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_stock_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"book_escrow", order_book.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub book_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    pub user: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct MatchOrders<'info> {
+    #[account(
+        mut,
+        seeds = [b"order_book", order_book.stock_symbol.as_bytes()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        seeds = [b"stock_mint", order_book.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", order_book.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        mut,
+        seeds = [b"book_escrow", order_book.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub book_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    /// Anyone may crank the order book
+    pub cranker: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> MatchOrders<'info> {
+    /// Whether the owner of the best bid can still receive shares: their
+    /// associated token account is open and not frozen and, while eligibility
+    /// is enforced, their grant is current. Sellers only receive SOL, so their
+    /// grant is not checked again. `accounts` must hold the buyer, that token
+    /// account and, while enforced, the buyer's eligibility PDA, so no crank
+    /// can have a bid evicted by passing other accounts.
+    fn can_receive_shares(&self, accounts: &'info [AccountInfo<'info>]) -> Result<bool> {
+        let bid = &self.order_book.bids[0];
+        let (buyer, buyer_stock_token_account) = (&accounts[0], &accounts[1]);
+        require_keys_eq!(buyer.key(), bid.owner, StockTradingError::InvalidMatchAccount);
+        require_keys_eq!(
+            buyer_stock_token_account.key(),
+            get_associated_token_address_with_program_id(
+                &bid.owner,
+                &self.stock_mint.key(),
+                &self.token_program.key(),
+            ),
+            StockTradingError::InvalidMatchAccount
+        );
+
+        if self.trading_pool.enforce_eligibility {
+            let (eligibility_address, _) = Pubkey::find_program_address(
+                &[b"user_eligibility", bid.owner.as_ref()],
+                &crate::ID,
+            );
+            require_keys_eq!(
+                accounts[3].key(),
+                eligibility_address,
+                StockTradingError::InvalidMatchAccount
+            );
+            let buyer_eligibility = Account::<UserEligibility>::try_from(&accounts[3]).ok();
+            if self.trading_pool
+                .check_eligibility(buyer_eligibility.as_deref(), bid.owner)
+                .is_err()
+            {
+                return Ok(false);
+            }
+        }
+
+        Ok(
+            InterfaceAccount::<token_interface::TokenAccount>::try_from(buyer_stock_token_account)
+                .is_ok_and(|token_account| !token_account.is_frozen())
+        )
+    }
+
+    /// Takes the best bid off the book and refunds its escrow to `buyer`.
+    fn evict_best_bid(&mut self, buyer: &AccountInfo<'info>, vault_bump: u8) -> Result<()> {
+        let bid = self.order_book.bids.remove(0);
+        refund_bid(
+            &bid,
+            buyer,
+            &mut self.trading_pool,
+            &self.trading_pool_vault,
+            &self.system_program,
+            vault_bump,
+        )?;
+
+        emit!(BookOrderCancelled {
+            stock_symbol: self.order_book.stock_symbol.clone(),
+            side: OrderSide::Buy,
+            sequence: bid.sequence,
+            owner: bid.owner,
+            shares_remaining: bid.shares_remaining,
+            cancelled_by: self.cranker.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Settles the best bid against the best ask at the price of whichever
+    /// rested first. `accounts` holds `[buyer, buyer_stock_token_account,
+    /// seller]`, then the buyer's eligibility while it is enforced, followed
+    /// by the transfer hook accounts for the buyer. The buyer's accounts are
+    /// checked by `can_receive_shares` first.
+    fn settle_match(&mut self, accounts: &'info [AccountInfo<'info>], vault_bump: u8) -> Result<()> {
+        let mut bid = self.order_book.bids[0].clone();
+        let mut ask = self.order_book.asks[0].clone();
+        let (buyer, buyer_stock_token_account, seller) = (&accounts[0], &accounts[1], &accounts[2]);
+        require_keys_eq!(seller.key(), ask.owner, StockTradingError::InvalidMatchAccount);
+        let hook_accounts = &accounts[3 + usize::from(self.trading_pool.enforce_eligibility)..];
+
+        let price_per_share = if bid.sequence < ask.sequence {
+            bid.price_per_share
+        } else {
+            ask.price_per_share
+        };
+        let shares = bid.shares_remaining.min(ask.shares_remaining);
+        // Rounded down, so a bid's fills never cost more than it escrowed
+        let total_cost = calculate_notional(
+            shares,
+            price_per_share,
+            &self.stock_mint_info,
+            Rounding::Down,
+        )?;
+
+        // Shares go straight from the book escrow to the buyer
+        let seeds = &[
+            b"trading_pool".as_ref(),
+            &[self.trading_pool.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: self.book_escrow.to_account_info(),
+            mint: self.stock_mint.to_account_info(),
+            to: buyer_stock_token_account.clone(),
+            authority: self.trading_pool.to_account_info(),
+        };
+        let cpi_program = self.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer)
+            .with_remaining_accounts(hook_accounts.to_vec());
+        
+        transfer_stock(cpi_ctx, shares, self.stock_mint.decimals)?;
+
+        // And the buyer's escrowed SOL straight to the seller
+        if total_cost > 0 {
+            transfer_from_vault(
+                &self.trading_pool_vault,
+                seller,
+                &self.system_program,
+                vault_bump,
+                total_cost,
+            )?;
+        }
+        self.trading_pool.release(total_cost)?;
+
+        bid.shares_remaining = bid.shares_remaining
+            .checked_sub(shares)
+            .ok_or(StockTradingError::Underflow)?;
+        bid.lamports_remaining = bid.lamports_remaining
+            .checked_sub(total_cost)
+            .ok_or(StockTradingError::Underflow)?;
+        ask.shares_remaining = ask.shares_remaining
+            .checked_sub(shares)
+            .ok_or(StockTradingError::Underflow)?;
+
+        // A filled bid gets back whatever it escrowed above the matched prices
+        if bid.shares_remaining == 0 {
+            if bid.lamports_remaining > 0 {
+                transfer_from_vault(
+                    &self.trading_pool_vault,
+                    buyer,
+                    &self.system_program,
+                    vault_bump,
+                    bid.lamports_remaining,
+                )?;
+            }
+            self.trading_pool.release(bid.lamports_remaining)?;
+            self.order_book.bids.remove(0);
+        } else {
+            self.order_book.bids[0] = bid.clone();
+        }
+        if ask.shares_remaining == 0 {
+            self.order_book.asks.remove(0);
+        } else {
+            self.order_book.asks[0] = ask.clone();
+        }
+
+        emit!(TradeMatched {
+            stock_symbol: self.order_book.stock_symbol.clone(),
+            bid_sequence: bid.sequence,
+            ask_sequence: ask.sequence,
+            buyer: bid.owner,
+            seller: ask.owner,
+            shares,
+            price_per_share,
+            total_cost,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct AdminCancelBookOrders<'info> {
+    #[account(
+        mut,
+        seeds = [b"order_book", order_book.stock_symbol.as_bytes()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        seeds = [b"stock_mint", order_book.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", order_book.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        mut,
+        seeds = [b"book_escrow", order_book.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub book_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    pub vault_authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> AdminCancelBookOrders<'info> {
+    /// Refunds an order taken off the book: `refund_account` is the owner of
+    /// a bid or the owner's associated stock token account for an ask.
+    fn refund(
+        &mut self,
+        side: OrderSide,
+        order: &BookOrder,
+        refund_account: &AccountInfo<'info>,
+        hook_accounts: &[AccountInfo<'info>],
+        vault_bump: u8,
+    ) -> Result<()> {
+        match side {
+            OrderSide::Buy => refund_bid(
+                order,
+                refund_account,
+                &mut self.trading_pool,
+                &self.trading_pool_vault,
+                &self.system_program,
+                vault_bump,
+            )?,
+            OrderSide::Sell => return_ask_shares(
+                order,
+                refund_account,
+                &self.book_escrow,
+                &self.stock_mint,
+                &self.trading_pool,
+                &self.token_program,
+                hook_accounts,
+            )?,
+        }
+
+        emit!(BookOrderCancelled {
+            stock_symbol: self.order_book.stock_symbol.clone(),
+            side,
+            sequence: order.sequence,
+            owner: order.owner,
+            shares_remaining: order.shares_remaining,
+            cancelled_by: self.vault_authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct RouteBidToBrokerage<'info> {
+    #[account(
+        mut,
+        seeds = [b"order_book", order_book.stock_symbol.as_bytes()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserAccount::LEN,
+        seeds = [b"user_account", user.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = 8 + BuyOrder::LEN,
+        seeds = [
+            b"buy_order",
+            user.key().as_ref(),
+            user_account.order_nonce.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub buy_order: Account<'info, BuyOrder>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", order_book.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RouteAskToBrokerage<'info> {
+    #[account(
+        mut,
+        seeds = [b"order_book", order_book.stock_symbol.as_bytes()],
+        bump = order_book.bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserAccount::LEN,
+        seeds = [b"user_account", user.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = 8 + SellOrder::LEN,
+        seeds = [
+            b"sell_order",
+            user.key().as_ref(),
+            user_account.order_nonce.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+    
+    #[account(
+        seeds = [b"stock_mint", order_book.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", order_book.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        mut,
+        seeds = [b"book_escrow", order_book.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub book_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        init,
+        payer = user,
+        token::mint = stock_mint,
+        token::authority = trading_pool,
+        token::token_program = token_program,
+        seeds = [b"escrow", sell_order.key().as_ref()],
+        bump
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(stock_symbol: String)]
+pub struct CreateAmmPool<'info> {
+    #[account(
+        init,
+        payer = vault_authority,
+        space = 8 + AmmPool::LEN,
+        seeds = [b"amm_pool", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub amm_pool: Account<'info, AmmPool>,
+    
+    #[account(
+        seeds = [b"stock_mint", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        init,
+        payer = vault_authority,
+        mint::decimals = AMM_LP_DECIMALS,
+        mint::authority = trading_pool,
+        mint::token_program = token_program,
+        seeds = [b"lp_mint", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub lp_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        init,
+        payer = vault_authority,
+        token::mint = stock_mint,
+        token::authority = trading_pool,
+        token::token_program = token_program,
+        seeds = [b"amm_stock_vault", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub amm_stock_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump,
+        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub vault_authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositLiquidity<'info> {
+    #[account(
+        mut,
+        seeds = [b"amm_pool", amm_pool.stock_symbol.as_bytes()],
+        bump = amm_pool.bump
+    )]
+    pub amm_pool: Account<'info, AmmPool>,
+    
+    #[account(
+        seeds = [b"stock_mint", amm_pool.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", amm_pool.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        mut,
+        seeds = [b"lp_mint", amm_pool.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub lp_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"amm_stock_vault", amm_pool.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub amm_stock_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        associated_token::mint = stock_mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_stock_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = lp_mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_lp_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    /// KYC grant of the user, required while eligibility is enforced
+    #[account(
+        seeds = [b"user_eligibility", user.key().as_ref()],
+        bump = user_eligibility.bump
+    )]
+    pub user_eligibility: Option<Account<'info, UserEligibility>>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawLiquidity<'info> {
+    #[account(
+        mut,
+        seeds = [b"amm_pool", amm_pool.stock_symbol.as_bytes()],
+        bump = amm_pool.bump
+    )]
+    pub amm_pool: Account<'info, AmmPool>,
+    
+    #[account(
+        seeds = [b"stock_mint", amm_pool.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"lp_mint", amm_pool.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub lp_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"amm_stock_vault", amm_pool.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub amm_stock_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = stock_mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_stock_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        associated_token::mint = lp_mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_lp_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    #[account(
+        mut,
+        seeds = [b"amm_pool", amm_pool.stock_symbol.as_bytes()],
+        bump = amm_pool.bump
+    )]
+    pub amm_pool: Account<'info, AmmPool>,
+    
+    #[account(
+        seeds = [b"stock_mint", amm_pool.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", amm_pool.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        mut,
+        seeds = [b"amm_stock_vault", amm_pool.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub amm_stock_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = stock_mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_stock_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    /// KYC grant of the user, required while eligibility is enforced
+    #[account(
+        seeds = [b"user_eligibility", user.key().as_ref()],
+        bump = user_eligibility.bump
+    )]
+    pub user_eligibility: Option<Account<'info, UserEligibility>>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(stock_symbol: String)]
+pub struct PlaceConditionalBuyOrder<'info> {
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserAccount::LEN,
+        seeds = [b"user_account", user.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    
     #[account(
         init,
-        payer = payer,
-        space = 8 + TradingPool::LEN,
+        payer = user,
+        space = 8 + ConditionalOrder::LEN,
+        seeds = [
+            b"conditional_order",
+            user.key().as_ref(),
+            user_account.order_nonce.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub conditional_order: Account<'info, ConditionalOrder>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        mut,
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: This is the trading pool vault that receives SOL
+    #[account(
+        mut,
+        seeds = [b"trading_pool_vault"],
+        bump
+    )]
+    pub trading_pool_vault: AccountInfo<'info>,
+    
+    /// KYC grant of the user, required while eligibility is enforced
+    #[account(
+        seeds = [b"user_eligibility", user.key().as_ref()],
+        bump = user_eligibility.bump
+    )]
+    pub user_eligibility: Option<Account<'info, UserEligibility>>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(stock_symbol: String)]
+pub struct PlaceConditionalSellOrder<'info> {
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserAccount::LEN,
+        seeds = [b"user_account", user.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = 8 + ConditionalOrder::LEN,
+        seeds = [
+            b"conditional_order",
+            user.key().as_ref(),
+            user_account.order_nonce.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub conditional_order: Account<'info, ConditionalOrder>,
+    
+    #[account(
+        seeds = [b"stock_mint", stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        mut,
+        associated_token::mint = stock_mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_stock_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        init,
+        payer = user,
+        token::mint = stock_mint,
+        token::authority = trading_pool,
+        token::token_program = token_program,
+        seeds = [b"conditional_escrow", conditional_order.key().as_ref()],
+        bump
+    )]
+    pub conditional_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// KYC grant of the user, required while eligibility is enforced
+    #[account(
+        seeds = [b"user_eligibility", user.key().as_ref()],
+        bump = user_eligibility.bump
+    )]
+    pub user_eligibility: Option<Account<'info, UserEligibility>>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TriggerConditionalBuyOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"conditional_order",
+            conditional_order.user.as_ref(),
+            conditional_order.order_id.to_le_bytes().as_ref()
+        ],
+        bump = conditional_order.bump,
+        close = cranker
+    )]
+    pub conditional_order: Account<'info, ConditionalOrder>,
+    
+    #[account(
+        mut,
+        seeds = [b"user_account", conditional_order.user.as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    
+    #[account(
+        init,
+        payer = cranker,
+        space = 8 + BuyOrder::LEN,
+        seeds = [
+            b"buy_order",
+            conditional_order.user.as_ref(),
+            user_account.order_nonce.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub buy_order: Account<'info, BuyOrder>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", conditional_order.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        seeds = [
+            b"price_feed",
+            conditional_order.stock_symbol.as_bytes(),
+            quote_seed(&None)
+        ],
+        bump = price_feed.bump
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+    
+    /// CHECK: Trading schedule, which may not exist. Order placement is only
+    /// restricted to market hours once one is set.
+    #[account(
+        seeds = [b"trading_schedule"],
+        bump
+    )]
+    pub trading_schedule: UncheckedAccount<'info>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TriggerConditionalSellOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"conditional_order",
+            conditional_order.user.as_ref(),
+            conditional_order.order_id.to_le_bytes().as_ref()
+        ],
+        bump = conditional_order.bump,
+        close = cranker
+    )]
+    pub conditional_order: Account<'info, ConditionalOrder>,
+    
+    #[account(
+        mut,
+        seeds = [b"user_account", conditional_order.user.as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    
+    #[account(
+        init,
+        payer = cranker,
+        space = 8 + SellOrder::LEN,
+        seeds = [
+            b"sell_order",
+            conditional_order.user.as_ref(),
+            user_account.order_nonce.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub sell_order: Account<'info, SellOrder>,
+    
+    #[account(
+        seeds = [b"stock_mint", conditional_order.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        seeds = [b"stock_mint_info", conditional_order.stock_symbol.as_bytes()],
+        bump = stock_mint_info.bump
+    )]
+    pub stock_mint_info: Account<'info, StockMintInfo>,
+    
+    #[account(
+        seeds = [
+            b"price_feed",
+            conditional_order.stock_symbol.as_bytes(),
+            quote_seed(&None)
+        ],
+        bump = price_feed.bump
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+    
+    /// CHECK: Trading schedule, which may not exist. Order placement is only
+    /// restricted to market hours once one is set.
+    #[account(
+        seeds = [b"trading_schedule"],
+        bump
+    )]
+    pub trading_schedule: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        token::mint = stock_mint,
+        token::authority = trading_pool,
+        token::token_program = token_program,
+        seeds = [b"conditional_escrow", conditional_order.key().as_ref()],
+        bump
+    )]
+    pub conditional_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        init,
+        payer = cranker,
+        token::mint = stock_mint,
+        token::authority = trading_pool,
+        token::token_program = token_program,
+        seeds = [b"escrow", sell_order.key().as_ref()],
+        bump
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelConditionalBuyOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"conditional_order",
+            conditional_order.user.as_ref(),
+            conditional_order.order_id.to_le_bytes().as_ref()
+        ],
+        bump = conditional_order.bump,
+        has_one = user @ StockTradingError::UnauthorizedUser,
+        close = user
+    )]
+    pub conditional_order: Account<'info, ConditionalOrder>,
+    
+    #[account(
+        mut,
         seeds = [b"trading_pool"],
-        bump
+        bump = trading_pool.bump
     )]
     pub trading_pool: Account<'info, TradingPool>,
     
-    /// CHECK: This is the trading pool vault that holds SOL
+    /// CHECK: This is the trading pool vault
     #[account(
+        mut,
         seeds = [b"trading_pool_vault"],
         bump
     )]
     pub trading_pool_vault: AccountInfo<'info>,
     
     #[account(mut)]
-    pub payer: Signer<'info>,
+    pub user: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
-#[derive(Accounts)]
-#[instruction(stock_symbol: String, _decimals: u8)]
-pub struct CreateStockMint<'info> {
-    #[account(
-        init,
-        payer = vault_authority,
-        mint::decimals = 0,
-        mint::authority = trading_pool,
-        seeds = [b"stock_mint", stock_symbol.as_bytes()],
-        bump
-    )]
-    pub stock_mint: Account<'info, Mint>,
-    
-    #[account(
-        init,
-        payer = vault_authority,
-        space = 8 + StockMintInfo::LEN,
-        seeds = [b"stock_mint_info", stock_symbol.as_bytes()],
-        bump
-    )]
-    pub stock_mint_info: Account<'info, StockMintInfo>,
-    
-    #[account(
-        seeds = [b"trading_pool"],
-        bump = trading_pool.bump,
-        has_one = vault_authority
-    )]
-    pub trading_pool: Account<'info, TradingPool>,
-    
-    #[account(mut)]
-    pub vault_authority: Signer<'info>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
+#[derive(Accounts)]
+pub struct CancelConditionalSellOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"conditional_order",
+            conditional_order.user.as_ref(),
+            conditional_order.order_id.to_le_bytes().as_ref()
+        ],
+        bump = conditional_order.bump,
+        has_one = user @ StockTradingError::UnauthorizedUser,
+        close = user
+    )]
+    pub conditional_order: Account<'info, ConditionalOrder>,
+    
+    #[account(
+        seeds = [b"stock_mint", conditional_order.stock_symbol.as_bytes()],
+        bump
+    )]
+    pub stock_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        associated_token::mint = stock_mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_stock_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = stock_mint,
+        token::authority = trading_pool,
+        token::token_program = token_program,
+        seeds = [b"conditional_escrow", conditional_order.key().as_ref()],
+        bump
+    )]
+    pub conditional_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// Account structs
+#[account]
+pub struct TradingPool {
+    pub vault_authority: Pubkey,
+    pub backend_authority: Pubkey,
+    pub oracle_authority: Pubkey,
+    pub max_price_deviation_bps: u16,
+    pub max_price_staleness: i64,
+    pub paused: bool,
+    pub fee_authority: Pubkey,
+    pub fee_bps: u16,
+    pub multisig: Option<Pubkey>,
+    pub pending_vault_authority: Option<Pubkey>,
+    pub pending_backend_authority: Option<Pubkey>,
+    pub withdrawal_delay: i64,
+    pub pending_withdrawal: Option<PendingWithdrawal>,
+    pub reserved_lamports: u64,
+    pub max_attestation_staleness: i64,
+    pub reserve_attestor: Pubkey,
+    pub compliance_authority: Pubkey,
+    /// Orders are restricted to wallets with a current `UserEligibility`.
+    pub enforce_eligibility: bool,
+    pub bump: u8,
+}
+
+impl TradingPool {
+    pub const LEN: usize = 32 + 32 + 32 + 2 + 8 + 1 + 32 + 2 + (1 + 32)
+        + (1 + 32) + (1 + 32) + 8 + (1 + PendingWithdrawal::LEN) + 8 + 8 + 32 + 32 + 1 + 1;
+
+    /// Requires a current eligibility grant for `user` while the pool
+    /// enforces eligibility.
+    pub fn check_eligibility(
+        &self,
+        user_eligibility: Option<&UserEligibility>,
+        user: Pubkey,
+    ) -> Result<()> {
+        if !self.enforce_eligibility {
+            return Ok(());
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            user_eligibility.is_some_and(|eligibility| {
+                eligibility.user == user && eligibility.is_eligible(now)
+            }),
+            StockTradingError::UserNotEligible
+        );
+        Ok(())
+    }
+
+    /// Earmarks vault SOL escrowed by an open buy order.
+    pub fn reserve(&mut self, amount: u64) -> Result<()> {
+        self.reserved_lamports = self.reserved_lamports
+            .checked_add(amount)
+            .ok_or(StockTradingError::Overflow)?;
+        Ok(())
+    }
+
+    /// Releases a reservation once the SOL is spent, refunded or forfeited.
+    pub fn release(&mut self, amount: u64) -> Result<()> {
+        self.reserved_lamports = self.reserved_lamports
+            .checked_sub(amount)
+            .ok_or(StockTradingError::Underflow)?;
+        Ok(())
+    }
+
+    /// Vault lamports that back no open order and sit above the rent-exempt floor.
+    pub fn free_lamports(&self, trading_pool_vault: &AccountInfo) -> Result<u64> {
+        let rent_exempt_minimum = Rent::get()?.minimum_balance(trading_pool_vault.data_len());
+        Ok(trading_pool_vault
+            .lamports()
+            .saturating_sub(rent_exempt_minimum)
+            .saturating_sub(self.reserved_lamports))
+    }
+
+    /// Applies the given oracle settings, leaving the rest unchanged.
+    pub fn set_oracle_config(
+        &mut self,
+        oracle_authority: Option<Pubkey>,
+        max_price_deviation_bps: Option<u16>,
+        max_price_staleness: Option<i64>,
+        max_attestation_staleness: Option<i64>,
+        reserve_attestor: Option<Pubkey>,
+        timestamp: i64,
+    ) -> Result<()> {
+        if let Some(oracle_authority) = oracle_authority {
+            self.oracle_authority = oracle_authority;
+        }
+
+        if let Some(max_price_deviation_bps) = max_price_deviation_bps {
+            require!(
+                max_price_deviation_bps <= BPS_DENOMINATOR,
+                StockTradingError::InvalidOracleConfig
+            );
+            self.max_price_deviation_bps = max_price_deviation_bps;
+        }
+
+        if let Some(max_price_staleness) = max_price_staleness {
+            require!(max_price_staleness > 0, StockTradingError::InvalidOracleConfig);
+            self.max_price_staleness = max_price_staleness;
+        }
+
+        if let Some(max_attestation_staleness) = max_attestation_staleness {
+            require!(max_attestation_staleness > 0, StockTradingError::InvalidOracleConfig);
+            self.max_attestation_staleness = max_attestation_staleness;
+        }
+
+        if let Some(reserve_attestor) = reserve_attestor {
+            self.reserve_attestor = reserve_attestor;
+        }
+
+        emit!(OracleConfigUpdated {
+            oracle_authority: self.oracle_authority,
+            max_price_deviation_bps: self.max_price_deviation_bps,
+            max_price_staleness: self.max_price_staleness,
+            max_attestation_staleness: self.max_attestation_staleness,
+            reserve_attestor: self.reserve_attestor,
+            timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Applies the given fee settings, leaving the rest unchanged.
+    pub fn set_fee_config(
+        &mut self,
+        fee_bps: Option<u16>,
+        fee_authority: Option<Pubkey>,
+        timestamp: i64,
+    ) -> Result<()> {
+        if let Some(fee_bps) = fee_bps {
+            require!(fee_bps <= MAX_FEE_BPS, StockTradingError::FeeTooHigh);
+            self.fee_bps = fee_bps;
+        }
+
+        if let Some(fee_authority) = fee_authority {
+            self.fee_authority = fee_authority;
+        }
+
+        emit!(FeeConfigUpdated {
+            fee_bps: self.fee_bps,
+            fee_authority: self.fee_authority,
+            timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Applies the given compliance settings, leaving the rest unchanged.
+    pub fn set_compliance_config(
+        &mut self,
+        compliance_authority: Option<Pubkey>,
+        enforce_eligibility: Option<bool>,
+        timestamp: i64,
+    ) {
+        if let Some(compliance_authority) = compliance_authority {
+            self.compliance_authority = compliance_authority;
+        }
+
+        if let Some(enforce_eligibility) = enforce_eligibility {
+            self.enforce_eligibility = enforce_eligibility;
+        }
+
+        emit!(ComplianceConfigUpdated {
+            compliance_authority: self.compliance_authority,
+            enforce_eligibility: self.enforce_eligibility,
+            timestamp,
+        });
+    }
+
+    /// Records new pending authorities. They only take effect once the
+    /// incoming key signs `accept_authority`; `None` clears a pending transfer.
+    pub fn propose_authorities(
+        &mut self,
+        new_vault_authority: Option<Pubkey>,
+        new_backend_authority: Option<Pubkey>,
+        proposed_by: Pubkey,
+        timestamp: i64,
+    ) {
+        self.pending_vault_authority = new_vault_authority;
+        self.pending_backend_authority = new_backend_authority;
+
+        emit!(AuthorityTransferProposed {
+            pending_vault_authority: new_vault_authority,
+            pending_backend_authority: new_backend_authority,
+            proposed_by,
+            timestamp,
+        });
+    }
+
+    /// Queues a vault withdrawal that anyone can execute once the pool's
+    /// withdrawal delay has passed. Only one withdrawal can be queued at a time.
+    pub fn queue_withdrawal(
+        &mut self,
+        amount: u64,
+        quote_mint: Option<Pubkey>,
+        destination: Pubkey,
+        queued_by: Pubkey,
+        now: i64,
+    ) -> Result<()> {
+        require!(amount > 0, StockTradingError::InvalidAmount);
+        require!(
+            self.pending_withdrawal.is_none(),
+            StockTradingError::WithdrawalAlreadyQueued
+        );
+
+        let executable_at = now
+            .checked_add(self.withdrawal_delay)
+            .ok_or(StockTradingError::Overflow)?;
+        self.pending_withdrawal = Some(PendingWithdrawal {
+            amount,
+            quote_mint,
+            destination,
+            queued_by,
+            executable_at,
+        });
+
+        emit!(VaultWithdrawalQueued {
+            amount,
+            quote_mint,
+            destination,
+            queued_by,
+            executable_at,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_withdrawal(&mut self, cancelled_by: Pubkey, now: i64) -> Result<()> {
+        let withdrawal = self.pending_withdrawal
+            .take()
+            .ok_or(StockTradingError::NoPendingWithdrawal)?;
+
+        emit!(VaultWithdrawalCancelled {
+            amount: withdrawal.amount,
+            destination: withdrawal.destination,
+            cancelled_by,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct PendingWithdrawal {
+    pub amount: u64,
+    /// `None` for SOL, otherwise the quote token drawn from its quote vault.
+    pub quote_mint: Option<Pubkey>,
+    pub destination: Pubkey,
+    pub queued_by: Pubkey,
+    pub executable_at: i64,
+}
+
+impl PendingWithdrawal {
+    pub const LEN: usize = 8 + (1 + 32) + 32 + 32 + 8;
+}
+
+/// Return data of `get_vault_balances`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct VaultBalances {
+    pub total_lamports: u64,
+    pub reserved_lamports: u64,
+    pub rent_exempt_minimum: u64,
+    pub free_lamports: u64,
+}
+
+/// Return data of `get_market_status`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct MarketStatus {
+    pub is_open: bool,
+    pub next_open: Option<i64>,
+    pub off_hours_policy: OffHoursPolicy,
+}
+
+/// Regular session of one weekday, in minutes after local midnight. The
+/// market stays closed on days whose session does not open before it closes.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct SessionWindow {
+    pub open_minute: u16,
+    pub close_minute: u16,
+}
+
+impl SessionWindow {
+    pub const LEN: usize = 2 + 2;
+}
+
+/// Session that closes early on local `day`, in days since the Unix epoch.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct EarlyClose {
+    pub day: i32,
+    pub close_minute: u16,
+}
+
+impl EarlyClose {
+    pub const LEN: usize = 4 + 2;
+}
+
+/// One order's final execution in `fulfill_buy_orders_batch`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct BuyFill {
+    pub shares_purchased: u64,
+    pub price_per_share: u64,
+    pub total_cost: u64,
+    pub refund_amount: u64,
+}
+
+/// One order's final execution in `fulfill_sell_orders_batch`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct SellFill {
+    pub shares_sold: u64,
+    pub price_per_share: u64,
+    pub total_proceeds: u64,
+    pub shares_returned: u64,
+    /// Number of transfer hook accounts following the order's accounts.
+    pub hook_account_count: u8,
+}
+
+#[account]
+pub struct StockMintInfo {
+    pub stock_symbol: String,
+    pub mint: Pubkey,
+    /// Token amounts are base units of the mint; one whole token is
+    /// `10^decimals` of them.
+    pub decimals: u8,
+    /// Minted tokens in base units. Splits do not change it; multiply by the
+    /// split ratio for the number of shares it represents.
+    pub total_supply: u64,
+    /// Shares represented by one whole token, as a reduced fraction. Starts
+    /// at 1/1 and is scaled by every stock split or reverse split.
+    pub split_numerator: u64,
+    pub split_denominator: u64,
+    pub trading_status: TradingStatus,
+    pub fee_bps_override: Option<u16>,
+    pub bump: u8,
+}
+
+impl StockMintInfo {
+    pub const LEN: usize = (4 + 10) + 32 + 1 + 8 + 8 + 8 + 1 + (1 + 2) + 1;
+
+    pub fn initialize(&mut self, stock_symbol: String, mint: Pubkey, decimals: u8, bump: u8) {
+        self.stock_symbol = stock_symbol;
+        self.mint = mint;
+        self.decimals = decimals;
+        self.total_supply = 0;
+        self.split_numerator = 1;
+        self.split_denominator = 1;
+        self.trading_status = TradingStatus::Active;
+        self.fee_bps_override = None;
+        self.bump = bump;
+    }
+
+    pub fn effective_fee_bps(&self, trading_pool: &TradingPool) -> u16 {
+        self.fee_bps_override.unwrap_or(trading_pool.fee_bps)
+    }
+
+    /// Scales the share multiplier by `numerator / denominator`.
+    pub fn apply_split(&mut self, numerator: u64, denominator: u64) -> Result<()> {
+        let split_numerator = self.split_numerator
+            .checked_mul(numerator)
+            .ok_or(StockTradingError::Overflow)?;
+        let split_denominator = self.split_denominator
+            .checked_mul(denominator)
+            .ok_or(StockTradingError::Overflow)?;
+        let divisor = gcd(split_numerator, split_denominator);
+        self.split_numerator = split_numerator / divisor;
+        self.split_denominator = split_denominator / divisor;
+        Ok(())
+    }
+
+    /// Shares, in base units, represented by `amount` base units of the mint.
+    pub fn shares_for(&self, amount: u64) -> Result<u64> {
+        let shares = (amount as u128)
+            .checked_mul(self.split_numerator as u128)
+            .ok_or(StockTradingError::Overflow)?
+            / self.split_denominator as u128;
+        u64::try_from(shares).map_err(|_| error!(StockTradingError::Overflow))
+    }
+}
+
+/// Weekly market hours, holidays and early closes of the exchange, in
+/// exchange local time `utc_offset` seconds ahead of UTC.
+#[account]
+pub struct TradingSchedule {
+    pub utc_offset: i32,
+    /// Regular session of each weekday, Monday first.
+    pub sessions: [SessionWindow; 7],
+    /// Local dates the market is closed, in days since the Unix epoch.
+    pub holidays: Vec<i32>,
+    pub early_closes: Vec<EarlyClose>,
+    pub off_hours_policy: OffHoursPolicy,
+    pub updated_at: i64,
+    pub bump: u8,
+}
+
+impl TradingSchedule {
+    pub const LEN: usize = 4 + SessionWindow::LEN * 7 + (4 + 4 * MAX_SCHEDULE_HOLIDAYS)
+        + (4 + EarlyClose::LEN * MAX_SCHEDULE_EARLY_CLOSES) + 1 + 8 + 1;
+
+    /// Opening and closing minute of the session on local `day`, or `None`
+    /// if the market stays closed that day.
+    pub fn session(&self, day: i64) -> Option<(u16, u16)> {
+        if self.holidays.iter().any(|&holiday| i64::from(holiday) == day) {
+            return None;
+        }
+
+        // The Unix epoch fell on a Thursday
+        let window = &self.sessions[(day + 3).rem_euclid(7) as usize];
+        let close_minute = self.early_closes
+            .iter()
+            .find(|early_close| i64::from(early_close.day) == day)
+            .map_or(window.close_minute, |early_close| {
+                early_close.close_minute.min(window.close_minute)
+            });
+        (window.open_minute < close_minute).then_some((window.open_minute, close_minute))
+    }
+
+    pub fn is_open(&self, now: i64) -> bool {
+        let local_time = now + i64::from(self.utc_offset);
+        let minute = local_time.rem_euclid(SECONDS_PER_DAY) / 60;
+        self.session(local_time.div_euclid(SECONDS_PER_DAY))
+            .is_some_and(|(open_minute, close_minute)| {
+                (i64::from(open_minute)..i64::from(close_minute)).contains(&minute)
+            })
+    }
+
+    /// Start of the first session opening after `now`, if there is one
+    /// within `SCHEDULE_LOOKAHEAD_DAYS`.
+    pub fn next_open(&self, now: i64) -> Option<i64> {
+        let today = (now + i64::from(self.utc_offset)).div_euclid(SECONDS_PER_DAY);
+        (today..=today + SCHEDULE_LOOKAHEAD_DAYS).find_map(|day| {
+            let (open_minute, _) = self.session(day)?;
+            let open = day * SECONDS_PER_DAY + i64::from(open_minute) * 60
+                - i64::from(self.utc_offset);
+            (open > now).then_some(open)
+        })
+    }
+}
+
+/// KYC outcome for one wallet, kept by the compliance authority.
+#[account]
+pub struct UserEligibility {
+    pub user: Pubkey,
+    pub status: EligibilityStatus,
+    /// ISO 3166-1 alpha-2 code of the jurisdiction the user was verified in.
+    pub jurisdiction: [u8; 2],
+    /// The grant lapses at this time; `None` keeps it until revoked.
+    pub expires_at: Option<i64>,
+    pub updated_by: Pubkey,
+    pub updated_at: i64,
+    pub bump: u8,
+}
+
+impl UserEligibility {
+    pub const LEN: usize = 32 + 1 + 2 + (1 + 8) + 32 + 8 + 1;
+
+    pub fn is_eligible(&self, now: i64) -> bool {
+        self.status == EligibilityStatus::Approved
+            && self.expires_at.is_none_or(|expires_at| now < expires_at)
+    }
+}
+
+/// Shares of a symbol the brokerage attests to holding for the protocol.
+#[account]
+pub struct ReserveAttestation {
+    pub stock_symbol: String,
+    /// Attested shares in base units, comparable with `shares_for` of the
+    /// minted supply.
+    pub attested_shares: u64,
+    pub attested_at: i64,
+    /// Reserve attestor's ed25519 signature over `message`.
+    pub signature: [u8; 64],
+    pub attested_by: Pubkey,
+    pub bump: u8,
+}
+
+impl ReserveAttestation {
+    pub const LEN: usize = (4 + 10) + 8 + 8 + 64 + 32 + 1;
+
+    /// Bytes the reserve attestor signs: a domain tag, the symbol, then the
+    /// little-endian shares and attestation time.
+    pub fn message(stock_symbol: &str, attested_shares: u64, attested_at: i64) -> Vec<u8> {
+        [
+            b"reserve_attestation".as_slice(),
+            stock_symbol.as_bytes(),
+            &attested_shares.to_le_bytes(),
+            &attested_at.to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    /// Requires the instruction before the current one to be an ed25519
+    /// program instruction that verified `signature` by `signer` over
+    /// `message`. The native program fails the transaction on a bad signature,
+    /// so only what it verified needs checking here.
+    pub fn verify_signature(
+        instructions: &AccountInfo,
+        signer: &Pubkey,
+        message: &[u8],
+        signature: &[u8; 64],
+    ) -> Result<()> {
+        let current_index = load_current_index_checked(instructions)?;
+        require!(current_index > 0, StockTradingError::InvalidAttestationSignature);
+        let ed25519_instruction =
+            load_instruction_at_checked(usize::from(current_index - 1), instructions)?;
+        require_keys_eq!(
+            ed25519_instruction.program_id,
+            ed25519_program::ID,
+            StockTradingError::InvalidAttestationSignature
+        );
+
+        // A one-signature header: count and padding, then the signature, public
+        // key and message offsets, each with the index of the instruction
+        // holding it
+        let data = ed25519_instruction.data.as_slice();
+        require!(
+            data.len() >= ED25519_HEADER_LEN && data[0] == 1,
+            StockTradingError::InvalidAttestationSignature
+        );
+        let u16_at = |at: usize| usize::from(u16::from_le_bytes([data[at], data[at + 1]]));
+        let slice_at = |offset: usize, len: usize| data.get(offset..offset.checked_add(len)?);
+
+        // Everything must come from the ed25519 instruction's own data
+        require!(
+            [4, 8, 14].into_iter().all(|at| u16_at(at) == usize::from(u16::MAX)),
+            StockTradingError::InvalidAttestationSignature
+        );
+        require!(
+            slice_at(u16_at(2), 64) == Some(signature.as_slice())
+                && slice_at(u16_at(6), 32) == Some(signer.as_ref())
+                && u16_at(12) == message.len()
+                && slice_at(u16_at(10), message.len()) == Some(message),
+            StockTradingError::InvalidAttestationSignature
+        );
+
+        Ok(())
+    }
+
+    /// Rejects a supply that the attestation is too old to vouch for or that
+    /// exceeds the attested shares.
+    pub fn check_backing(
+        &self,
+        stock_mint_info: &StockMintInfo,
+        trading_pool: &TradingPool,
+    ) -> Result<()> {
+        let age = Clock::get()?
+            .unix_timestamp
+            .checked_sub(self.attested_at)
+            .ok_or(StockTradingError::Overflow)?;
+        require!(
+            age <= trading_pool.max_attestation_staleness,
+            StockTradingError::StaleReserveAttestation
+        );
+        require!(
+            stock_mint_info.shares_for(stock_mint_info.total_supply)? <= self.attested_shares,
+            StockTradingError::InsufficientReserves
+        );
+        Ok(())
+    }
+}
+
+#[account]
+pub struct PriceFeed {
+    pub stock_symbol: String,
+    pub quote_mint: Option<Pubkey>,
+    pub price: u64,
+    pub confidence: u64,
+    pub publish_time: i64,
+    pub bump: u8,
+}
+
+impl PriceFeed {
+    pub const LEN: usize = (4 + 10) + (1 + 32) + 8 + 8 + 8 + 1;
+
+    /// The feed price, rejected when the feed is stale or too uncertain.
+    pub fn current_price(&self, trading_pool: &TradingPool) -> Result<u64> {
+        let now = Clock::get()?.unix_timestamp;
+        let age = now
+            .checked_sub(self.publish_time)
+            .ok_or(StockTradingError::Overflow)?;
+        require!(
+            self.price > 0 && age <= trading_pool.max_price_staleness,
+            StockTradingError::StalePriceFeed
+        );
+
+        let band = (self.price as u128) * (trading_pool.max_price_deviation_bps as u128);
+        require!(
+            (self.confidence as u128) * (BPS_DENOMINATOR as u128) <= band,
+            StockTradingError::PriceConfidenceTooWide
+        );
+
+        Ok(self.price)
+    }
+
+    /// Rejects fill prices when the feed is stale, too uncertain, or when
+    /// the fill deviates from the feed by more than the pool's band.
+    pub fn check_price(&self, price_per_share: u64, trading_pool: &TradingPool) -> Result<()> {
+        self.current_price(trading_pool)?;
+
+        let band = (self.price as u128) * (trading_pool.max_price_deviation_bps as u128);
+        require!(
+            (price_per_share.abs_diff(self.price) as u128) * (BPS_DENOMINATOR as u128) <= band,
+            StockTradingError::PriceDeviationTooLarge
+        );
+
+        Ok(())
+    }
+}
+
+#[account]
+pub struct QuoteMintConfig {
+    pub mint: Pubkey,
+    pub enabled: bool,
+    pub reserved_amount: u64,
+    pub bump: u8,
 }
 
-#[derive(Accounts)]
-#[instruction(stock_symbol: String)]
-pub struct PlaceBuyOrder<'info> {
-    #[account(
-        init,
-        payer = user,
-        space = 8 + BuyOrder::LEN,
-        seeds = [
-            b"buy_order",
-            user.key().as_ref(),
-            trading_pool.total_orders.to_le_bytes().as_ref()
-        ],
-        bump
-    )]
-    pub buy_order: Account<'info, BuyOrder>,
-    
-    #[account(
-        mut,
-        seeds = [b"trading_pool"],
-        bump = trading_pool.bump
-    )]
-    pub trading_pool: Account<'info, TradingPool>,
-    
-    /// CHECK: This is the trading pool vault that receives SOL
-    #[account(
-        mut,
-        seeds = [b"trading_pool_vault"],
-        bump
-    )]
-    pub trading_pool_vault: AccountInfo<'info>,
-    
-    #[account(mut)]
-    pub user: Signer<'info>,
-    pub system_program: Program<'info, System>,
+impl QuoteMintConfig {
+    pub const LEN: usize = 32 + 1 + 8 + 1;
+
+    /// Earmarks quote tokens escrowed by an open buy order.
+    pub fn reserve(&mut self, amount: u64) -> Result<()> {
+        self.reserved_amount = self.reserved_amount
+            .checked_add(amount)
+            .ok_or(StockTradingError::Overflow)?;
+        Ok(())
+    }
+
+    /// Releases a reservation once the tokens are spent or refunded.
+    pub fn release(&mut self, amount: u64) -> Result<()> {
+        self.reserved_amount = self.reserved_amount
+            .checked_sub(amount)
+            .ok_or(StockTradingError::Underflow)?;
+        Ok(())
+    }
+
+    /// Quote vault tokens that back no open order.
+    pub fn free_amount(&self, quote_vault: &TokenAccount) -> u64 {
+        quote_vault.amount.saturating_sub(self.reserved_amount)
+    }
+}
+
+/// Per-user order counter. Order PDAs are seeded with the user and this
+/// nonce, so placing orders never contends on an account shared by all users.
+#[account]
+pub struct UserAccount {
+    pub user: Pubkey,
+    /// Id of the user's next order.
+    pub order_nonce: u64,
+    pub bump: u8,
+}
+
+impl UserAccount {
+    pub const LEN: usize = 32 + 8 + 1;
+
+    /// Claims the next order id, filling in the account on the user's first order.
+    pub fn next_order_id(&mut self, user: Pubkey, bump: u8) -> Result<u64> {
+        self.user = user;
+        self.bump = bump;
+        let order_id = self.order_nonce;
+        self.order_nonce = order_id
+            .checked_add(1)
+            .ok_or(StockTradingError::Overflow)?;
+        Ok(order_id)
+    }
+}
+
+#[account]
+pub struct BuyOrder {
+    pub user: Pubkey,
+    pub stock_symbol: String,
+    pub sol_amount: u64,
+    pub max_price_per_share: u64,
+    pub order_id: u64,
+    pub status: OrderStatus,
+    pub timestamp: i64,
+    pub expires_at: Option<i64>,
+    pub shares_received: u64,
+    pub sol_spent: u64,
+    pub fees_paid: u64,
+    pub actual_price_per_share: u64,
+    /// `None` for SOL orders. Otherwise the allowlisted SPL token the order
+    /// is quoted in; amounts and prices are then in that token's base units.
+    pub quote_mint: Option<Pubkey>,
+    /// Split ratio of the mint when the order was placed; the limit price is
+    /// per share at this ratio.
+    pub split_numerator: u64,
+    pub split_denominator: u64,
+    /// Open of the session an order placed outside market hours waits for.
+    pub queued_until: Option<i64>,
+    pub bump: u8,
+}
+
+impl BuyOrder {
+    pub const LEN: usize = 32 + (4 + 10) + 8 + 8 + 8 + 1 + 8 + (1 + 8) + 8 + 8 + 8 + 8 + (1 + 32) + 8 + 8
+        + (1 + 8) + 1;
+
+    pub fn is_open(&self) -> bool {
+        matches!(self.status, OrderStatus::Pending | OrderStatus::PartiallyFilled)
+    }
+
+    /// Final state of the order, emitted before its account is closed.
+    pub fn receipt(&self, closed_at: i64) -> OrderReceipt {
+        OrderReceipt {
+            order_id: self.order_id,
+            user: self.user,
+            stock_symbol: self.stock_symbol.clone(),
+            side: OrderSide::Buy,
+            status: self.status.clone(),
+            quote_mint: self.quote_mint,
+            amount: self.sol_amount,
+            shares_filled: self.shares_received,
+            quote_filled: self.sol_spent,
+            fees_paid: self.fees_paid,
+            average_price_per_share: self.actual_price_per_share,
+            placed_at: self.timestamp,
+            closed_at,
+        }
+    }
+
+    pub fn remaining_sol(&self) -> Result<u64> {
+        self.sol_amount
+            .checked_sub(self.sol_spent)
+            .and_then(|remaining| remaining.checked_sub(self.fees_paid))
+            .ok_or(error!(StockTradingError::Underflow))
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        matches!(self.expires_at, Some(expires_at) if now >= expires_at)
+    }
+
+    pub fn is_queued(&self, now: i64) -> bool {
+        matches!(self.queued_until, Some(queued_until) if now < queued_until)
+    }
+}
+
+#[account]
+pub struct SellOrder {
+    pub user: Pubkey,
+    pub stock_symbol: String,
+    pub shares_to_sell: u64,
+    pub min_price_per_share: u64,
+    pub order_id: u64,
+    pub status: OrderStatus,
+    pub timestamp: i64,
+    pub expires_at: Option<i64>,
+    pub shares_sold: u64,
+    pub sol_received: u64,
+    pub fees_paid: u64,
+    pub actual_price_per_share: u64,
+    /// `None` for SOL proceeds, otherwise the allowlisted SPL token they are paid in.
+    pub quote_mint: Option<Pubkey>,
+    /// Split ratio of the mint when the order was placed; the limit price is
+    /// per share at this ratio.
+    pub split_numerator: u64,
+    pub split_denominator: u64,
+    /// Open of the session an order placed outside market hours waits for.
+    pub queued_until: Option<i64>,
+    pub bump: u8,
+}
+
+impl SellOrder {
+    pub const LEN: usize = 32 + (4 + 10) + 8 + 8 + 8 + 1 + 8 + (1 + 8) + 8 + 8 + 8 + 8 + (1 + 32) + 8 + 8
+        + (1 + 8) + 1;
+
+    pub fn is_open(&self) -> bool {
+        matches!(self.status, OrderStatus::Pending | OrderStatus::PartiallyFilled)
+    }
+
+    /// Final state of the order, emitted before its account is closed.
+    pub fn receipt(&self, closed_at: i64) -> OrderReceipt {
+        OrderReceipt {
+            order_id: self.order_id,
+            user: self.user,
+            stock_symbol: self.stock_symbol.clone(),
+            side: OrderSide::Sell,
+            status: self.status.clone(),
+            quote_mint: self.quote_mint,
+            amount: self.shares_to_sell,
+            shares_filled: self.shares_sold,
+            quote_filled: self.sol_received,
+            fees_paid: self.fees_paid,
+            average_price_per_share: self.actual_price_per_share,
+            placed_at: self.timestamp,
+            closed_at,
+        }
+    }
+
+    pub fn remaining_shares(&self) -> Result<u64> {
+        self.shares_to_sell
+            .checked_sub(self.shares_sold)
+            .ok_or(error!(StockTradingError::Underflow))
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        matches!(self.expires_at, Some(expires_at) if now >= expires_at)
+    }
+
+    pub fn is_queued(&self, now: i64) -> bool {
+        matches!(self.queued_until, Some(queued_until) if now < queued_until)
+    }
+}
+
+/// Stop-loss or take-profit order. Its SOL or shares are escrowed up front,
+/// and a crank places the actual order once the price feed crosses the
+/// trigger.
+#[account]
+pub struct ConditionalOrder {
+    pub user: Pubkey,
+    pub stock_symbol: String,
+    pub order_id: u64,
+    pub side: OrderSide,
+    /// Lamports escrowed for a buy, shares escrowed for a sell.
+    pub amount: u64,
+    pub trigger_price: u64,
+    pub trigger_direction: TriggerDirection,
+    /// Max price of the buy order, or min price of the sell order, placed
+    /// once triggered.
+    pub limit_price: u64,
+    /// Split ratio of the mint when the order was placed; the trigger and
+    /// limit prices are per share at this ratio.
+    pub split_numerator: u64,
+    pub split_denominator: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl ConditionalOrder {
+    pub const LEN: usize = 32 + (4 + 10) + 8 + 1 + 8 + 8 + 1 + 8 + 8 + 8 + 8 + 1;
+
+    pub fn initialize(
+        &mut self,
+        user: Pubkey,
+        stock_symbol: String,
+        order_id: u64,
+        side: OrderSide,
+        amount: u64,
+        trigger_price: u64,
+        trigger_direction: TriggerDirection,
+        limit_price: u64,
+        stock_mint_info: &StockMintInfo,
+        bump: u8,
+    ) -> Result<()> {
+        self.user = user;
+        self.stock_symbol = stock_symbol;
+        self.order_id = order_id;
+        self.side = side;
+        self.amount = amount;
+        self.trigger_price = trigger_price;
+        self.trigger_direction = trigger_direction;
+        self.limit_price = limit_price;
+        self.split_numerator = stock_mint_info.split_numerator;
+        self.split_denominator = stock_mint_info.split_denominator;
+        self.created_at = Clock::get()?.unix_timestamp;
+        self.bump = bump;
+        Ok(())
+    }
+
+    /// Whether the feed `price`, at the mint's current split ratio, has
+    /// crossed the trigger.
+    pub fn is_triggered(&self, price: u64, stock_mint_info: &StockMintInfo) -> Result<bool> {
+        within_price_limit(
+            price,
+            stock_mint_info,
+            self.trigger_price,
+            (self.split_numerator, self.split_denominator),
+            self.trigger_direction == TriggerDirection::Below,
+        )
+    }
+
+    pub fn placed_event(&self) -> ConditionalOrderPlaced {
+        ConditionalOrderPlaced {
+            order_id: self.order_id,
+            user: self.user,
+            stock_symbol: self.stock_symbol.clone(),
+            side: self.side,
+            amount: self.amount,
+            trigger_price: self.trigger_price,
+            trigger_direction: self.trigger_direction,
+            limit_price: self.limit_price,
+            timestamp: self.created_at,
+        }
+    }
+}
+
+#[account]
+pub struct Distribution {
+    pub stock_symbol: String,
+    /// Holder snapshot time the Merkle tree was built from.
+    pub record_date: i64,
+    /// `None` for SOL dividends, otherwise the allowlisted SPL token they are paid in.
+    pub quote_mint: Option<Pubkey>,
+    pub merkle_root: [u8; 32],
+    pub total_amount: u64,
+    pub claimed_amount: u64,
+    pub claimant_count: u32,
+    /// Claims open after the pool's withdrawal delay from creation.
+    pub claimable_at: i64,
+    pub expires_at: i64,
+    /// Set once the unclaimed amount has been released back to the vault.
+    pub reclaimed: bool,
+    /// Bit `i` is set once leaf `i` has been claimed.
+    pub claimed_bitmap: Vec<u8>,
+    pub bump: u8,
+}
+
+impl Distribution {
+    pub fn bitmap_len(claimant_count: u32) -> usize {
+        (claimant_count as usize).div_ceil(8)
+    }
+
+    pub fn space(claimant_count: u32) -> usize {
+        (4 + 10) + 8 + (1 + 32) + 32 + 8 + 8 + 4 + 8 + 8 + 1 + (4 + Self::bitmap_len(claimant_count)) + 1
+    }
+
+    pub fn is_claimed(&self, index: u32) -> bool {
+        self.claimed_bitmap[index as usize / 8] & (1 << (index % 8)) != 0
+    }
+
+    pub fn set_claimed(&mut self, index: u32) {
+        self.claimed_bitmap[index as usize / 8] |= 1 << (index % 8);
+    }
+
+    pub fn unclaimed_amount(&self) -> Result<u64> {
+        self.total_amount
+            .checked_sub(self.claimed_amount)
+            .ok_or(error!(StockTradingError::Underflow))
+    }
+}
+
+/// Peer-to-peer limit order book of one stock, quoted in SOL. Both sides are
+/// kept sorted best price first and, at equal prices, oldest first.
+#[account]
+pub struct OrderBook {
+    pub stock_symbol: String,
+    pub bids: Vec<BookOrder>,
+    pub asks: Vec<BookOrder>,
+    pub next_sequence: u64,
+    pub bump: u8,
+}
+
+impl OrderBook {
+    /// Resting orders per side.
+    pub const MAX_ORDERS: usize = 32;
+    /// Smallest order, in lamports at its limit price, so that dust cannot
+    /// fill the book.
+    pub const MIN_ORDER_LAMPORTS: u64 = 10_000_000;
+    pub const LEN: usize = (4 + 10) + (4 + BookOrder::LEN * Self::MAX_ORDERS) * 2 + 8 + 1;
+
+    pub fn next_sequence(&mut self) -> Result<u64> {
+        let sequence = self.next_sequence;
+        self.next_sequence = sequence
+            .checked_add(1)
+            .ok_or(StockTradingError::Overflow)?;
+        Ok(sequence)
+    }
+
+    /// Rests an order behind every order at the same or a better price. On a
+    /// full side the worst order makes room if the new one beats its price,
+    /// and is returned so that it can be refunded.
+    pub fn insert(&mut self, side: OrderSide, order: BookOrder) -> Result<Option<BookOrder>> {
+        let orders = match side {
+            OrderSide::Buy => &mut self.bids,
+            OrderSide::Sell => &mut self.asks,
+        };
+        let evicted = if orders.len() < Self::MAX_ORDERS {
+            None
+        } else {
+            require!(
+                orders.last().is_some_and(|worst| {
+                    side.is_better_price(order.price_per_share, worst.price_per_share)
+                }),
+                StockTradingError::OrderBookFull
+            );
+            orders.pop()
+        };
+
+        let position = orders
+            .iter()
+            .position(|resting| side.is_better_price(order.price_per_share, resting.price_per_share))
+            .unwrap_or(orders.len());
+        orders.insert(position, order);
+        Ok(evicted)
+    }
+
+    pub fn remove(&mut self, side: OrderSide, sequence: u64) -> Result<BookOrder> {
+        let orders = match side {
+            OrderSide::Buy => &mut self.bids,
+            OrderSide::Sell => &mut self.asks,
+        };
+        let position = orders
+            .iter()
+            .position(|order| order.sequence == sequence)
+            .ok_or(StockTradingError::BookOrderNotFound)?;
+        Ok(orders.remove(position))
+    }
+
+    /// Whether the best bid pays at least the best ask.
+    pub fn is_crossed(&self) -> bool {
+        match (self.bids.first(), self.asks.first()) {
+            (Some(bid), Some(ask)) => bid.price_per_share >= ask.price_per_share,
+            _ => false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct BookOrder {
+    pub sequence: u64,
+    pub owner: Pubkey,
+    pub price_per_share: u64,
+    pub shares_remaining: u64,
+    /// SOL still escrowed by a bid; always zero for an ask.
+    pub lamports_remaining: u64,
+    pub placed_at: i64,
+}
+
+impl BookOrder {
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 8 + 8;
+}
+
+/// Constant-product pool of one stock against SOL. The shares sit in the
+/// pool's stock vault and the SOL in the trading pool vault, reserved for the
+/// pool; the brokerage only has to hedge the pool's net position.
+#[account]
+pub struct AmmPool {
+    pub stock_symbol: String,
+    pub lp_mint: Pubkey,
+    /// Share base units in the pool.
+    pub stock_reserve: u64,
+    /// Lamports in the pool.
+    pub sol_reserve: u64,
+    pub fee_bps: u16,
+    pub bump: u8,
+}
+
+impl AmmPool {
+    pub const LEN: usize = (4 + 10) + 32 + 8 + 8 + 2 + 1;
+
+    /// SOL to deposit alongside `stock_amount` and the LP tokens minted for
+    /// both. The first deposit takes `max_sol_amount` as given.
+    pub fn deposit_amounts(
+        &self,
+        stock_amount: u64,
+        max_sol_amount: u64,
+        lp_supply: u64,
+    ) -> Result<(u64, u64)> {
+        if lp_supply == 0 {
+            let lp_tokens = isqrt((stock_amount as u128) * (max_sol_amount as u128))?;
+            return Ok((max_sol_amount, lp_tokens));
+        }
+
+        let sol_amount = mul_div(stock_amount, self.sol_reserve, self.stock_reserve, Rounding::Up)?;
+        require!(sol_amount <= max_sol_amount, StockTradingError::SlippageExceeded);
+        let lp_tokens = mul_div(stock_amount, lp_supply, self.stock_reserve, Rounding::Down)?;
+        Ok((sol_amount, lp_tokens))
+    }
+
+    /// Shares and SOL paid out for burning `lp_tokens`.
+    pub fn withdrawal_amounts(&self, lp_tokens: u64, lp_supply: u64) -> Result<(u64, u64)> {
+        Ok((
+            mul_div(lp_tokens, self.stock_reserve, lp_supply, Rounding::Down)?,
+            mul_div(lp_tokens, self.sol_reserve, lp_supply, Rounding::Down)?,
+        ))
+    }
+
+    /// Output of swapping `amount_in` on `side` and the fee taken from it.
+    /// The whole input, fee included, is added to the pool.
+    pub fn swap_amounts(&self, side: OrderSide, amount_in: u64) -> Result<(u64, u64)> {
+        let (reserve_in, reserve_out) = match side {
+            OrderSide::Buy => (self.sol_reserve, self.stock_reserve),
+            OrderSide::Sell => (self.stock_reserve, self.sol_reserve),
+        };
+        let fee_amount = calculate_fee(amount_in, self.fee_bps)?;
+        let amount_in_after_fee = amount_in
+            .checked_sub(fee_amount)
+            .ok_or(StockTradingError::Underflow)?;
+        let amount_out = mul_div(
+            reserve_out,
+            amount_in_after_fee,
+            reserve_in
+                .checked_add(amount_in_after_fee)
+                .ok_or(StockTradingError::Overflow)?,
+            Rounding::Down,
+        )?;
+        Ok((amount_out, fee_amount))
+    }
+}
+
+#[account]
+pub struct Multisig {
+    pub owners: Vec<Pubkey>,
+    pub threshold: u8,
+    pub proposal_count: u64,
+    /// Proposals numbered below this were made for an earlier owner set and
+    /// can no longer be approved or executed.
+    pub min_proposal_id: u64,
+    pub bump: u8,
+}
+
+impl Multisig {
+    /// Approvals are tracked in a `u16` bitmap indexed by owner position.
+    pub const MAX_OWNERS: usize = 16;
+    pub const LEN: usize = (4 + 32 * Self::MAX_OWNERS) + 1 + 8 + 8 + 1;
+
+    pub fn owner_index(&self, key: &Pubkey) -> Result<usize> {
+        self.owners
+            .iter()
+            .position(|owner| owner == key)
+            .ok_or(error!(StockTradingError::NotMultisigOwner))
+    }
+
+    /// Replaces the owner set, which must be unique and reach `threshold`.
+    pub fn set_owners(&mut self, owners: Vec<Pubkey>, threshold: u8) -> Result<()> {
+        require!(
+            !owners.is_empty() && owners.len() <= Self::MAX_OWNERS,
+            StockTradingError::InvalidMultisigOwners
+        );
+        for (i, owner) in owners.iter().enumerate() {
+            require!(
+                !owners[..i].contains(owner),
+                StockTradingError::InvalidMultisigOwners
+            );
+        }
+        require!(
+            threshold > 0 && threshold as usize <= owners.len(),
+            StockTradingError::InvalidMultisigThreshold
+        );
+
+        self.owners = owners;
+        self.threshold = threshold;
+        Ok(())
+    }
+
+    /// Requires a pending proposal for the current owner set with enough
+    /// approvals.
+    pub fn check_executable(&self, proposal: &MultisigProposal) -> Result<()> {
+        require!(!proposal.executed, StockTradingError::ProposalAlreadyExecuted);
+        require!(
+            proposal.proposal_id >= self.min_proposal_id,
+            StockTradingError::StaleProposal
+        );
+        require!(
+            proposal.approval_count() >= self.threshold,
+            StockTradingError::ThresholdNotMet
+        );
+        Ok(())
+    }
+}
+
+#[account]
+pub struct MultisigProposal {
+    pub multisig: Pubkey,
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub action: MultisigAction,
+    pub approvals: u16,
+    pub executed: bool,
+    pub created_at: i64,
+    pub bump: u8,
 }
 
-#[derive(Accounts)]
-pub struct FulfillBuyOrder<'info> {
-    #[account(
-        mut,
-        seeds = [
-            b"buy_order",
-            buy_order.user.as_ref(),
-            buy_order.order_id.to_le_bytes().as_ref()
-        ],
-        bump = buy_order.bump
-    )]
-    pub buy_order: Account<'info, BuyOrder>,
-    
-    #[account(
-        mut,
-        seeds = [b"stock_mint", buy_order.stock_symbol.as_bytes()],
-        bump
-    )]
-    pub stock_mint: Account<'info, Mint>,
-    
-    #[account(
-        mut,
-        seeds = [b"stock_mint_info", buy_order.stock_symbol.as_bytes()],
-        bump = stock_mint_info.bump
-    )]
-    pub stock_mint_info: Account<'info, StockMintInfo>,
-    
-    #[account(
-        init_if_needed,
-        payer = backend_authority,
-        associated_token::mint = stock_mint,
-        associated_token::authority = user
-    )]
-    pub user_stock_token_account: Account<'info, TokenAccount>,
-    
-    #[account(
-        seeds = [b"trading_pool"],
-        bump = trading_pool.bump
-    )]
-    pub trading_pool: Account<'info, TradingPool>,
-    
-    /// CHECK: This is the trading pool vault
-    #[account(
-        mut,
-        seeds = [b"trading_pool_vault"],
-        bump
-    )]
-    pub trading_pool_vault: AccountInfo<'info>,
-    
-    /// CHECK: User account to receive refund
-    #[account(mut)]
-    pub user: AccountInfo<'info>,
-    
-    #[account(mut)]
-    pub backend_authority: Signer<'info>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
+impl MultisigProposal {
+    pub const LEN: usize = 32 + 8 + 32 + MultisigAction::LEN + 2 + 1 + 8 + 1;
+
+    pub fn approval_count(&self) -> u8 {
+        self.approvals.count_ones() as u8
+    }
 }
 
-#[derive(Accounts)]
-#[instruction(stock_symbol: String)]
-pub struct PlaceSellOrder<'info> {
-    #[account(
-        init,
-        payer = user,
-        space = 8 + SellOrder::LEN,
-        seeds = [
-            b"sell_order",
-            user.key().as_ref(),
-            trading_pool.total_orders.to_le_bytes().as_ref()
-        ],
-        bump
-    )]
-    pub sell_order: Account<'info, SellOrder>,
-    
-    #[account(
-        seeds = [b"stock_mint", stock_symbol.as_bytes()],
-        bump
-    )]
-    pub stock_mint: Account<'info, Mint>,
-    
-    #[account(
-        mut,
-        associated_token::mint = stock_mint,
-        associated_token::authority = user
-    )]
-    pub user_stock_token_account: Account<'info, TokenAccount>,
-    
-    #[account(
-        init_if_needed,
-        payer = user,
-        token::mint = stock_mint,
-        token::authority = trading_pool,
-        seeds = [b"escrow", stock_mint.key().as_ref()],
-        bump
-    )]
-    pub escrow_token_account: Account<'info, TokenAccount>,
-    
-    #[account(
-        mut,
-        seeds = [b"trading_pool"],
-        bump = trading_pool.bump
-    )]
-    pub trading_pool: Account<'info, TradingPool>,
-    
-    #[account(mut)]
-    pub user: Signer<'info>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum MultisigAction {
+    WithdrawVaultFunds {
+        amount: u64,
+        quote_mint: Option<Pubkey>,
+        destination: Pubkey,
+    },
+    UpdateAuthorities {
+        new_vault_authority: Option<Pubkey>,
+        new_backend_authority: Option<Pubkey>,
+    },
+    CancelVaultWithdrawal,
+    CreateDistribution {
+        params: DistributionParams,
+    },
+    SetOracleConfig {
+        oracle_authority: Option<Pubkey>,
+        max_price_deviation_bps: Option<u16>,
+        max_price_staleness: Option<i64>,
+        max_attestation_staleness: Option<i64>,
+        reserve_attestor: Option<Pubkey>,
+    },
+    SetFeeConfig {
+        fee_bps: Option<u16>,
+        fee_authority: Option<Pubkey>,
+    },
+    SetComplianceConfig {
+        compliance_authority: Option<Pubkey>,
+        enforce_eligibility: Option<bool>,
+    },
+    SetOwners {
+        owners: Vec<Pubkey>,
+        threshold: u8,
+    },
 }
 
-#[derive(Accounts)]
-pub struct FulfillSellOrder<'info> {
-    #[account(
-        mut,
-        seeds = [
-            b"sell_order",
-            sell_order.user.as_ref(),
-            sell_order.order_id.to_le_bytes().as_ref()
-        ],
-        bump = sell_order.bump
-    )]
-    pub sell_order: Account<'info, SellOrder>,
-    
-    #[account(
-        mut,
-        seeds = [b"stock_mint", sell_order.stock_symbol.as_bytes()],
-        bump
-    )]
-    pub stock_mint: Account<'info, Mint>,
-    
-    #[account(
-        mut,
-        seeds = [b"stock_mint_info", sell_order.stock_symbol.as_bytes()],
-        bump = stock_mint_info.bump
-    )]
-    pub stock_mint_info: Account<'info, StockMintInfo>,
-    
-    #[account(
-        mut,
-        associated_token::mint = stock_mint,
-        associated_token::authority = sell_order.user
-    )]
-    pub user_stock_token_account: Account<'info, TokenAccount>,
-    
-    #[account(
-        mut,
-        token::mint = stock_mint,
-        token::authority = trading_pool,
-        seeds = [b"escrow", stock_mint.key().as_ref()],
-        bump
-    )]
-    pub escrow_token_account: Account<'info, TokenAccount>,
-    
-    #[account(
-        seeds = [b"trading_pool"],
-        bump = trading_pool.bump
-    )]
-    pub trading_pool: Account<'info, TradingPool>,
-    
-    /// CHECK: This is the trading pool vault
-    #[account(
-        mut,
-        seeds = [b"trading_pool_vault"],
-        bump
-    )]
-    pub trading_pool_vault: AccountInfo<'info>,
-    
-    /// CHECK: User account to receive SOL
-    #[account(mut)]
-    pub user: AccountInfo<'info>,
-    
-    #[account(mut)]
-    pub backend_authority: Signer<'info>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
+impl MultisigAction {
+    /// Sized for the largest variant, `SetOwners`.
+    pub const LEN: usize = 1 + (4 + 32 * Multisig::MAX_OWNERS) + 1;
 }
 
-#[derive(Accounts)]
-pub struct WithdrawVaultFunds<'info> {
-    #[account(
-        seeds = [b"trading_pool"],
-        bump = trading_pool.bump,
-        has_one = vault_authority
-    )]
-    pub trading_pool: Account<'info, TradingPool>,
-    
-    /// CHECK: This is the trading pool vault
-    #[account(
-        mut,
-        seeds = [b"trading_pool_vault"],
-        bump
-    )]
-    pub trading_pool_vault: AccountInfo<'info>,
-    
-    #[account(mut)]
-    pub vault_authority: Signer<'info>,
-    pub system_program: Program<'info, System>,
+/// Dividend published by `create_distribution`, or proposed to a multisig.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct DistributionParams {
+    pub stock_symbol: String,
+    pub record_date: i64,
+    pub merkle_root: [u8; 32],
+    pub total_amount: u64,
+    pub claimant_count: u32,
+    pub expires_at: i64,
+    pub quote_mint: Option<Pubkey>,
 }
 
-#[derive(Accounts)]
-pub struct DepositVaultFunds<'info> {
-    #[account(
-        seeds = [b"trading_pool"],
-        bump = trading_pool.bump,
-        has_one = vault_authority
-    )]
-    pub trading_pool: Account<'info, TradingPool>,
-    
-    /// CHECK: This is the trading pool vault
-    #[account(
-        mut,
-        seeds = [b"trading_pool_vault"],
-        bump
-    )]
-    pub trading_pool_vault: AccountInfo<'info>,
-    
-    #[account(mut)]
-    pub vault_authority: Signer<'info>,
-    pub system_program: Program<'info, System>,
+impl DistributionParams {
+    pub const LEN: usize = (4 + 10) + 8 + 32 + 8 + 4 + 8 + (1 + 32);
 }
 
-#[derive(Accounts)]
-pub struct UpdateAuthorities<'info> {
-    #[account(
-        mut,
-        seeds = [b"trading_pool"],
-        bump = trading_pool.bump,
-        has_one = vault_authority @ StockTradingError::UnauthorizedVaultAccess
-    )]
-    pub trading_pool: Account<'info, TradingPool>,
-    
-    pub current_vault_authority: Signer<'info>,
-    /// CHECK: Alias for current_vault_authority to satisfy has_one constraint
-    pub vault_authority: UncheckedAccount<'info>,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum OrderStatus {
+    Pending,
+    PartiallyFilled,
+    Fulfilled,
+    Cancelled,
+    /// Only reported in receipts of orders reclaimed after expiring.
+    Expired,
+}
+
+/// What happens to orders placed outside market hours.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum OffHoursPolicy {
+    Reject,
+    Queue,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum EligibilityStatus {
+    Approved,
+    Revoked,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TradingStatus {
+    Active,
+    Halted,
+    Delisted,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+impl OrderSide {
+    /// Whether `price` is strictly better than `other` for an order on this
+    /// side: higher for a bid, lower for an ask.
+    pub fn is_better_price(&self, price: u64, other: u64) -> bool {
+        match self {
+            OrderSide::Buy => price > other,
+            OrderSide::Sell => price < other,
+        }
+    }
+}
+
+/// Side of the trigger price the feed has to reach for a conditional order
+/// to fire.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerDirection {
+    /// Fires once the price is at or above the trigger.
+    Above,
+    /// Fires once the price is at or below the trigger.
+    Below,
+}
+
+// Events
+#[event]
+pub struct StockMintCreated {
+    pub stock_symbol: String,
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub name: String,
+    pub uri: String,
+    pub token_program: Pubkey,
+    pub transfer_hook_program: Option<Pubkey>,
+}
+
+#[event]
+pub struct StockMetadataUpdated {
+    pub stock_symbol: String,
+    pub mint: Pubkey,
+    pub name: String,
+    pub uri: String,
+    pub updated_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StockSplitApplied {
+    pub stock_symbol: String,
+    pub mint: Pubkey,
+    pub numerator: u64,
+    pub denominator: u64,
+    pub split_numerator: u64,
+    pub split_denominator: u64,
+    pub total_supply: u64,
+    pub total_shares: u64,
+    pub applied_by: Pubkey,
+    pub timestamp: i64,
 }
 
-// Account structs
-#[account]
-pub struct TradingPool {
-    pub vault_authority: Pubkey,
-    pub backend_authority: Pubkey,
-    pub total_orders: u64,
-    pub bump: u8,
+#[event]
+pub struct PriceFeedUpdated {
+    pub stock_symbol: String,
+    pub quote_mint: Option<Pubkey>,
+    pub price: u64,
+    pub confidence: u64,
+    pub publish_time: i64,
 }
 
-impl TradingPool {
-    pub const LEN: usize = 32 + 32 + 8 + 1;
+#[event]
+pub struct OracleConfigUpdated {
+    pub oracle_authority: Pubkey,
+    pub max_price_deviation_bps: u16,
+    pub max_price_staleness: i64,
+    pub max_attestation_staleness: i64,
+    pub reserve_attestor: Pubkey,
+    pub timestamp: i64,
 }
 
-#[account]
-pub struct StockMintInfo {
+#[event]
+pub struct ReservesAttested {
     pub stock_symbol: String,
-    pub mint: Pubkey,
-    pub total_supply: u64,
-    pub bump: u8,
+    pub attested_shares: u64,
+    pub outstanding_shares: u64,
+    pub attested_at: i64,
+    pub signature: [u8; 64],
+    pub attested_by: Pubkey,
+    pub timestamp: i64,
 }
 
-impl StockMintInfo {
-    pub const LEN: usize = (4 + 10) + 32 + 8 + 1;
+#[event]
+pub struct TradingPauseUpdated {
+    pub paused: bool,
+    pub stock_symbol: Option<String>,
+    pub trading_status: Option<TradingStatus>,
+    pub timestamp: i64,
 }
 
-#[account]
-pub struct BuyOrder {
-    pub user: Pubkey,
+#[event]
+pub struct FeeConfigUpdated {
+    pub fee_bps: u16,
+    pub fee_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StockFeeOverrideUpdated {
     pub stock_symbol: String,
-    pub sol_amount: u64,
-    pub max_price_per_share: u64,
-    pub order_id: u64,
-    pub status: OrderStatus,
+    pub fee_bps_override: Option<u16>,
     pub timestamp: i64,
-    pub shares_received: u64,
-    pub actual_price_per_share: u64,
-    pub bump: u8,
 }
 
-impl BuyOrder {
-    pub const LEN: usize = 32 + (4 + 10) + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 1;
+#[event]
+pub struct TradingScheduleUpdated {
+    pub utc_offset: i32,
+    pub sessions: [SessionWindow; 7],
+    pub holidays: Vec<i32>,
+    pub early_closes: Vec<EarlyClose>,
+    pub off_hours_policy: OffHoursPolicy,
+    pub updated_by: Pubkey,
+    pub timestamp: i64,
 }
 
-#[account]
-pub struct SellOrder {
+#[event]
+pub struct ComplianceConfigUpdated {
+    pub compliance_authority: Pubkey,
+    pub enforce_eligibility: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EligibilityGranted {
     pub user: Pubkey,
-    pub stock_symbol: String,
-    pub shares_to_sell: u64,
-    pub min_price_per_share: u64,
-    pub order_id: u64,
-    pub status: OrderStatus,
+    pub jurisdiction: [u8; 2],
+    pub expires_at: Option<i64>,
+    pub granted_by: Pubkey,
     pub timestamp: i64,
-    pub sol_received: u64,
-    pub actual_price_per_share: u64,
-    pub bump: u8,
 }
 
-impl SellOrder {
-    pub const LEN: usize = 32 + (4 + 10) + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 1;
+#[event]
+pub struct EligibilityRevoked {
+    pub user: Pubkey,
+    pub revoked_by: Pubkey,
+    pub timestamp: i64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
-pub enum OrderStatus {
-    Pending,
-    Fulfilled,
-    Cancelled,
+#[event]
+pub struct FeesWithdrawn {
+    pub authority: Pubkey,
+    pub quote_mint: Option<Pubkey>,
+    pub amount: u64,
+    pub timestamp: i64,
 }
 
-// Events
 #[event]
-pub struct StockMintCreated {
-    pub stock_symbol: String,
+pub struct QuoteMintUpdated {
     pub mint: Pubkey,
-    pub decimals: u8,
+    pub enabled: bool,
+    pub timestamp: i64,
 }
 
 #[event]
@@ -854,8 +9022,11 @@ pub struct BuyOrderPlaced {
     pub order_id: u64,
     pub user: Pubkey,
     pub stock_symbol: String,
+    pub quote_mint: Option<Pubkey>,
     pub sol_amount: u64,
     pub max_price_per_share: u64,
+    pub expires_at: Option<i64>,
+    pub queued_until: Option<i64>,
     pub timestamp: i64,
 }
 
@@ -868,6 +9039,23 @@ pub struct BuyOrderFulfilled {
     pub price_per_share: u64,
     pub total_cost: u64,
     pub refund_amount: u64,
+    pub fee_amount: u64,
+    pub total_shares_received: u64,
+    pub average_price_per_share: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BuyOrderPartiallyFilled {
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub stock_symbol: String,
+    pub shares_purchased: u64,
+    pub price_per_share: u64,
+    pub total_cost: u64,
+    pub fee_amount: u64,
+    pub total_shares_received: u64,
+    pub total_sol_spent: u64,
     pub timestamp: i64,
 }
 
@@ -876,8 +9064,11 @@ pub struct SellOrderPlaced {
     pub order_id: u64,
     pub user: Pubkey,
     pub stock_symbol: String,
+    pub quote_mint: Option<Pubkey>,
     pub shares_to_sell: u64,
     pub min_price_per_share: u64,
+    pub expires_at: Option<i64>,
+    pub queued_until: Option<i64>,
     pub timestamp: i64,
 }
 
@@ -890,13 +9081,278 @@ pub struct SellOrderFulfilled {
     pub price_per_share: u64,
     pub total_proceeds: u64,
     pub shares_returned: u64,
+    pub fee_amount: u64,
+    pub total_shares_sold: u64,
+    pub total_sol_received: u64,
+    pub average_price_per_share: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SellOrderPartiallyFilled {
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub stock_symbol: String,
+    pub shares_sold: u64,
+    pub price_per_share: u64,
+    pub total_proceeds: u64,
+    pub fee_amount: u64,
+    pub total_shares_sold: u64,
+    pub total_sol_received: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BuyOrderCancelled {
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub stock_symbol: String,
+    pub refund_amount: u64,
+    pub cancelled_by: Pubkey,
+    pub reason_code: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SellOrderCancelled {
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub stock_symbol: String,
+    pub shares_returned: u64,
+    pub cancelled_by: Pubkey,
+    pub reason_code: u16,
+    pub timestamp: i64,
+}
+
+/// Compact record of a terminal order. Order accounts are closed once
+/// terminal, so this is the only history left on chain.
+#[event]
+pub struct OrderReceipt {
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub stock_symbol: String,
+    pub side: OrderSide,
+    pub status: OrderStatus,
+    pub quote_mint: Option<Pubkey>,
+    /// Quote amount escrowed by a buy, or shares escrowed by a sell.
+    pub amount: u64,
+    pub shares_filled: u64,
+    pub quote_filled: u64,
+    pub fees_paid: u64,
+    pub average_price_per_share: u64,
+    pub placed_at: i64,
+    pub closed_at: i64,
+}
+
+#[event]
+pub struct DistributionCreated {
+    pub stock_symbol: String,
+    pub record_date: i64,
+    pub quote_mint: Option<Pubkey>,
+    pub merkle_root: [u8; 32],
+    pub total_amount: u64,
+    pub claimant_count: u32,
+    pub claimable_at: i64,
+    pub expires_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DividendClaimed {
+    pub stock_symbol: String,
+    pub record_date: i64,
+    pub quote_mint: Option<Pubkey>,
+    pub holder: Pubkey,
+    pub index: u32,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UnclaimedDividendsReclaimed {
+    pub stock_symbol: String,
+    pub record_date: i64,
+    pub quote_mint: Option<Pubkey>,
+    pub amount_returned: u64,
+    pub reclaimed_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderBookCreated {
+    pub stock_symbol: String,
+    pub mint: Pubkey,
+    pub book_escrow: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BookOrderPlaced {
+    pub stock_symbol: String,
+    pub side: OrderSide,
+    pub sequence: u64,
+    pub owner: Pubkey,
+    pub price_per_share: u64,
+    pub shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BookOrderCancelled {
+    pub stock_symbol: String,
+    pub side: OrderSide,
+    pub sequence: u64,
+    pub owner: Pubkey,
+    pub shares_remaining: u64,
+    /// The owner, or whoever evicted or force-cancelled the order.
+    pub cancelled_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BookOrderRouted {
+    pub stock_symbol: String,
+    pub side: OrderSide,
+    pub sequence: u64,
+    pub owner: Pubkey,
+    /// Brokerage order that took over the rest of the book order.
+    pub order_id: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TradeMatched {
+    pub stock_symbol: String,
+    pub bid_sequence: u64,
+    pub ask_sequence: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub shares: u64,
+    pub price_per_share: u64,
+    pub total_cost: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AmmPoolCreated {
+    pub stock_symbol: String,
+    pub lp_mint: Pubkey,
+    pub stock_vault: Pubkey,
+    pub fee_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidityDeposited {
+    pub stock_symbol: String,
+    pub provider: Pubkey,
+    pub stock_amount: u64,
+    pub sol_amount: u64,
+    pub lp_tokens: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidityWithdrawn {
+    pub stock_symbol: String,
+    pub provider: Pubkey,
+    pub stock_amount: u64,
+    pub sol_amount: u64,
+    pub lp_tokens: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AmmSwapped {
+    pub stock_symbol: String,
+    pub user: Pubkey,
+    pub side: OrderSide,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee_amount: u64,
+    pub stock_reserve: u64,
+    pub sol_reserve: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ConditionalOrderPlaced {
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub stock_symbol: String,
+    pub side: OrderSide,
+    pub amount: u64,
+    pub trigger_price: u64,
+    pub trigger_direction: TriggerDirection,
+    pub limit_price: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ConditionalOrderTriggered {
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub stock_symbol: String,
+    pub side: OrderSide,
+    pub trigger_price: u64,
+    /// Feed price the trigger fired at.
+    pub price: u64,
+    /// Order placed for the escrowed SOL or shares.
+    pub triggered_order_id: u64,
+    pub triggered_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ConditionalOrderCancelled {
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub stock_symbol: String,
+    pub side: OrderSide,
+    pub amount_returned: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ExpiredOrderReclaimed {
+    pub order_id: u64,
+    pub user: Pubkey,
+    pub stock_symbol: String,
+    pub side: OrderSide,
+    pub amount_returned: u64,
+    pub reclaimed_by: Pubkey,
     pub timestamp: i64,
 }
 
 #[event]
 pub struct VaultFundsWithdrawn {
     pub authority: Pubkey,
+    pub quote_mint: Option<Pubkey>,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultWithdrawalQueued {
+    pub amount: u64,
+    pub quote_mint: Option<Pubkey>,
+    pub destination: Pubkey,
+    pub queued_by: Pubkey,
+    pub executable_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultWithdrawalCancelled {
     pub amount: u64,
+    pub destination: Pubkey,
+    pub cancelled_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalDelayUpdated {
+    pub withdrawal_delay: i64,
     pub timestamp: i64,
 }
 
@@ -907,6 +9363,23 @@ pub struct VaultFundsDeposited {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct VaultBalancesReported {
+    pub total_lamports: u64,
+    pub reserved_lamports: u64,
+    pub rent_exempt_minimum: u64,
+    pub free_lamports: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityTransferProposed {
+    pub pending_vault_authority: Option<Pubkey>,
+    pub pending_backend_authority: Option<Pubkey>,
+    pub proposed_by: Pubkey,
+    pub timestamp: i64,
+}
+
 #[event]
 pub struct AuthoritiesUpdated {
     pub vault_authority: Pubkey,
@@ -914,6 +9387,49 @@ pub struct AuthoritiesUpdated {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct MultisigCreated {
+    pub multisig: Pubkey,
+    pub owners: Vec<Pubkey>,
+    pub threshold: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MultisigOwnersUpdated {
+    pub multisig: Pubkey,
+    pub owners: Vec<Pubkey>,
+    pub threshold: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MultisigProposalCreated {
+    pub multisig: Pubkey,
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub action: MultisigAction,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MultisigProposalApproved {
+    pub multisig: Pubkey,
+    pub proposal_id: u64,
+    pub owner: Pubkey,
+    pub approvals: u8,
+    pub threshold: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MultisigProposalExecuted {
+    pub multisig: Pubkey,
+    pub proposal_id: u64,
+    pub executor: Pubkey,
+    pub timestamp: i64,
+}
+
 #[error_code]
 pub enum StockTradingError {
     #[msg("Stock symbol too long")]
@@ -938,4 +9454,152 @@ pub enum StockTradingError {
     Overflow,
     #[msg("Arithmetic underflow")]
     Underflow,
+    #[msg("Unauthorized user")]
+    UnauthorizedUser,
+    #[msg("Invalid reason code")]
+    InvalidReasonCode,
+    #[msg("Order expiry must be in the future")]
+    InvalidExpiry,
+    #[msg("Order has expired")]
+    OrderExpired,
+    #[msg("Order has not expired")]
+    OrderNotExpired,
+    #[msg("Unauthorized oracle access")]
+    UnauthorizedOracle,
+    #[msg("Invalid price publish time")]
+    InvalidPublishTime,
+    #[msg("Invalid oracle configuration")]
+    InvalidOracleConfig,
+    #[msg("Price feed is stale")]
+    StalePriceFeed,
+    #[msg("Price feed confidence interval too wide")]
+    PriceConfidenceTooWide,
+    #[msg("Price deviates too far from price feed")]
+    PriceDeviationTooLarge,
+    #[msg("Trading is paused")]
+    TradingPaused,
+    #[msg("Stock is not open for trading")]
+    SymbolNotTradable,
+    #[msg("Stock mint info account required")]
+    MissingStockMintInfo,
+    #[msg("Fee exceeds maximum")]
+    FeeTooHigh,
+    #[msg("Unauthorized fee access")]
+    UnauthorizedFeeAccess,
+    #[msg("Insufficient fees in fee vault")]
+    InsufficientFees,
+    #[msg("Action requires a multisig proposal")]
+    MultisigRequired,
+    #[msg("Invalid multisig owners")]
+    InvalidMultisigOwners,
+    #[msg("Invalid multisig threshold")]
+    InvalidMultisigThreshold,
+    #[msg("Signer is not a multisig owner")]
+    NotMultisigOwner,
+    #[msg("Proposal already executed")]
+    ProposalAlreadyExecuted,
+    #[msg("Proposal already approved by this owner")]
+    ProposalAlreadyApproved,
+    #[msg("Approval threshold not met")]
+    ThresholdNotMet,
+    #[msg("Invalid destination account")]
+    InvalidDestination,
+    #[msg("No pending authority transfer for signer")]
+    NoPendingAuthority,
+    #[msg("Invalid withdrawal delay")]
+    InvalidWithdrawalDelay,
+    #[msg("A vault withdrawal is already queued")]
+    WithdrawalAlreadyQueued,
+    #[msg("No vault withdrawal is queued")]
+    NoPendingWithdrawal,
+    #[msg("Withdrawal delay has not passed")]
+    WithdrawalTimelockActive,
+    #[msg("Insufficient free balance in vault")]
+    InsufficientVaultFunds,
+    #[msg("Amount does not match shares times price")]
+    NotionalMismatch,
+    #[msg("Quote mint is not enabled")]
+    QuoteMintDisabled,
+    #[msg("Quote token accounts required")]
+    MissingQuoteAccounts,
+    #[msg("Token account does not match the quote mint")]
+    InvalidQuoteMint,
+    #[msg("Stock mint decimals exceed the maximum")]
+    InvalidDecimals,
+    #[msg("Stock name too long")]
+    StockNameTooLong,
+    #[msg("Metadata URI too long")]
+    MetadataUriTooLong,
+    #[msg("Split ratio must be nonzero and not 1:1")]
+    InvalidSplitRatio,
+    #[msg("Trading must be halted for this symbol")]
+    SymbolNotHalted,
+    #[msg("Invalid number of distribution claimants")]
+    InvalidClaimantCount,
+    #[msg("Record date is in the future")]
+    InvalidRecordDate,
+    #[msg("Distribution has expired")]
+    DistributionExpired,
+    #[msg("Distribution has not expired yet")]
+    DistributionNotExpired,
+    #[msg("Dividend already claimed")]
+    DividendAlreadyClaimed,
+    #[msg("Invalid Merkle proof")]
+    InvalidMerkleProof,
+    #[msg("Batch fills do not match the accounts supplied")]
+    InvalidBatch,
+    #[msg("Order does not belong to the batch market")]
+    BatchMarketMismatch,
+    #[msg("Account does not belong to the batched order")]
+    InvalidBatchAccount,
+    #[msg("Order book side is full")]
+    OrderBookFull,
+    #[msg("Book order not found")]
+    BookOrderNotFound,
+    #[msg("Account does not belong to the matched orders")]
+    InvalidMatchAccount,
+    #[msg("No crossing orders to match")]
+    NoCrossingOrders,
+    #[msg("Order book must be empty")]
+    OrderBookNotEmpty,
+    #[msg("Slippage tolerance exceeded")]
+    SlippageExceeded,
+    #[msg("Invalid reserve attestation time")]
+    InvalidAttestationTime,
+    #[msg("Reserve attestation is stale")]
+    StaleReserveAttestation,
+    #[msg("Minting would exceed attested reserves")]
+    InsufficientReserves,
+    #[msg("Unauthorized compliance access")]
+    UnauthorizedCompliance,
+    #[msg("Invalid jurisdiction code")]
+    InvalidJurisdiction,
+    #[msg("User is not eligible to trade")]
+    UserNotEligible,
+    #[msg("Invalid trading schedule")]
+    InvalidTradingSchedule,
+    #[msg("Market is closed")]
+    MarketClosed,
+    #[msg("Order is queued for the next market open")]
+    OrderQueued,
+    #[msg("Invalid trigger price")]
+    InvalidTriggerPrice,
+    #[msg("Trigger price has not been reached")]
+    TriggerNotReached,
+    #[msg("Order is on the other side")]
+    InvalidOrderSide,
+    #[msg("Proposal action cannot be executed by this instruction")]
+    InvalidProposalAction,
+    #[msg("Distribution is not claimable yet")]
+    DistributionNotClaimable,
+    #[msg("Proposal was made for a previous multisig owner set")]
+    StaleProposal,
+    #[msg("Reserve attestation is not signed by the reserve attestor")]
+    InvalidAttestationSignature,
+    #[msg("Unclaimed dividends of this distribution were already reclaimed")]
+    DistributionReclaimed,
+    #[msg("Book order is below the minimum order size")]
+    BookOrderTooSmall,
+    #[msg("Refund account does not belong to the owner of the book order")]
+    InvalidRefundAccount,
 }
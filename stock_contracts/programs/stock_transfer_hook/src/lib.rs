@@ -0,0 +1,221 @@
+// Code generated by `#[program]` still calls the deprecated `AccountInfo::realloc`.
+#![allow(deprecated)]
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount};
+use spl_discriminator::SplDiscriminate;
+use spl_tlv_account_resolution::{
+    account::ExtraAccountMeta, seeds::Seed, state::ExtraAccountMetaList,
+};
+use spl_transfer_hook_interface::instruction::ExecuteInstruction;
+use stock_contracts::program::StockContracts;
+use stock_contracts::{TradingPool, UserEligibility};
+
+declare_id!("AwEnmUAjLfYthrxvMbyTXbLhnFq5jgndZUttcQi419Uz");
+
+#[program]
+pub mod stock_transfer_hook {
+    use super::*;
+
+    /// Sets the authority that manages the hook. Only the program's upgrade
+    /// authority may initialize it, so nobody can claim the config first.
+    pub fn initialize_hook_config(
+        ctx: Context<InitializeHookConfig>,
+        authority: Pubkey,
+    ) -> Result<()> {
+        let hook_config = &mut ctx.accounts.hook_config;
+        hook_config.authority = authority;
+        hook_config.bump = ctx.bumps.hook_config;
+
+        Ok(())
+    }
+
+    /// Registers the accounts Token-2022 must pass to `transfer_hook` for a
+    /// stock mint: the trading program, its trading pool and the eligibility
+    /// grant it keeps for the destination account's owner.
+    pub fn initialize_extra_account_meta_list(
+        ctx: Context<InitializeExtraAccountMetaList>,
+    ) -> Result<()> {
+        let extra_account_metas = extra_account_metas()?;
+        let mut data = ctx.accounts.extra_account_meta_list.try_borrow_mut_data()?;
+        ExtraAccountMetaList::init::<ExecuteInstruction>(&mut data, &extra_account_metas)?;
+
+        Ok(())
+    }
+
+    /// Invoked by Token-2022 on every transfer of a hooked stock mint. While
+    /// the trading pool enforces eligibility, the destination owner needs the
+    /// same current grant the trading program requires for orders.
+    #[instruction(discriminator = ExecuteInstruction::SPL_DISCRIMINATOR_SLICE)]
+    pub fn transfer_hook(ctx: Context<TransferHook>, _amount: u64) -> Result<()> {
+        let trading_pool = &ctx.accounts.trading_pool;
+        let destination_owner = ctx.accounts.destination_token.owner;
+
+        // Order escrows, the book escrow and the AMM vault all belong to the
+        // trading pool, which never needs a grant of its own
+        if !trading_pool.enforce_eligibility || destination_owner == trading_pool.key() {
+            return Ok(());
+        }
+
+        // A missing or foreign account counts as no grant
+        let destination_eligibility = &ctx.accounts.destination_eligibility;
+        require!(
+            destination_eligibility.owner == &stock_contracts::ID,
+            TransferHookError::HolderNotEligible
+        );
+        let eligibility = UserEligibility::try_deserialize(
+            &mut &destination_eligibility.try_borrow_data()?[..],
+        )
+        .map_err(|_| TransferHookError::HolderNotEligible)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            eligibility.user == destination_owner && eligibility.is_eligible(now),
+            TransferHookError::HolderNotEligible
+        );
+
+        Ok(())
+    }
+}
+
+/// The trading program at index 5, then its trading pool and the destination
+/// owner's `UserEligibility` PDA under it, the latter derived from the owner
+/// field (bytes 32..64) of the destination token account at index 2.
+fn extra_account_metas() -> Result<Vec<ExtraAccountMeta>> {
+    Ok(vec![
+        ExtraAccountMeta::new_with_pubkey(&stock_contracts::ID, false, false)?,
+        ExtraAccountMeta::new_external_pda_with_seeds(
+            5,
+            &[Seed::Literal {
+                bytes: b"trading_pool".to_vec(),
+            }],
+            false,
+            false,
+        )?,
+        ExtraAccountMeta::new_external_pda_with_seeds(
+            5,
+            &[
+                Seed::Literal {
+                    bytes: b"user_eligibility".to_vec(),
+                },
+                Seed::AccountData {
+                    account_index: 2,
+                    data_index: 32,
+                    length: 32,
+                },
+            ],
+            false,
+            false,
+        )?,
+    ])
+}
+
+#[derive(Accounts)]
+pub struct InitializeHookConfig<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + HookConfig::LEN,
+        seeds = [b"hook_config"],
+        bump
+    )]
+    pub hook_config: Account<'info, HookConfig>,
+    
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::StockTransferHook>,
+    
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(payer.key())
+            @ TransferHookError::UnauthorizedAuthority
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeExtraAccountMetaList<'info> {
+    /// CHECK: Validation account read by Token-2022, initialized here
+    #[account(
+        init,
+        payer = authority,
+        space = ExtraAccountMetaList::size_of(extra_account_metas()?.len())?,
+        seeds = [b"extra-account-metas", mint.key().as_ref()],
+        bump
+    )]
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+    
+    pub mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        seeds = [b"hook_config"],
+        bump = hook_config.bump,
+        has_one = authority @ TransferHookError::UnauthorizedAuthority
+    )]
+    pub hook_config: Account<'info, HookConfig>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Account order is fixed by the transfer hook interface; extra accounts
+/// follow in the order of the validation account.
+#[derive(Accounts)]
+pub struct TransferHook<'info> {
+    #[account(token::mint = mint)]
+    pub source_token: InterfaceAccount<'info, TokenAccount>,
+    
+    pub mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(token::mint = mint)]
+    pub destination_token: InterfaceAccount<'info, TokenAccount>,
+    
+    /// CHECK: Source owner or delegate, already verified by Token-2022
+    pub owner: UncheckedAccount<'info>,
+    
+    /// CHECK: Validation account for the mint
+    #[account(
+        seeds = [b"extra-account-metas", mint.key().as_ref()],
+        bump
+    )]
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+    
+    pub stock_contracts_program: Program<'info, StockContracts>,
+    
+    #[account(
+        seeds = [b"trading_pool"],
+        seeds::program = stock_contracts_program.key(),
+        bump = trading_pool.bump
+    )]
+    pub trading_pool: Account<'info, TradingPool>,
+    
+    /// CHECK: Grant kept by the trading program for the destination owner,
+    /// read in `transfer_hook` only when the owner needs one
+    #[account(
+        seeds = [b"user_eligibility", destination_token.owner.as_ref()],
+        seeds::program = stock_contracts_program.key(),
+        bump
+    )]
+    pub destination_eligibility: UncheckedAccount<'info>,
+}
+
+#[account]
+pub struct HookConfig {
+    pub authority: Pubkey,
+    pub bump: u8,
+}
+
+impl HookConfig {
+    pub const LEN: usize = 32 + 1;
+}
+
+#[error_code]
+pub enum TransferHookError {
+    #[msg("Unauthorized hook authority")]
+    UnauthorizedAuthority,
+    #[msg("Destination holder is not eligible to hold this stock")]
+    HolderNotEligible,
+}
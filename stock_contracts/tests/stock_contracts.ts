@@ -1,15 +1,34 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { StockContracts } from "../target/types/stock_contracts";
-import { PublicKey, Keypair, SystemProgram, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { StockTransferHook } from "../target/types/stock_transfer_hook";
+import {
+  PublicKey,
+  Keypair,
+  SystemProgram,
+  LAMPORTS_PER_SOL,
+  SYSVAR_RENT_PUBKEY,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+  Ed25519Program,
+  Transaction,
+  sendAndConfirmTransaction
+} from "@solana/web3.js";
 import { 
   TOKEN_PROGRAM_ID, 
+  TOKEN_2022_PROGRAM_ID,
   ASSOCIATED_TOKEN_PROGRAM_ID,
   getAssociatedTokenAddress,
+  getAssociatedTokenAddressSync,
+  createAssociatedTokenAccountIdempotent,
+  createTransferCheckedWithTransferHookInstruction,
   getAccount,
-  getMint
+  getMint,
+  createMint,
+  getOrCreateAssociatedTokenAccount,
+  mintTo
 } from "@solana/spl-token";
 import { assert } from "chai";
+import { createHash } from "crypto";
 
 describe("stock_contracts", () => {
   // Configure the client to use the local cluster.
@@ -17,16 +36,24 @@ describe("stock_contracts", () => {
   anchor.setProvider(provider);
 
   const program = anchor.workspace.StockContracts as Program<StockContracts>;
+  const hookProgram = anchor.workspace.StockTransferHook as Program<StockTransferHook>;
   
   // Test accounts
   const vaultAuthority = Keypair.generate();
   const backendAuthority = Keypair.generate();
   const user1 = Keypair.generate();
   const user2 = Keypair.generate();
+  const reserveAttestor = Keypair.generate();
+  
+  // Authorities installed by the "Update authorities" test
+  const rotatedVaultAuthority = Keypair.generate();
+  const rotatedBackendAuthority = Keypair.generate();
   
   // PDAs
   let tradingPoolPDA: PublicKey;
   let tradingPoolVaultPDA: PublicKey;
+  let feeVaultPDA: PublicKey;
+  let tradingSchedulePDA: PublicKey;
   let tradingPoolBump: number;
   let tradingPoolVaultBump: number;
   
@@ -36,6 +63,308 @@ describe("stock_contracts", () => {
   let stockMintInfoPDA: PublicKey;
   let stockMintBump: number;
   let stockMintInfoBump: number;
+  let priceFeedPDA: PublicKey;
+  let lastPublishTime = 0;
+  let lastAttestedAt = 0;
+  // Attested reserves that cover any supply the tests mint
+  const fullyBacked = new anchor.BN("1000000000000000000");
+  
+  // Optional quote-token accounts, left out for SOL-quoted orders
+  const solQuoteAccounts = {
+    quoteMintConfig: null,
+    quoteVault: null,
+    quoteFeeVault: null,
+    userQuoteTokenAccount: null,
+    quoteTokenProgram: null,
+  };
+  
+  // Metaplex token metadata program, cloned into the local validator
+  const TOKEN_METADATA_PROGRAM_ID = new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+  const metadataBaseUri = "http://127.0.0.1:8080/api/stock/metadata";
+  
+  const findMetadataPDA = (mint: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("metadata"), TOKEN_METADATA_PROGRAM_ID.toBuffer(), mint.toBuffer()],
+      TOKEN_METADATA_PROGRAM_ID
+    )[0];
+  
+  // Reads name, symbol and URI from a metadata account. Each is a
+  // length-prefixed string padded with NULs, after the key, update authority
+  // and mint.
+  const fetchTokenMetadata = async (metadata: PublicKey) => {
+    const { data } = await provider.connection.getAccountInfo(metadata);
+    let offset = 1 + 32 + 32;
+    const readString = () => {
+      const length = data.readUInt32LE(offset);
+      const value = data.subarray(offset + 4, offset + 4 + length).toString("utf8");
+      offset += 4 + length;
+      return value.replace(/\0+$/, "");
+    };
+    return { name: readString(), symbol: readString(), uri: readString() };
+  };
+  
+  // Dividend Merkle tree, hashed like the backend builder and `claim_dividend`:
+  // prefixed leaves, sorted pairs, and an unpaired node carried up unchanged.
+  const sha256 = (...parts: Buffer[]) =>
+    createHash("sha256").update(Buffer.concat(parts)).digest();
+  const dividendLeaf = (index: number, holder: PublicKey, amount: number) => {
+    const indexBytes = Buffer.alloc(4);
+    indexBytes.writeUInt32LE(index);
+    return sha256(
+      Buffer.from([0]),
+      indexBytes,
+      holder.toBuffer(),
+      new anchor.BN(amount).toArrayLike(Buffer, "le", 8)
+    );
+  };
+  const buildDividendTree = (claims: { holder: PublicKey; amount: number }[]) => {
+    let level = claims.map(({ holder, amount }, index) => dividendLeaf(index, holder, amount));
+    const proofs: number[][][] = claims.map(() => []);
+    const positions = claims.map((_, index) => index);
+    while (level.length > 1) {
+      positions.forEach((position, i) => {
+        const sibling = position ^ 1;
+        if (sibling < level.length) {
+          proofs[i].push(Array.from(level[sibling]));
+        }
+        positions[i] = position >> 1;
+      });
+      const next: Buffer[] = [];
+      for (let i = 0; i < level.length; i += 2) {
+        if (i + 1 === level.length) {
+          next.push(level[i]);
+        } else {
+          const [left, right] = Buffer.compare(level[i], level[i + 1]) <= 0
+            ? [level[i], level[i + 1]]
+            : [level[i + 1], level[i]];
+          next.push(sha256(Buffer.from([1]), left, right));
+        }
+      }
+      level = next;
+    }
+    return { root: Array.from(level[0]), proofs };
+  };
+  
+  // Each sell order escrows its shares in its own token account
+  const findEscrowPDA = (sellOrder: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow"), sellOrder.toBuffer()],
+      program.programId
+    )[0];
+  const findConditionalEscrowPDA = (conditionalOrder: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("conditional_escrow"), conditionalOrder.toBuffer()],
+      program.programId
+    )[0];
+  
+  // Order PDAs are seeded with the user's order nonce, which starts at zero
+  // before the user account is created by the first order.
+  const findUserAccountPDA = (user: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("user_account"), user.toBuffer()],
+      program.programId
+    )[0];
+  const nextOrderId = async (user: PublicKey) => {
+    const userAccount = await program.account.userAccount.fetchNullable(findUserAccountPDA(user));
+    return userAccount ? userAccount.orderNonce : new anchor.BN(0);
+  };
+  
+  const findOrderBookPDA = (symbol: string) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("order_book"), Buffer.from(symbol)],
+      program.programId
+    )[0];
+  const findBookEscrowPDA = (symbol: string) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("book_escrow"), Buffer.from(symbol)],
+      program.programId
+    )[0];
+  const findAmmPDA = (seed: string, symbol: string) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from(seed), Buffer.from(symbol)],
+      program.programId
+    )[0];
+  
+  const findUserEligibilityPDA = (user: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("user_eligibility"), user.toBuffer()],
+      program.programId
+    )[0];
+  const findReserveAttestationPDA = (symbol: string) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("reserve_attestation"), Buffer.from(symbol)],
+      program.programId
+    )[0];
+  
+  // Terminal orders are closed, so their final state is read back from the
+  // receipt event in the closing transaction's logs.
+  const eventParser = new anchor.EventParser(program.programId, program.coder);
+  const fetchReceipt = async (signature: string) => {
+    await provider.connection.confirmTransaction(signature, "confirmed");
+    const transaction = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    for (const event of eventParser.parseLogs(transaction.meta.logMessages)) {
+      if (event.name.toLowerCase() === "orderreceipt") {
+        return event.data;
+      }
+    }
+    throw new Error("No order receipt in " + signature);
+  };
+  
+  // Short delay so queued vault withdrawals can be executed within the suite
+  const withdrawalDelay = 2;
+  
+  // Waits until the cluster clock is strictly past `timestamp` and returns it.
+  const waitForBlockTimeAfter = async (timestamp: number) => {
+    let blockTime = await provider.connection.getBlockTime(
+      await provider.connection.getSlot()
+    );
+    while (blockTime <= timestamp) {
+      await new Promise(resolve => setTimeout(resolve, 500));
+      blockTime = await provider.connection.getBlockTime(
+        await provider.connection.getSlot()
+      );
+    }
+    return blockTime;
+  };
+  
+  // Publishes a fresh price to the feed, waiting for the cluster clock to
+  // advance so publish times stay strictly increasing.
+  const publishPrice = async (price: number, priceFeed: PublicKey = priceFeedPDA) => {
+    const publishTime = await waitForBlockTimeAfter(lastPublishTime);
+    
+    await program.methods
+      .updatePriceFeed(new anchor.BN(price), new anchor.BN(0), new anchor.BN(publishTime))
+      .accounts({
+        priceFeed,
+        tradingPool: tradingPoolPDA,
+        oracleAuthority: backendAuthority.publicKey,
+      })
+      .signers([backendAuthority])
+      .rpc();
+    
+    lastPublishTime = publishTime;
+  };
+  
+  // Builds an attest_reserves call preceded by the ed25519 instruction in
+  // which `attestor` signs the statement.
+  const attestReservesBuilder = (
+    symbol: string,
+    attestedShares: anchor.BN,
+    attestedAt: number,
+    attestor: Keypair = reserveAttestor,
+    authority: Keypair = backendAuthority
+  ) => {
+    const ed25519Instruction = Ed25519Program.createInstructionWithPrivateKey({
+      privateKey: attestor.secretKey,
+      message: Buffer.concat([
+        Buffer.from("reserve_attestation"),
+        Buffer.from(symbol),
+        attestedShares.toArrayLike(Buffer, "le", 8),
+        new anchor.BN(attestedAt).toArrayLike(Buffer, "le", 8),
+      ]),
+    });
+    // The signature follows the 16 byte header and the 32 byte public key
+    const signature = Array.from(ed25519Instruction.data.subarray(48, 112));
+    
+    return program.methods
+      .attestReserves(symbol, attestedShares, new anchor.BN(attestedAt), signature)
+      .accounts({
+        reserveAttestation: findReserveAttestationPDA(symbol),
+        stockMintInfo: PublicKey.findProgramAddressSync(
+          [Buffer.from("stock_mint_info"), Buffer.from(symbol)],
+          program.programId
+        )[0],
+        tradingPool: tradingPoolPDA,
+        backendAuthority: authority.publicKey,
+        instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        systemProgram: SystemProgram.programId,
+      })
+      .preInstructions([ed25519Instruction])
+      .signers([authority]);
+  };
+  
+  // Attests the brokerage's holdings of a symbol; attestation times must be
+  // strictly increasing too.
+  const attestReserves = async (symbol: string, attestedShares: anchor.BN) => {
+    const attestedAt = await waitForBlockTimeAfter(lastAttestedAt);
+    
+    await attestReservesBuilder(symbol, attestedShares, attestedAt).rpc();
+    
+    lastAttestedAt = attestedAt;
+  };
+  
+  // Turns the pool's KYC eligibility requirement on or off.
+  const setEnforcement = (enforceEligibility: boolean) => program.methods
+    .setComplianceConfig(null, enforceEligibility)
+    .accounts({
+      tradingPool: tradingPoolPDA,
+      vaultAuthority: vaultAuthority.publicKey,
+    })
+    .signers([vaultAuthority])
+    .rpc();
+  
+  // Places a SOL-quoted buy order of user1 on the default symbol at
+  // `buyOrder`, the PDA of user1's next order id.
+  const placeBuyOrder = (
+    buyOrder: PublicKey,
+    solAmount: number,
+    maxPricePerShare: number,
+    userEligibility: PublicKey | null = null
+  ) => program.methods
+    .placeBuyOrder(stockSymbol, new anchor.BN(solAmount), new anchor.BN(maxPricePerShare), null)
+    .accounts({
+      ...solQuoteAccounts,
+      userAccount: findUserAccountPDA(user1.publicKey),
+      buyOrder,
+      stockMintInfo: stockMintInfoPDA,
+      tradingSchedule: tradingSchedulePDA,
+      tradingPool: tradingPoolPDA,
+      tradingPoolVault: tradingPoolVaultPDA,
+      userEligibility,
+      user: user1.publicKey,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([user1])
+    .rpc();
+  
+  // Fills all of user1's buy order on the default symbol, refunding whatever
+  // of its `solAmount` the shares did not cost.
+  const fulfillBuyOrder = (
+    buyOrder: PublicKey,
+    solAmount: number,
+    shares: number,
+    pricePerShare: number,
+    userEligibility: PublicKey | null = null
+  ) => program.methods
+    .fulfillBuyOrder(
+      new anchor.BN(shares),
+      new anchor.BN(pricePerShare),
+      new anchor.BN(shares * pricePerShare),
+      new anchor.BN(solAmount - shares * pricePerShare)
+    )
+    .accounts({
+      ...solQuoteAccounts,
+      buyOrder,
+      stockMint: stockMintPDA,
+      stockMintInfo: stockMintInfoPDA,
+      priceFeed: priceFeedPDA,
+      reserveAttestation: findReserveAttestationPDA(stockSymbol),
+      userStockTokenAccount: getAssociatedTokenAddressSync(stockMintPDA, user1.publicKey),
+      tradingPool: tradingPoolPDA,
+      tradingPoolVault: tradingPoolVaultPDA,
+      feeVault: feeVaultPDA,
+      userEligibility,
+      user: user1.publicKey,
+      backendAuthority: backendAuthority.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([backendAuthority])
+    .rpc();
   
   before(async () => {
     // Airdrop SOL to test accounts
@@ -59,6 +388,16 @@ describe("stock_contracts", () => {
       program.programId
     );
     
+    [feeVaultPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("fee_vault")],
+      program.programId
+    );
+    
+    [tradingSchedulePDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("trading_schedule")],
+      program.programId
+    );
+    
     [stockMintPDA, stockMintBump] = PublicKey.findProgramAddressSync(
       [Buffer.from("stock_mint"), Buffer.from(stockSymbol)],
       program.programId
@@ -68,17 +407,24 @@ describe("stock_contracts", () => {
       [Buffer.from("stock_mint_info"), Buffer.from(stockSymbol)],
       program.programId
     );
+    
+    [priceFeedPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("price_feed"), Buffer.from(stockSymbol)],
+      program.programId
+    );
   });
 
   it("Initialize trading pool", async () => {
     const tx = await program.methods
       .initializeTradingPool(
         vaultAuthority.publicKey,
-        backendAuthority.publicKey
+        backendAuthority.publicKey,
+        new anchor.BN(withdrawalDelay)
       )
       .accounts({
         tradingPool: tradingPoolPDA,
         tradingPoolVault: tradingPoolVaultPDA,
+        feeVault: feeVaultPDA,
         payer: provider.wallet.publicKey,
         systemProgram: SystemProgram.programId,
       })
@@ -90,22 +436,32 @@ describe("stock_contracts", () => {
     const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
     assert.equal(tradingPool.vaultAuthority.toBase58(), vaultAuthority.publicKey.toBase58());
     assert.equal(tradingPool.backendAuthority.toBase58(), backendAuthority.publicKey.toBase58());
-    assert.equal(tradingPool.totalOrders.toNumber(), 0);
+    assert.equal(tradingPool.oracleAuthority.toBase58(), backendAuthority.publicKey.toBase58());
+    assert.equal(tradingPool.feeAuthority.toBase58(), vaultAuthority.publicKey.toBase58());
+    assert.equal(tradingPool.feeBps, 0);
+    assert.equal(tradingPool.withdrawalDelay.toNumber(), withdrawalDelay);
+    assert.isNull(tradingPool.pendingWithdrawal);
     assert.equal(tradingPool.bump, tradingPoolBump);
   });
 
   it("Create stock mint", async () => {
     const decimals = 0; // Stocks are whole units
+    const name = "Apple Inc.";
+    const uri = `${metadataBaseUri}/${stockSymbol}`;
+    const metadataPDA = findMetadataPDA(stockMintPDA);
     
     const tx = await program.methods
-      .createStockMint(stockSymbol, decimals)
+      .createStockMint(stockSymbol, decimals, name, uri)
       .accounts({
         stockMint: stockMintPDA,
         stockMintInfo: stockMintInfoPDA,
         tradingPool: tradingPoolPDA,
+        metadata: metadataPDA,
         vaultAuthority: vaultAuthority.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
         systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
       })
       .signers([vaultAuthority])
       .rpc();
@@ -116,6 +472,7 @@ describe("stock_contracts", () => {
     const stockMintInfo = await program.account.stockMintInfo.fetch(stockMintInfoPDA);
     assert.equal(stockMintInfo.stockSymbol, stockSymbol);
     assert.equal(stockMintInfo.mint.toBase58(), stockMintPDA.toBase58());
+    assert.equal(stockMintInfo.decimals, decimals);
     assert.equal(stockMintInfo.totalSupply.toNumber(), 0);
     assert.equal(stockMintInfo.bump, stockMintInfoBump);
     
@@ -123,6 +480,167 @@ describe("stock_contracts", () => {
     const mintAccount = await getMint(provider.connection, stockMintPDA);
     assert.equal(mintAccount.decimals, decimals);
     assert.equal(mintAccount.mintAuthority?.toBase58(), tradingPoolPDA.toBase58());
+    
+    // Verify token metadata
+    const metadata = await fetchTokenMetadata(metadataPDA);
+    assert.equal(metadata.name, name);
+    assert.equal(metadata.symbol, stockSymbol);
+    assert.equal(metadata.uri, uri);
+  });
+
+  it("Update stock metadata", async () => {
+    const name = "Apple Inc. Common Stock";
+    const uri = `${metadataBaseUri}/${stockSymbol}?v=2`;
+    const metadataPDA = findMetadataPDA(stockMintPDA);
+    
+    // Only the vault authority may update metadata
+    try {
+      await program.methods
+        .updateStockMetadata(name, uri)
+        .accounts({
+          stockMintInfo: stockMintInfoPDA,
+          metadata: metadataPDA,
+          tradingPool: tradingPoolPDA,
+          vaultAuthority: user1.publicKey,
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+      
+      assert.fail("Should have failed with unauthorized vault access");
+    } catch (error) {
+      assert.include(error.toString(), "UnauthorizedVaultAccess");
+    }
+    
+    const tx = await program.methods
+      .updateStockMetadata(name, uri)
+      .accounts({
+        stockMintInfo: stockMintInfoPDA,
+        metadata: metadataPDA,
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    console.log("Update stock metadata tx:", tx);
+    
+    const metadata = await fetchTokenMetadata(metadataPDA);
+    assert.equal(metadata.name, name);
+    assert.equal(metadata.symbol, stockSymbol);
+    assert.equal(metadata.uri, uri);
+  });
+
+  it("Initialize and update price feed", async () => {
+    const tx = await program.methods
+      .initializePriceFeed(stockSymbol, null)
+      .accounts({
+        priceFeed: priceFeedPDA,
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    console.log("Initialize price feed tx:", tx);
+    
+    await publishPrice(1000000);
+    
+    const priceFeed = await program.account.priceFeed.fetch(priceFeedPDA);
+    assert.equal(priceFeed.stockSymbol, stockSymbol);
+    assert.equal(priceFeed.price.toNumber(), 1000000);
+    assert.equal(priceFeed.publishTime.toNumber(), lastPublishTime);
+    
+    // Only the oracle authority may publish prices
+    try {
+      await program.methods
+        .updatePriceFeed(new anchor.BN(1), new anchor.BN(0), new anchor.BN(lastPublishTime + 1))
+        .accounts({
+          priceFeed: priceFeedPDA,
+          tradingPool: tradingPoolPDA,
+          oracleAuthority: user1.publicKey,
+        })
+        .signers([user1])
+        .rpc();
+      
+      assert.fail("Should have failed with unauthorized oracle");
+    } catch (error) {
+      assert.include(error.toString(), "UnauthorizedOracle");
+    }
+  });
+
+  it("Attest brokerage reserves", async () => {
+    // The custodian's key signs attestations, not the backend
+    await program.methods
+      .setOracleConfig(null, null, null, null, reserveAttestor.publicKey)
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    await attestReserves(stockSymbol, fullyBacked);
+    
+    const reserveAttestation = await program.account.reserveAttestation.fetch(
+      findReserveAttestationPDA(stockSymbol)
+    );
+    assert.equal(reserveAttestation.stockSymbol, stockSymbol);
+    assert.equal(reserveAttestation.attestedShares.toString(), fullyBacked.toString());
+    assert.equal(reserveAttestation.attestedAt.toNumber(), lastAttestedAt);
+    assert.ok(reserveAttestation.attestedBy.equals(reserveAttestor.publicKey));
+    
+    // Attestations cannot be replayed or backdated
+    try {
+      await attestReservesBuilder(stockSymbol, fullyBacked, lastAttestedAt).rpc();
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidAttestationTime");
+    }
+    
+    // Only the backend authority may submit attestations
+    try {
+      await attestReservesBuilder(
+        stockSymbol, fullyBacked, lastAttestedAt + 1, reserveAttestor, user1
+      ).rpc();
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.toString(), "UnauthorizedBackend");
+    }
+    
+    // Only the reserve attestor may sign them
+    const nextAttestedAt = await waitForBlockTimeAfter(lastAttestedAt);
+    try {
+      await attestReservesBuilder(
+        stockSymbol, fullyBacked, nextAttestedAt, backendAuthority
+      ).rpc();
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidAttestationSignature");
+    }
+    
+    // And the signature must be verified in the same transaction
+    try {
+      await program.methods
+        .attestReserves(
+          stockSymbol, fullyBacked, new anchor.BN(nextAttestedAt), reserveAttestation.signature
+        )
+        .accounts({
+          reserveAttestation: findReserveAttestationPDA(stockSymbol),
+          stockMintInfo: stockMintInfoPDA,
+          tradingPool: tradingPoolPDA,
+          backendAuthority: backendAuthority.publicKey,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backendAuthority])
+        .rpc();
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidAttestationSignature");
+    }
   });
 
   it("Place buy order", async () => {
@@ -135,7 +653,7 @@ describe("stock_contracts", () => {
     
     // Get order ID
     const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
-    const orderId = tradingPool.totalOrders;
+    const orderId = await nextOrderId(user1.publicKey);
     
     // Derive buy order PDA
     const [buyOrderPDA] = PublicKey.findProgramAddressSync(
@@ -148,11 +666,16 @@ describe("stock_contracts", () => {
     );
     
     const tx = await program.methods
-      .placeBuyOrder(stockSymbol, new anchor.BN(solAmount), new anchor.BN(maxPricePerShare))
+      .placeBuyOrder(stockSymbol, new anchor.BN(solAmount), new anchor.BN(maxPricePerShare), null)
       .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user1.publicKey),
         buyOrder: buyOrderPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
         tradingPool: tradingPoolPDA,
         tradingPoolVault: tradingPoolVaultPDA,
+        userEligibility: null,
         user: user1.publicKey,
         systemProgram: SystemProgram.programId,
       })
@@ -182,9 +705,13 @@ describe("stock_contracts", () => {
     );
     assert.equal(vaultFinalBalance - vaultInitialBalance, solAmount);
     
-    // Verify total orders increased
+    // Verify the user's order nonce advanced and the escrowed SOL is reserved
     const updatedTradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
-    assert.equal(updatedTradingPool.totalOrders.toNumber(), orderId.toNumber() + 1);
+    assert.equal((await nextOrderId(user1.publicKey)).toNumber(), orderId.toNumber() + 1);
+    assert.equal(
+      updatedTradingPool.reservedLamports.toNumber() - tradingPool.reservedLamports.toNumber(),
+      solAmount
+    );
   });
 
   it("Fulfill buy order", async () => {
@@ -210,6 +737,8 @@ describe("stock_contracts", () => {
       user1.publicKey
     );
     
+    await publishPrice(pricePerShare);
+    
     const tx = await program.methods
       .fulfillBuyOrder(
         new anchor.BN(sharesPurchased),
@@ -218,12 +747,17 @@ describe("stock_contracts", () => {
         new anchor.BN(refundAmount)
       )
       .accounts({
+        ...solQuoteAccounts,
         buyOrder: buyOrderPDA,
         stockMint: stockMintPDA,
         stockMintInfo: stockMintInfoPDA,
+        priceFeed: priceFeedPDA,
+        reserveAttestation: findReserveAttestationPDA(stockSymbol),
         userStockTokenAccount: userStockTokenAccount,
         tradingPool: tradingPoolPDA,
         tradingPoolVault: tradingPoolVaultPDA,
+        feeVault: feeVaultPDA,
+        userEligibility: null,
         user: user1.publicKey,
         backendAuthority: backendAuthority.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
@@ -235,11 +769,12 @@ describe("stock_contracts", () => {
     
     console.log("Fulfill buy order tx:", tx);
     
-    // Verify order was fulfilled
-    const buyOrder = await program.account.buyOrder.fetch(buyOrderPDA);
-    assert.equal(buyOrder.status.fulfilled !== undefined, true);
-    assert.equal(buyOrder.sharesReceived.toNumber(), sharesPurchased);
-    assert.equal(buyOrder.actualPricePerShare.toNumber(), pricePerShare);
+    // Verify order was fulfilled and closed
+    const receipt = await fetchReceipt(tx);
+    assert.equal(receipt.status.fulfilled !== undefined, true);
+    assert.equal(receipt.sharesFilled.toNumber(), sharesPurchased);
+    assert.equal(receipt.averagePricePerShare.toNumber(), pricePerShare);
+    assert.isNull(await program.account.buyOrder.fetchNullable(buyOrderPDA));
     
     // Verify user received tokens
     const userTokenAccount = await getAccount(provider.connection, userStockTokenAccount);
@@ -248,6 +783,10 @@ describe("stock_contracts", () => {
     // Verify total supply updated
     const stockMintInfo = await program.account.stockMintInfo.fetch(stockMintInfoPDA);
     assert.equal(stockMintInfo.totalSupply.toNumber(), sharesPurchased);
+    
+    // The order's reservation is released once it completes
+    const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.equal(tradingPool.reservedLamports.toNumber(), 0);
   });
 
   it("Place sell order", async () => {
@@ -260,15 +799,8 @@ describe("stock_contracts", () => {
       user1.publicKey
     );
     
-    // Get escrow token account
-    const [escrowTokenAccount] = PublicKey.findProgramAddressSync(
-      [Buffer.from("escrow"), stockMintPDA.toBuffer()],
-      program.programId
-    );
-    
     // Get order ID
-    const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
-    const orderId = tradingPool.totalOrders;
+    const orderId = await nextOrderId(user1.publicKey);
     
     // Derive sell order PDA
     const [sellOrderPDA] = PublicKey.findProgramAddressSync(
@@ -280,18 +812,26 @@ describe("stock_contracts", () => {
       program.programId
     );
     
+    // Get the order's escrow token account
+    const escrowTokenAccount = findEscrowPDA(sellOrderPDA);
+    
     // Get initial token balance
     const userTokenAccountBefore = await getAccount(provider.connection, userStockTokenAccount);
     const initialTokenBalance = Number(userTokenAccountBefore.amount);
     
     const tx = await program.methods
-      .placeSellOrder(stockSymbol, new anchor.BN(sharesToSell), new anchor.BN(minPricePerShare))
+      .placeSellOrder(stockSymbol, new anchor.BN(sharesToSell), new anchor.BN(minPricePerShare), null)
       .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user1.publicKey),
         sellOrder: sellOrderPDA,
         stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
         userStockTokenAccount: userStockTokenAccount,
         escrowTokenAccount: escrowTokenAccount,
         tradingPool: tradingPoolPDA,
+        userEligibility: null,
         user: user1.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
         systemProgram: SystemProgram.programId,
@@ -342,17 +882,19 @@ describe("stock_contracts", () => {
       user1.publicKey
     );
     
-    // Get escrow token account
-    const [escrowTokenAccount] = PublicKey.findProgramAddressSync(
-      [Buffer.from("escrow"), stockMintPDA.toBuffer()],
-      program.programId
-    );
+    // Get the order's escrow token account
+    const escrowTokenAccount = findEscrowPDA(sellOrderPDA);
     
-    // Get initial balances
+    // Get initial balances, including the rent the user gets back
     const userInitialBalance = await provider.connection.getBalance(user1.publicKey);
+    const rentReturned =
+      (await provider.connection.getBalance(sellOrderPDA)) +
+      (await provider.connection.getBalance(escrowTokenAccount));
     const userTokenAccountBefore = await getAccount(provider.connection, userStockTokenAccount);
     const initialTokenBalance = Number(userTokenAccountBefore.amount);
     
+    await publishPrice(pricePerShare);
+    
     const tx = await program.methods
       .fulfillSellOrder(
         new anchor.BN(sharesSold),
@@ -361,13 +903,17 @@ describe("stock_contracts", () => {
         new anchor.BN(sharesReturned)
       )
       .accounts({
+        ...solQuoteAccounts,
         sellOrder: sellOrderPDA,
         stockMint: stockMintPDA,
         stockMintInfo: stockMintInfoPDA,
+        priceFeed: priceFeedPDA,
         userStockTokenAccount: userStockTokenAccount,
         escrowTokenAccount: escrowTokenAccount,
         tradingPool: tradingPoolPDA,
         tradingPoolVault: tradingPoolVaultPDA,
+        feeVault: feeVaultPDA,
+        userEligibility: null,
         user: user1.publicKey,
         backendAuthority: backendAuthority.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
@@ -379,14 +925,18 @@ describe("stock_contracts", () => {
     console.log("Fulfill sell order tx:", tx);
     
     // Verify order was fulfilled
-    const sellOrder = await program.account.sellOrder.fetch(sellOrderPDA);
-    assert.equal(sellOrder.status.fulfilled !== undefined, true);
-    assert.equal(sellOrder.solReceived.toNumber(), totalProceeds);
-    assert.equal(sellOrder.actualPricePerShare.toNumber(), pricePerShare);
+    const receipt = await fetchReceipt(tx);
+    assert.equal(receipt.status.fulfilled !== undefined, true);
+    assert.equal(receipt.quoteFilled.toNumber(), totalProceeds);
+    assert.equal(receipt.averagePricePerShare.toNumber(), pricePerShare);
     
-    // Verify user received SOL
+    // The order and its escrow are closed back to the user
+    assert.isNull(await program.account.sellOrder.fetchNullable(sellOrderPDA));
+    assert.isNull(await provider.connection.getAccountInfo(escrowTokenAccount));
+    
+    // Verify user received SOL and the rent
     const userFinalBalance = await provider.connection.getBalance(user1.publicKey);
-    assert.equal(userFinalBalance - userInitialBalance, totalProceeds);
+    assert.equal(userFinalBalance - userInitialBalance, totalProceeds + rentReturned);
     
     // Verify user received returned tokens
     const userTokenAccountAfter = await getAccount(provider.connection, userStockTokenAccount);
@@ -397,90 +947,113 @@ describe("stock_contracts", () => {
     assert.equal(stockMintInfo.totalSupply.toNumber(), 2000 - sharesSold); // 2000 initial - 800 sold
   });
 
-  it("Deposit vault funds", async () => {
-    const depositAmount = 5 * LAMPORTS_PER_SOL; // 5 SOL
-    
-    const vaultInitialBalance = await provider.connection.getBalance(tradingPoolVaultPDA);
-    
-    const tx = await program.methods
-      .depositVaultFunds(new anchor.BN(depositAmount))
-      .accounts({
-        tradingPool: tradingPoolPDA,
-        tradingPoolVault: tradingPoolVaultPDA,
-        vaultAuthority: vaultAuthority.publicKey,
-        systemProgram: SystemProgram.programId,
-      })
-      .signers([vaultAuthority])
-      .rpc();
+  it("Partially fill and then finalize buy order", async () => {
+    const solAmount = 1 * LAMPORTS_PER_SOL;
+    const maxPricePerShare = 1000000;
     
-    console.log("Deposit vault funds tx:", tx);
+    const orderId = await nextOrderId(user1.publicKey);
     
-    const vaultFinalBalance = await provider.connection.getBalance(tradingPoolVaultPDA);
-    assert.equal(vaultFinalBalance - vaultInitialBalance, depositAmount);
-  });
-
-  it("Withdraw vault funds", async () => {
-    const withdrawAmount = 1 * LAMPORTS_PER_SOL; // 1 SOL
+    const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user1.publicKey.toBuffer(),
+        orderId.toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
     
-    const vaultInitialBalance = await provider.connection.getBalance(tradingPoolVaultPDA);
-    const authorityInitialBalance = await provider.connection.getBalance(vaultAuthority.publicKey);
+    const userStockTokenAccount = await getAssociatedTokenAddress(
+      stockMintPDA,
+      user1.publicKey
+    );
     
-    const tx = await program.methods
-      .withdrawVaultFunds(new anchor.BN(withdrawAmount))
+    await program.methods
+      .placeBuyOrder(stockSymbol, new anchor.BN(solAmount), new anchor.BN(maxPricePerShare), null)
       .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user1.publicKey),
+        buyOrder: buyOrderPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
         tradingPool: tradingPoolPDA,
         tradingPoolVault: tradingPoolVaultPDA,
-        vaultAuthority: vaultAuthority.publicKey,
+        userEligibility: null,
+        user: user1.publicKey,
         systemProgram: SystemProgram.programId,
       })
-      .signers([vaultAuthority])
+      .signers([user1])
       .rpc();
     
-    console.log("Withdraw vault funds tx:", tx);
+    const fulfillAccounts = {
+      ...solQuoteAccounts,
+      buyOrder: buyOrderPDA,
+      stockMint: stockMintPDA,
+      stockMintInfo: stockMintInfoPDA,
+      priceFeed: priceFeedPDA,
+      reserveAttestation: findReserveAttestationPDA(stockSymbol),
+      userStockTokenAccount: userStockTokenAccount,
+      tradingPool: tradingPoolPDA,
+      tradingPoolVault: tradingPoolVaultPDA,
+      feeVault: feeVaultPDA,
+      userEligibility: null,
+      user: user1.publicKey,
+      backendAuthority: backendAuthority.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    };
     
-    const vaultFinalBalance = await provider.connection.getBalance(tradingPoolVaultPDA);
-    const authorityFinalBalance = await provider.connection.getBalance(vaultAuthority.publicKey);
+    const tokenAccountBefore = await getAccount(provider.connection, userStockTokenAccount);
     
-    assert.equal(vaultInitialBalance - vaultFinalBalance, withdrawAmount);
-    assert.approximately(
-      authorityFinalBalance - authorityInitialBalance,
-      withdrawAmount,
-      0.01 * LAMPORTS_PER_SOL // Allow for transaction fees
-    );
-  });
-
-  it("Update authorities", async () => {
-    const newVaultAuthority = Keypair.generate();
-    const newBackendAuthority = Keypair.generate();
+    // First broker execution: 300 shares at 0.001 SOL
+    await publishPrice(1000000);
+    await program.methods
+      .partiallyFillBuyOrder(
+        new anchor.BN(300),
+        new anchor.BN(1000000),
+        new anchor.BN(300 * 1000000)
+      )
+      .accounts(fulfillAccounts)
+      .signers([backendAuthority])
+      .rpc();
+    
+    const buyOrder = await program.account.buyOrder.fetch(buyOrderPDA);
+    assert.equal(buyOrder.status.partiallyFilled !== undefined, true);
+    assert.equal(buyOrder.sharesReceived.toNumber(), 300);
+    assert.equal(buyOrder.solSpent.toNumber(), 300 * 1000000);
     
+    // Final execution: 200 shares at 0.0009 SOL, refund the rest
+    const refundAmount = solAmount - 300 * 1000000 - 200 * 900000;
+    await publishPrice(900000);
     const tx = await program.methods
-      .updateAuthorities(
-        newVaultAuthority.publicKey,
-        newBackendAuthority.publicKey
+      .fulfillBuyOrder(
+        new anchor.BN(200),
+        new anchor.BN(900000),
+        new anchor.BN(200 * 900000),
+        new anchor.BN(refundAmount)
       )
-      .accounts({
-        tradingPool: tradingPoolPDA,
-        currentVaultAuthority: vaultAuthority.publicKey,
-        vaultAuthority: vaultAuthority.publicKey,
-      })
-      .signers([vaultAuthority])
+      .accounts(fulfillAccounts)
+      .signers([backendAuthority])
       .rpc();
     
-    console.log("Update authorities tx:", tx);
+    console.log("Finalize partially filled buy order tx:", tx);
     
-    // Verify authorities were updated
-    const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
-    assert.equal(tradingPool.vaultAuthority.toBase58(), newVaultAuthority.publicKey.toBase58());
-    assert.equal(tradingPool.backendAuthority.toBase58(), newBackendAuthority.publicKey.toBase58());
+    const receipt = await fetchReceipt(tx);
+    assert.equal(receipt.status.fulfilled !== undefined, true);
+    assert.equal(receipt.sharesFilled.toNumber(), 500);
+    assert.equal(receipt.quoteFilled.toNumber(), 300 * 1000000 + 200 * 900000);
+    // Volume-weighted average of both executions
+    assert.equal(receipt.averagePricePerShare.toNumber(), 960000);
+    
+    const tokenAccountAfter = await getAccount(provider.connection, userStockTokenAccount);
+    assert.equal(Number(tokenAccountAfter.amount) - Number(tokenAccountBefore.amount), 500);
   });
 
-  it("Should fail: unauthorized backend trying to fulfill order", async () => {
-    // First create a new buy order
+  it("Cancel buy order", async () => {
     const solAmount = 1 * LAMPORTS_PER_SOL;
     const maxPricePerShare = 1000000;
     
-    const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
-    const orderId = tradingPool.totalOrders;
+    const orderId = await nextOrderId(user2.publicKey);
     
     const [buyOrderPDA] = PublicKey.findProgramAddressSync(
       [
@@ -491,40 +1064,3368 @@ describe("stock_contracts", () => {
       program.programId
     );
     
-    // Place order
     await program.methods
-      .placeBuyOrder(stockSymbol, new anchor.BN(solAmount), new anchor.BN(maxPricePerShare))
+      .placeBuyOrder(stockSymbol, new anchor.BN(solAmount), new anchor.BN(maxPricePerShare), null)
       .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user2.publicKey),
         buyOrder: buyOrderPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
         tradingPool: tradingPoolPDA,
         tradingPoolVault: tradingPoolVaultPDA,
+        userEligibility: null,
         user: user2.publicKey,
         systemProgram: SystemProgram.programId,
       })
       .signers([user2])
       .rpc();
     
-    // Try to fulfill with wrong authority (using old backend authority)
-    const userStockTokenAccount = await getAssociatedTokenAddress(
-      stockMintPDA,
-      user2.publicKey
-    );
+    const vaultInitialBalance = await provider.connection.getBalance(tradingPoolVaultPDA);
     
-    try {
-      await program.methods
-        .fulfillBuyOrder(
-          new anchor.BN(1000),
-          new anchor.BN(1000000),
-          new anchor.BN(1000000000),
-          new anchor.BN(0)
-        )
-        .accounts({
+    const tx = await program.methods
+      .cancelBuyOrder()
+      .accounts({
+        ...solQuoteAccounts,
+        buyOrder: buyOrderPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        user: user2.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user2])
+      .rpc();
+    
+    console.log("Cancel buy order tx:", tx);
+    
+    const receipt = await fetchReceipt(tx);
+    assert.equal(receipt.status.cancelled !== undefined, true);
+    assert.equal(receipt.amount.toNumber(), solAmount);
+    assert.isNull(await program.account.buyOrder.fetchNullable(buyOrderPDA));
+    
+    // Verify the full amount was refunded from the vault
+    const vaultFinalBalance = await provider.connection.getBalance(tradingPoolVaultPDA);
+    assert.equal(vaultInitialBalance - vaultFinalBalance, solAmount);
+    
+    // Cancelling twice must fail, the order is gone
+    try {
+      await program.methods
+        .cancelBuyOrder()
+        .accounts({
+          ...solQuoteAccounts,
+          buyOrder: buyOrderPDA,
+          tradingPool: tradingPoolPDA,
+          tradingPoolVault: tradingPoolVaultPDA,
+          user: user2.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user2])
+        .rpc();
+      
+      assert.fail("Should have failed on a closed order");
+    } catch (error) {
+      assert.include(error.toString(), "AccountNotInitialized");
+    }
+    
+    // A partially filled order can still be cancelled for its unspent SOL
+    const [partialOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user2.publicKey.toBuffer(),
+        (await nextOrderId(user2.publicKey)).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    await program.methods
+      .placeBuyOrder(stockSymbol, new anchor.BN(solAmount), new anchor.BN(maxPricePerShare), null)
+      .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user2.publicKey),
+        buyOrder: partialOrderPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        userEligibility: null,
+        user: user2.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user2])
+      .rpc();
+    
+    await publishPrice(1000000);
+    await program.methods
+      .partiallyFillBuyOrder(
+        new anchor.BN(300),
+        new anchor.BN(1000000),
+        new anchor.BN(300 * 1000000)
+      )
+      .accounts({
+        ...solQuoteAccounts,
+        buyOrder: partialOrderPDA,
+        stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        priceFeed: priceFeedPDA,
+        reserveAttestation: findReserveAttestationPDA(stockSymbol),
+        userStockTokenAccount: getAssociatedTokenAddressSync(stockMintPDA, user2.publicKey),
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        feeVault: feeVaultPDA,
+        userEligibility: null,
+        user: user2.publicKey,
+        backendAuthority: backendAuthority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([backendAuthority])
+      .rpc();
+    
+    const vaultBeforePartialCancel = await provider.connection.getBalance(tradingPoolVaultPDA);
+    const partialCancelTx = await program.methods
+      .cancelBuyOrder()
+      .accounts({
+        ...solQuoteAccounts,
+        buyOrder: partialOrderPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        user: user2.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user2])
+      .rpc();
+    
+    const partialReceipt = await fetchReceipt(partialCancelTx);
+    assert.equal(partialReceipt.status.cancelled !== undefined, true);
+    assert.equal(partialReceipt.sharesFilled.toNumber(), 300);
+    assert.equal(
+      vaultBeforePartialCancel - await provider.connection.getBalance(tradingPoolVaultPDA),
+      solAmount - 300 * 1000000
+    );
+  });
+
+  it("Reject sell order", async () => {
+    const sharesToSell = 100;
+    const minPricePerShare = 900000;
+    const reasonCode = 1;
+    
+    const userStockTokenAccount = await getAssociatedTokenAddress(
+      stockMintPDA,
+      user1.publicKey
+    );
+    
+    const orderId = await nextOrderId(user1.publicKey);
+    
+    const [sellOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("sell_order"),
+        user1.publicKey.toBuffer(),
+        orderId.toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    const escrowTokenAccount = findEscrowPDA(sellOrderPDA);
+    
+    const userTokenAccountBefore = await getAccount(provider.connection, userStockTokenAccount);
+    const initialTokenBalance = Number(userTokenAccountBefore.amount);
+    
+    await program.methods
+      .placeSellOrder(stockSymbol, new anchor.BN(sharesToSell), new anchor.BN(minPricePerShare), null)
+      .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user1.publicKey),
+        sellOrder: sellOrderPDA,
+        stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
+        userStockTokenAccount: userStockTokenAccount,
+        escrowTokenAccount: escrowTokenAccount,
+        tradingPool: tradingPoolPDA,
+        userEligibility: null,
+        user: user1.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    const tx = await program.methods
+      .rejectSellOrder(reasonCode)
+      .accounts({
+        sellOrder: sellOrderPDA,
+        stockMint: stockMintPDA,
+        userStockTokenAccount: userStockTokenAccount,
+        escrowTokenAccount: escrowTokenAccount,
+        tradingPool: tradingPoolPDA,
+        user: user1.publicKey,
+        backendAuthority: backendAuthority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([backendAuthority])
+      .rpc();
+    
+    console.log("Reject sell order tx:", tx);
+    
+    const receipt = await fetchReceipt(tx);
+    assert.equal(receipt.status.cancelled !== undefined, true);
+    assert.equal(receipt.sharesFilled.toNumber(), 0);
+    assert.isNull(await program.account.sellOrder.fetchNullable(sellOrderPDA));
+    assert.isNull(await provider.connection.getAccountInfo(escrowTokenAccount));
+    
+    // Verify escrowed shares were returned
+    const userTokenAccountAfter = await getAccount(provider.connection, userStockTokenAccount);
+    assert.equal(Number(userTokenAccountAfter.amount), initialTokenBalance);
+  });
+
+  it("Reclaim expired buy order", async () => {
+    const solAmount = 1 * LAMPORTS_PER_SOL;
+    const maxPricePerShare = 1000000;
+    const expiresAt = Math.floor(Date.now() / 1000) + 2;
+    
+    const orderId = await nextOrderId(user2.publicKey);
+    
+    const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user2.publicKey.toBuffer(),
+        orderId.toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    await program.methods
+      .placeBuyOrder(
+        stockSymbol,
+        new anchor.BN(solAmount),
+        new anchor.BN(maxPricePerShare),
+        new anchor.BN(expiresAt)
+      )
+      .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user2.publicKey),
+        buyOrder: buyOrderPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        userEligibility: null,
+        user: user2.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user2])
+      .rpc();
+    
+    const buyOrder = await program.account.buyOrder.fetch(buyOrderPDA);
+    assert.equal(buyOrder.expiresAt.toNumber(), expiresAt);
+    
+    // Wait for the order to expire
+    await new Promise(resolve => setTimeout(resolve, 4000));
+    
+    const userInitialBalance = await provider.connection.getBalance(user2.publicKey);
+    
+    // Anyone can crank the reclaim
+    const tx = await program.methods
+      .reclaimExpiredBuyOrder()
+      .accounts({
+        ...solQuoteAccounts,
+        buyOrder: buyOrderPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        user: user2.publicKey,
+        cranker: provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+    
+    console.log("Reclaim expired buy order tx:", tx);
+    
+    // Verify the user got the SOL back and the order account was closed
+    const userFinalBalance = await provider.connection.getBalance(user2.publicKey);
+    assert.isAtLeast(userFinalBalance - userInitialBalance, solAmount);
+    
+    const closedOrder = await provider.connection.getAccountInfo(buyOrderPDA);
+    assert.isNull(closedOrder);
+    
+    const receipt = await fetchReceipt(tx);
+    assert.equal(receipt.status.expired !== undefined, true);
+    assert.equal(receipt.quoteFilled.toNumber(), 0);
+  });
+
+  it("Fulfill a batch of buy orders", async () => {
+    const solAmount = 1 * LAMPORTS_PER_SOL;
+    const pricePerShare = 1000000;
+    const buyers = [user1, user2];
+    const sharesPurchased = [1000, 600];
+    
+    const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    const buyOrderPDAs: PublicKey[] = [];
+    const userStockTokenAccounts: PublicKey[] = [];
+    const remainingAccounts = [];
+    for (const buyer of buyers) {
+      const orderId = await nextOrderId(buyer.publicKey);
+      const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("buy_order"),
+          buyer.publicKey.toBuffer(),
+          orderId.toArrayLike(Buffer, "le", 8)
+        ],
+        program.programId
+      );
+      
+      await program.methods
+        .placeBuyOrder(stockSymbol, new anchor.BN(solAmount), new anchor.BN(pricePerShare), null)
+        .accounts({
+          ...solQuoteAccounts,
+          userAccount: findUserAccountPDA(buyer.publicKey),
+          buyOrder: buyOrderPDA,
+          stockMintInfo: stockMintInfoPDA,
+          tradingSchedule: tradingSchedulePDA,
+          tradingPool: tradingPoolPDA,
+          tradingPoolVault: tradingPoolVaultPDA,
+          userEligibility: null,
+          user: buyer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+      
+      // Batch fills need the buyer's stock account to exist already
+      const userStockTokenAccount = await createAssociatedTokenAccountIdempotent(
+        provider.connection,
+        buyer,
+        stockMintPDA,
+        buyer.publicKey
+      );
+      
+      buyOrderPDAs.push(buyOrderPDA);
+      userStockTokenAccounts.push(userStockTokenAccount);
+      remainingAccounts.push(
+        { pubkey: buyOrderPDA, isSigner: false, isWritable: true },
+        { pubkey: buyer.publicKey, isSigner: false, isWritable: true },
+        { pubkey: userStockTokenAccount, isSigner: false, isWritable: true }
+      );
+    }
+    
+    const fills = sharesPurchased.map((shares) => ({
+      sharesPurchased: new anchor.BN(shares),
+      pricePerShare: new anchor.BN(pricePerShare),
+      totalCost: new anchor.BN(shares * pricePerShare),
+      refundAmount: new anchor.BN(solAmount - shares * pricePerShare),
+    }));
+    
+    const supplyBefore = (await program.account.stockMintInfo.fetch(stockMintInfoPDA))
+      .totalSupply.toNumber();
+    const balancesBefore = await Promise.all(
+      buyers.map((buyer) => provider.connection.getBalance(buyer.publicKey))
+    );
+    const tokensBefore = await Promise.all(
+      userStockTokenAccounts.map(async (account) =>
+        Number((await getAccount(provider.connection, account)).amount)
+      )
+    );
+    const rentReturned = await Promise.all(
+      buyOrderPDAs.map((buyOrderPDA) => provider.connection.getBalance(buyOrderPDA))
+    );
+    
+    await publishPrice(pricePerShare);
+    
+    const tx = await program.methods
+      .fulfillBuyOrdersBatch(stockSymbol, null, fills)
+      .accounts({
+        stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        priceFeed: priceFeedPDA,
+        reserveAttestation: findReserveAttestationPDA(stockSymbol),
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        feeVault: feeVaultPDA,
+        quoteMintConfig: null,
+        quoteVault: null,
+        quoteFeeVault: null,
+        quoteTokenProgram: null,
+        backendAuthority: backendAuthority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(remainingAccounts)
+      .signers([backendAuthority])
+      .rpc();
+    
+    console.log("Fulfill buy orders batch tx:", tx);
+    
+    // Every order in the batch is filled, refunded and closed
+    for (let i = 0; i < buyers.length; i++) {
+      assert.isNull(await program.account.buyOrder.fetchNullable(buyOrderPDAs[i]));
+      
+      const tokenAccount = await getAccount(provider.connection, userStockTokenAccounts[i]);
+      assert.equal(Number(tokenAccount.amount), tokensBefore[i] + sharesPurchased[i]);
+      
+      const balance = await provider.connection.getBalance(buyers[i].publicKey);
+      assert.equal(
+        balance - balancesBefore[i],
+        solAmount - sharesPurchased[i] * pricePerShare + rentReturned[i]
+      );
+    }
+    
+    const stockMintInfo = await program.account.stockMintInfo.fetch(stockMintInfoPDA);
+    assert.equal(
+      stockMintInfo.totalSupply.toNumber(),
+      supplyBefore + sharesPurchased[0] + sharesPurchased[1]
+    );
+    
+    // Both reservations are released
+    const updatedTradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.equal(
+      updatedTradingPool.reservedLamports.toNumber(),
+      tradingPool.reservedLamports.toNumber()
+    );
+  });
+
+  it("Match bids and asks on the order book", async () => {
+    const orderBookPDA = findOrderBookPDA(stockSymbol);
+    const bookEscrowPDA = findBookEscrowPDA(stockSymbol);
+    const user1StockTokenAccount = getAssociatedTokenAddressSync(stockMintPDA, user1.publicKey);
+    const user2StockTokenAccount = getAssociatedTokenAddressSync(stockMintPDA, user2.publicKey);
+    
+    await program.methods
+      .createOrderBook(stockSymbol)
+      .accounts({
+        orderBook: orderBookPDA,
+        stockMint: stockMintPDA,
+        bookEscrow: bookEscrowPDA,
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    // user2 rests two asks, user1 then bids above the best one
+    const placeAsk = (shares: number, pricePerShare: number) => program.methods
+      .placeAsk(new anchor.BN(shares), new anchor.BN(pricePerShare))
+      .accounts({
+        orderBook: orderBookPDA,
+        stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        userStockTokenAccount: user2StockTokenAccount,
+        bookEscrow: bookEscrowPDA,
+        tradingPool: tradingPoolPDA,
+        userEligibility: null,
+        evictedOwnerStockTokenAccount: null,
+        user: user2.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user2])
+      .rpc();
+    await placeAsk(300, 1000000);
+    await placeAsk(100, 1200000);
+    
+    const bidShares = 200;
+    const bidPrice = 1100000;
+    await program.methods
+      .placeBid(new anchor.BN(bidShares), new anchor.BN(bidPrice))
+      .accounts({
+        orderBook: orderBookPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        userEligibility: null,
+        evictedOwner: null,
+        user: user1.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    let orderBook = await program.account.orderBook.fetch(orderBookPDA);
+    assert.deepEqual(orderBook.asks.map((ask) => ask.sequence.toNumber()), [0, 1]);
+    assert.equal(orderBook.bids[0].sequence.toNumber(), 2);
+    assert.equal(orderBook.bids[0].lamportsRemaining.toNumber(), bidShares * bidPrice);
+    
+    const supplyBefore = (await program.account.stockMintInfo.fetch(stockMintInfoPDA))
+      .totalSupply.toNumber();
+    const user1BalanceBefore = await provider.connection.getBalance(user1.publicKey);
+    const user2BalanceBefore = await provider.connection.getBalance(user2.publicKey);
+    const user1TokensBefore = Number(
+      (await getAccount(provider.connection, user1StockTokenAccount)).amount
+    );
+    
+    // Anyone can crank the match
+    const tx = await program.methods
+      .matchOrders(5, 0)
+      .accounts({
+        orderBook: orderBookPDA,
+        stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        bookEscrow: bookEscrowPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        cranker: provider.wallet.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts([
+        { pubkey: user1.publicKey, isSigner: false, isWritable: true },
+        { pubkey: user1StockTokenAccount, isSigner: false, isWritable: true },
+        { pubkey: user2.publicKey, isSigner: false, isWritable: true },
+      ])
+      .rpc();
+    
+    console.log("Match orders tx:", tx);
+    
+    // The resting ask sets the price; the bid's excess escrow is refunded
+    const askPrice = 1000000;
+    const user1TokensAfter = Number(
+      (await getAccount(provider.connection, user1StockTokenAccount)).amount
+    );
+    assert.equal(user1TokensAfter - user1TokensBefore, bidShares);
+    assert.equal(
+      (await provider.connection.getBalance(user2.publicKey)) - user2BalanceBefore,
+      bidShares * askPrice
+    );
+    assert.equal(
+      (await provider.connection.getBalance(user1.publicKey)) - user1BalanceBefore,
+      bidShares * (bidPrice - askPrice)
+    );
+    
+    // Shares changed hands without being minted or burned
+    const stockMintInfo = await program.account.stockMintInfo.fetch(stockMintInfoPDA);
+    assert.equal(stockMintInfo.totalSupply.toNumber(), supplyBefore);
+    
+    orderBook = await program.account.orderBook.fetch(orderBookPDA);
+    assert.equal(orderBook.bids.length, 0);
+    assert.equal(orderBook.asks[0].sharesRemaining.toNumber(), 100);
+    
+    // The unmatched rest of the first ask falls back to the brokerage
+    const [sellOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("sell_order"),
+        user2.publicKey.toBuffer(),
+        (await nextOrderId(user2.publicKey)).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    const escrowTokenAccount = findEscrowPDA(sellOrderPDA);
+    
+    await program.methods
+      .routeAskToBrokerage(new anchor.BN(0))
+      .accounts({
+        orderBook: orderBookPDA,
+        userAccount: findUserAccountPDA(user2.publicKey),
+        sellOrder: sellOrderPDA,
+        stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        bookEscrow: bookEscrowPDA,
+        escrowTokenAccount: escrowTokenAccount,
+        tradingPool: tradingPoolPDA,
+        user: user2.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user2])
+      .rpc();
+    
+    const sellOrder = await program.account.sellOrder.fetch(sellOrderPDA);
+    assert.equal(sellOrder.sharesToSell.toNumber(), 100);
+    assert.equal(sellOrder.minPricePerShare.toNumber(), askPrice);
+    const escrowAccount = await getAccount(provider.connection, escrowTokenAccount);
+    assert.equal(Number(escrowAccount.amount), 100);
+    
+    // The other ask is cancelled and its shares returned
+    const user2TokensBefore = Number(
+      (await getAccount(provider.connection, user2StockTokenAccount)).amount
+    );
+    await program.methods
+      .cancelAsk(new anchor.BN(1))
+      .accounts({
+        orderBook: orderBookPDA,
+        stockMint: stockMintPDA,
+        userStockTokenAccount: user2StockTokenAccount,
+        bookEscrow: bookEscrowPDA,
+        tradingPool: tradingPoolPDA,
+        user: user2.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user2])
+      .rpc();
+    
+    const user2TokensAfter = Number(
+      (await getAccount(provider.connection, user2StockTokenAccount)).amount
+    );
+    assert.equal(user2TokensAfter - user2TokensBefore, 100);
+    
+    orderBook = await program.account.orderBook.fetch(orderBookPDA);
+    assert.equal(orderBook.asks.length, 0);
+    const bookEscrow = await getAccount(provider.connection, bookEscrowPDA);
+    assert.equal(Number(bookEscrow.amount), 0);
+  });
+
+  it("Evict, cancel, route and flush book orders", async () => {
+    const orderBookPDA = findOrderBookPDA(stockSymbol);
+    const bookEscrowPDA = findBookEscrowPDA(stockSymbol);
+    const user2StockTokenAccount = getAssociatedTokenAddressSync(stockMintPDA, user2.publicKey);
+    
+    const placeBid = (
+      user: Keypair,
+      shares: number,
+      pricePerShare: number,
+      evictedOwner: PublicKey | null = null
+    ) => program.methods
+      .placeBid(new anchor.BN(shares), new anchor.BN(pricePerShare))
+      .accounts({
+        orderBook: orderBookPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        userEligibility: null,
+        evictedOwner,
+        user: user.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+    const nextSequence = async () =>
+      (await program.account.orderBook.fetch(orderBookPDA)).nextSequence.toNumber();
+    const adminAccounts = {
+      orderBook: orderBookPDA,
+      stockMint: stockMintPDA,
+      stockMintInfo: stockMintInfoPDA,
+      bookEscrow: bookEscrowPDA,
+      tradingPool: tradingPoolPDA,
+      tradingPoolVault: tradingPoolVaultPDA,
+      vaultAuthority: vaultAuthority.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    };
+    const reservedBefore = (await program.account.tradingPool.fetch(tradingPoolPDA))
+      .reservedLamports.toNumber();
+    
+    // A buyer without a stock account holds the best bid over a crossing ask
+    const stuckBuyer = Keypair.generate();
+    await provider.connection.requestAirdrop(stuckBuyer.publicKey, LAMPORTS_PER_SOL);
+    await new Promise(resolve => setTimeout(resolve, 1000));
+    const stuckBuyerBalance = await provider.connection.getBalance(stuckBuyer.publicKey);
+    
+    await placeBid(stuckBuyer, 20, 1000000);
+    await program.methods
+      .placeAsk(new anchor.BN(20), new anchor.BN(900000))
+      .accounts({
+        orderBook: orderBookPDA,
+        stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        userStockTokenAccount: user2StockTokenAccount,
+        bookEscrow: bookEscrowPDA,
+        tradingPool: tradingPoolPDA,
+        userEligibility: null,
+        evictedOwnerStockTokenAccount: null,
+        user: user2.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user2])
+      .rpc();
+    
+    // The crank evicts and refunds the bid instead of failing on it
+    await program.methods
+      .matchOrders(1, 0)
+      .accounts({
+        orderBook: orderBookPDA,
+        stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        bookEscrow: bookEscrowPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        cranker: provider.wallet.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts([
+        { pubkey: stuckBuyer.publicKey, isSigner: false, isWritable: true },
+        {
+          pubkey: getAssociatedTokenAddressSync(stockMintPDA, stuckBuyer.publicKey),
+          isSigner: false,
+          isWritable: true
+        },
+        { pubkey: user2.publicKey, isSigner: false, isWritable: true },
+      ])
+      .rpc();
+    
+    assert.equal(await provider.connection.getBalance(stuckBuyer.publicKey), stuckBuyerBalance);
+    let orderBook = await program.account.orderBook.fetch(orderBookPDA);
+    assert.equal(orderBook.bids.length, 0);
+    assert.equal(orderBook.asks[0].sharesRemaining.toNumber(), 20);
+    
+    // Dust cannot rest on the book
+    try {
+      await placeBid(user2, 1, 850000);
+      assert.fail("Should have failed below the minimum order size");
+    } catch (error) {
+      assert.include(error.toString(), "BookOrderTooSmall");
+    }
+    
+    // Fill the bid side, then outbid its worst order
+    const firstSequence = await nextSequence();
+    for (let i = 0; i < 32; i++) {
+      await placeBid(user1, 20, 800000);
+    }
+    
+    try {
+      await placeBid(user2, 20, 800000, user1.publicKey);
+      assert.fail("Should have failed without a better price");
+    } catch (error) {
+      assert.include(error.toString(), "OrderBookFull");
+    }
+    
+    const user1BalanceBefore = await provider.connection.getBalance(user1.publicKey);
+    const outbidSequence = await nextSequence();
+    await placeBid(user2, 20, 850000, user1.publicKey);
+    
+    assert.equal(
+      (await provider.connection.getBalance(user1.publicKey)) - user1BalanceBefore,
+      20 * 800000
+    );
+    orderBook = await program.account.orderBook.fetch(orderBookPDA);
+    assert.equal(orderBook.bids.length, 32);
+    assert.equal(orderBook.bids[0].sequence.toNumber(), outbidSequence);
+    assert.equal(orderBook.bids[31].sequence.toNumber(), outbidSequence - 2);
+    
+    // Owners cancel their bids for a refund of the escrow
+    const user2BalanceBefore = await provider.connection.getBalance(user2.publicKey);
+    await program.methods
+      .cancelBid(new anchor.BN(outbidSequence))
+      .accounts({
+        orderBook: orderBookPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        user: user2.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user2])
+      .rpc();
+    
+    assert.equal(
+      (await provider.connection.getBalance(user2.publicKey)) - user2BalanceBefore,
+      20 * 850000
+    );
+    
+    // ...or hand them to the brokerage, which keeps the escrow reserved
+    const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user1.publicKey.toBuffer(),
+        (await nextOrderId(user1.publicKey)).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    await program.methods
+      .routeBidToBrokerage(new anchor.BN(firstSequence))
+      .accounts({
+        orderBook: orderBookPDA,
+        userAccount: findUserAccountPDA(user1.publicKey),
+        buyOrder: buyOrderPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingPool: tradingPoolPDA,
+        user: user1.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    const buyOrder = await program.account.buyOrder.fetch(buyOrderPDA);
+    assert.equal(buyOrder.solAmount.toNumber(), 20 * 800000);
+    assert.equal(buyOrder.maxPricePerShare.toNumber(), 800000);
+    assert.equal(buyOrder.status.pending !== undefined, true);
+    
+    // The vault authority can take any order off the book
+    const user1BalanceBeforeForceCancel = await provider.connection.getBalance(user1.publicKey);
+    await program.methods
+      .forceCancelBookOrder({ buy: {} }, new anchor.BN(firstSequence + 1))
+      .accounts(adminAccounts)
+      .remainingAccounts([
+        { pubkey: user1.publicKey, isSigner: false, isWritable: true },
+      ])
+      .signers([vaultAuthority])
+      .rpc();
+    
+    assert.equal(
+      (await provider.connection.getBalance(user1.publicKey)) - user1BalanceBeforeForceCancel,
+      20 * 800000
+    );
+    
+    // A halted symbol's book is flushed so corporate actions can go ahead
+    try {
+      await program.methods
+        .flushOrderBook(16)
+        .accounts(adminAccounts)
+        .signers([vaultAuthority])
+        .rpc();
+      assert.fail("Should have failed while the symbol is active");
+    } catch (error) {
+      assert.include(error.toString(), "SymbolNotHalted");
+    }
+    
+    const setStatus = (tradingStatus: object) => program.methods
+      .setPause(null, tradingStatus)
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        stockMintInfo: stockMintInfoPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    await setStatus({ halted: {} });
+    
+    try {
+      await program.methods
+        .applyStockSplit(new anchor.BN(2), new anchor.BN(1))
+        .accounts({
+          stockMintInfo: stockMintInfoPDA,
+          orderBook: orderBookPDA,
+          tradingPool: tradingPoolPDA,
+          vaultAuthority: vaultAuthority.publicKey,
+        })
+        .signers([vaultAuthority])
+        .rpc();
+      assert.fail("Should have failed with orders on the book");
+    } catch (error) {
+      assert.include(error.toString(), "OrderBookNotEmpty");
+    }
+    
+    // 29 bids of user1 go first, then user2's ask
+    const user2TokensBefore = Number(
+      (await getAccount(provider.connection, user2StockTokenAccount)).amount
+    );
+    const user1Refund = { pubkey: user1.publicKey, isSigner: false, isWritable: true };
+    await program.methods
+      .flushOrderBook(16)
+      .accounts(adminAccounts)
+      .remainingAccounts(Array(16).fill(user1Refund))
+      .signers([vaultAuthority])
+      .rpc();
+    await program.methods
+      .flushOrderBook(16)
+      .accounts(adminAccounts)
+      .remainingAccounts([
+        ...Array(13).fill(user1Refund),
+        { pubkey: user2StockTokenAccount, isSigner: false, isWritable: true },
+      ])
+      .signers([vaultAuthority])
+      .rpc();
+    
+    await setStatus({ active: {} });
+    
+    orderBook = await program.account.orderBook.fetch(orderBookPDA);
+    assert.equal(orderBook.bids.length, 0);
+    assert.equal(orderBook.asks.length, 0);
+    assert.equal(Number((await getAccount(provider.connection, bookEscrowPDA)).amount), 0);
+    assert.equal(
+      Number((await getAccount(provider.connection, user2StockTokenAccount)).amount),
+      user2TokensBefore + 20
+    );
+    
+    // Only the routed bid's escrow is still reserved
+    const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.equal(tradingPool.reservedLamports.toNumber(), reservedBefore + 20 * 800000);
+  });
+
+  it("Provide liquidity and swap on the AMM pool", async () => {
+    const ammPoolPDA = findAmmPDA("amm_pool", stockSymbol);
+    const lpMintPDA = findAmmPDA("lp_mint", stockSymbol);
+    const ammStockVaultPDA = findAmmPDA("amm_stock_vault", stockSymbol);
+    const user1StockTokenAccount = getAssociatedTokenAddressSync(stockMintPDA, user1.publicKey);
+    const user2StockTokenAccount = getAssociatedTokenAddressSync(stockMintPDA, user2.publicKey);
+    const user2LpTokenAccount = getAssociatedTokenAddressSync(lpMintPDA, user2.publicKey);
+    const feeBps = 30;
+    
+    await program.methods
+      .createAmmPool(stockSymbol, feeBps)
+      .accounts({
+        ammPool: ammPoolPDA,
+        stockMint: stockMintPDA,
+        lpMint: lpMintPDA,
+        ammStockVault: ammStockVaultPDA,
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    const ammAccounts = {
+      ammPool: ammPoolPDA,
+      stockMint: stockMintPDA,
+      ammStockVault: ammStockVaultPDA,
+      tradingPool: tradingPoolPDA,
+      tradingPoolVault: tradingPoolVaultPDA,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    };
+    
+    // user2 seeds the pool at 1,000,000 lamports per share
+    const depositShares = 100;
+    const depositLamports = 100000000;
+    const reservedBefore = (await program.account.tradingPool.fetch(tradingPoolPDA))
+      .reservedLamports.toNumber();
+    await program.methods
+      .depositLiquidity(new anchor.BN(depositShares), new anchor.BN(depositLamports), new anchor.BN(1))
+      .accounts({
+        ...ammAccounts,
+        stockMintInfo: stockMintInfoPDA,
+        lpMint: lpMintPDA,
+        userStockTokenAccount: user2StockTokenAccount,
+        userLpTokenAccount: user2LpTokenAccount,
+        userEligibility: null,
+        user: user2.publicKey,
+      })
+      .signers([user2])
+      .rpc();
+    
+    // The first deposit mints the geometric mean of the two amounts
+    const lpTokens = Math.sqrt(depositShares * depositLamports);
+    let lpAccount = await getAccount(provider.connection, user2LpTokenAccount);
+    assert.equal(Number(lpAccount.amount), lpTokens);
+    let ammPool = await program.account.ammPool.fetch(ammPoolPDA);
+    assert.equal(ammPool.stockReserve.toNumber(), depositShares);
+    assert.equal(ammPool.solReserve.toNumber(), depositLamports);
+    assert.equal(
+      (await program.account.tradingPool.fetch(tradingPoolPDA)).reservedLamports.toNumber(),
+      reservedBefore + depositLamports
+    );
+    
+    // user1 buys shares with SOL; the fee stays in the pool
+    const buyLamports = 10000000;
+    const buyAfterFee = buyLamports - Math.floor(buyLamports * feeBps / 10000);
+    const expectedShares = Math.floor(
+      depositShares * buyAfterFee / (depositLamports + buyAfterFee)
+    );
+    const user1TokensBefore = Number(
+      (await getAccount(provider.connection, user1StockTokenAccount)).amount
+    );
+    await program.methods
+      .swap({ buy: {} }, new anchor.BN(buyLamports), new anchor.BN(expectedShares))
+      .accounts({
+        ...ammAccounts,
+        stockMintInfo: stockMintInfoPDA,
+        userStockTokenAccount: user1StockTokenAccount,
+        userEligibility: null,
+        user: user1.publicKey,
+      })
+      .signers([user1])
+      .rpc();
+    
+    const user1TokensAfter = Number(
+      (await getAccount(provider.connection, user1StockTokenAccount)).amount
+    );
+    assert.equal(user1TokensAfter - user1TokensBefore, expectedShares);
+    
+    // A minimum above the quoted output is rejected
+    try {
+      await program.methods
+        .swap({ sell: {} }, new anchor.BN(expectedShares), new anchor.BN(buyLamports))
+        .accounts({
+          ...ammAccounts,
+          stockMintInfo: stockMintInfoPDA,
+          userStockTokenAccount: user1StockTokenAccount,
+          userEligibility: null,
+          user: user1.publicKey,
+        })
+        .signers([user1])
+        .rpc();
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.message, "SlippageExceeded");
+    }
+    
+    // ...and the shares sell back for SOL
+    ammPool = await program.account.ammPool.fetch(ammPoolPDA);
+    const sellAfterFee = expectedShares - Math.floor(expectedShares * feeBps / 10000);
+    const expectedLamports = Math.floor(
+      ammPool.solReserve.toNumber() * sellAfterFee / (ammPool.stockReserve.toNumber() + sellAfterFee)
+    );
+    const user1BalanceBefore = await provider.connection.getBalance(user1.publicKey);
+    await program.methods
+      .swap({ sell: {} }, new anchor.BN(expectedShares), new anchor.BN(expectedLamports))
+      .accounts({
+        ...ammAccounts,
+        stockMintInfo: stockMintInfoPDA,
+        userStockTokenAccount: user1StockTokenAccount,
+        userEligibility: null,
+        user: user1.publicKey,
+      })
+      .signers([user1])
+      .rpc();
+    
+    assert.equal(
+      (await provider.connection.getBalance(user1.publicKey)) - user1BalanceBefore,
+      expectedLamports
+    );
+    
+    // Withdrawing all LP tokens returns the reserves, fees included
+    ammPool = await program.account.ammPool.fetch(ammPoolPDA);
+    const stockReserve = ammPool.stockReserve.toNumber();
+    const solReserve = ammPool.solReserve.toNumber();
+    assert.isAbove(solReserve, depositLamports);
+    
+    const user2BalanceBefore = await provider.connection.getBalance(user2.publicKey);
+    const user2TokensBefore = Number(
+      (await getAccount(provider.connection, user2StockTokenAccount)).amount
+    );
+    await program.methods
+      .withdrawLiquidity(new anchor.BN(lpTokens), new anchor.BN(stockReserve), new anchor.BN(solReserve))
+      .accounts({
+        ...ammAccounts,
+        lpMint: lpMintPDA,
+        userStockTokenAccount: user2StockTokenAccount,
+        userLpTokenAccount: user2LpTokenAccount,
+        user: user2.publicKey,
+      })
+      .signers([user2])
+      .rpc();
+    
+    assert.equal(
+      (await provider.connection.getBalance(user2.publicKey)) - user2BalanceBefore,
+      solReserve
+    );
+    const user2TokensAfter = Number(
+      (await getAccount(provider.connection, user2StockTokenAccount)).amount
+    );
+    assert.equal(user2TokensAfter - user2TokensBefore, stockReserve);
+    lpAccount = await getAccount(provider.connection, user2LpTokenAccount);
+    assert.equal(Number(lpAccount.amount), 0);
+    
+    ammPool = await program.account.ammPool.fetch(ammPoolPDA);
+    assert.equal(ammPool.stockReserve.toNumber(), 0);
+    assert.equal(ammPool.solReserve.toNumber(), 0);
+    assert.equal(
+      (await program.account.tradingPool.fetch(tradingPoolPDA)).reservedLamports.toNumber(),
+      reservedBefore
+    );
+  });
+
+  it("Should fail: fill price deviates from price feed", async () => {
+    const solAmount = 1 * LAMPORTS_PER_SOL;
+    const maxPricePerShare = 1000000;
+    
+    const orderId = await nextOrderId(user1.publicKey);
+    
+    const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user1.publicKey.toBuffer(),
+        orderId.toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    const userStockTokenAccount = await getAssociatedTokenAddress(
+      stockMintPDA,
+      user1.publicKey
+    );
+    
+    await program.methods
+      .placeBuyOrder(stockSymbol, new anchor.BN(solAmount), new anchor.BN(maxPricePerShare), null)
+      .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user1.publicKey),
+        buyOrder: buyOrderPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        userEligibility: null,
+        user: user1.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    await publishPrice(1000000);
+    
+    try {
+      // 20% below the feed price, well outside the default 2% band
+      await program.methods
+        .fulfillBuyOrder(
+          new anchor.BN(1000),
+          new anchor.BN(800000),
+          new anchor.BN(1000 * 800000),
+          new anchor.BN(0)
+        )
+        .accounts({
+          ...solQuoteAccounts,
+          buyOrder: buyOrderPDA,
+          stockMint: stockMintPDA,
+          stockMintInfo: stockMintInfoPDA,
+          priceFeed: priceFeedPDA,
+          reserveAttestation: findReserveAttestationPDA(stockSymbol),
+          userStockTokenAccount: userStockTokenAccount,
+          tradingPool: tradingPoolPDA,
+          tradingPoolVault: tradingPoolVaultPDA,
+          feeVault: feeVaultPDA,
+          userEligibility: null,
+          user: user1.publicKey,
+          backendAuthority: backendAuthority.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backendAuthority])
+        .rpc();
+      
+      assert.fail("Should have failed with price deviation");
+    } catch (error) {
+      assert.include(error.toString(), "PriceDeviationTooLarge");
+    }
+  });
+
+  it("Should fail: minting beyond attested reserves", async () => {
+    const solAmount = 1 * LAMPORTS_PER_SOL;
+    const maxPricePerShare = 1000000;
+    
+    const orderId = await nextOrderId(user1.publicKey);
+    
+    const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user1.publicKey.toBuffer(),
+        orderId.toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    await placeBuyOrder(buyOrderPDA, solAmount, maxPricePerShare);
+    
+    // The brokerage holds exactly the shares already outstanding
+    const stockMintInfo = await program.account.stockMintInfo.fetch(stockMintInfoPDA);
+    await attestReserves(stockSymbol, stockMintInfo.totalSupply);
+    await publishPrice(1000000);
+    
+    try {
+      await fulfillBuyOrder(buyOrderPDA, solAmount, 1000, 1000000);
+      assert.fail("Should have failed with insufficient reserves");
+    } catch (error) {
+      assert.include(error.toString(), "InsufficientReserves");
+    }
+    
+    // Once the brokerage attests the new shares the fill goes through
+    await attestReserves(stockSymbol, fullyBacked);
+    await fulfillBuyOrder(buyOrderPDA, solAmount, 1000, 1000000);
+    
+    const stockMintInfoAfter = await program.account.stockMintInfo.fetch(stockMintInfoPDA);
+    assert.equal(
+      stockMintInfoAfter.totalSupply.toNumber() - stockMintInfo.totalSupply.toNumber(),
+      1000
+    );
+  });
+
+  it("Enforce KYC eligibility on orders", async () => {
+    const userEligibilityPDA = findUserEligibilityPDA(user1.publicKey);
+    const solAmount = 1 * LAMPORTS_PER_SOL;
+    const maxPricePerShare = 1000000;
+    
+    await setEnforcement(true);
+    
+    const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user1.publicKey.toBuffer(),
+        (await nextOrderId(user1.publicKey)).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    // Wallets without a grant cannot place orders
+    try {
+      await placeBuyOrder(buyOrderPDA, solAmount, maxPricePerShare);
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.toString(), "UserNotEligible");
+    }
+    
+    // The compliance authority, the vault authority by default, grants it
+    const expiresAt = Math.floor(Date.now() / 1000) + 3600;
+    await program.methods
+      .grantEligibility(user1.publicKey, Array.from(Buffer.from("US")), new anchor.BN(expiresAt))
+      .accounts({
+        userEligibility: userEligibilityPDA,
+        tradingPool: tradingPoolPDA,
+        complianceAuthority: vaultAuthority.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    const userEligibility = await program.account.userEligibility.fetch(userEligibilityPDA);
+    assert.ok(userEligibility.user.equals(user1.publicKey));
+    assert.equal(userEligibility.status.approved !== undefined, true);
+    assert.equal(Buffer.from(userEligibility.jurisdiction).toString(), "US");
+    assert.equal(userEligibility.expiresAt.toNumber(), expiresAt);
+    
+    await placeBuyOrder(buyOrderPDA, solAmount, maxPricePerShare, userEligibilityPDA);
+    
+    // A revoked wallet's open order cannot be filled
+    await program.methods
+      .revokeEligibility()
+      .accounts({
+        userEligibility: userEligibilityPDA,
+        tradingPool: tradingPoolPDA,
+        complianceAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    // ...and the order book is closed to it too
+    try {
+      await program.methods
+        .placeBid(new anchor.BN(100), new anchor.BN(maxPricePerShare))
+        .accounts({
+          orderBook: findOrderBookPDA(stockSymbol),
+          stockMintInfo: stockMintInfoPDA,
+          tradingPool: tradingPoolPDA,
+          tradingPoolVault: tradingPoolVaultPDA,
+          userEligibility: userEligibilityPDA,
+          evictedOwner: null,
+          user: user1.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.toString(), "UserNotEligible");
+    }
+    
+    await publishPrice(1000000);
+    try {
+      await fulfillBuyOrder(buyOrderPDA, solAmount, 1000, 1000000, userEligibilityPDA);
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.toString(), "UserNotEligible");
+    }
+    
+    // Without enforcement the order fills as before
+    await setEnforcement(false);
+    await fulfillBuyOrder(buyOrderPDA, solAmount, 1000, 1000000, userEligibilityPDA);
+    
+    const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.equal(tradingPool.enforceEligibility, false);
+    assert.ok(tradingPool.complianceAuthority.equals(vaultAuthority.publicKey));
+  });
+
+  it("Restrict order placement to market hours", async () => {
+    const solAmount = 1 * LAMPORTS_PER_SOL;
+    const maxPricePerShare = 1000000;
+    const alwaysOpen = Array(7).fill({ openMinute: 0, closeMinute: 1440 });
+    
+    // Sessions run around the clock in UTC, except on two holidays from today
+    const blockTime = await provider.connection.getBlockTime(
+      await provider.connection.getSlot()
+    );
+    const today = Math.floor(blockTime / 86400);
+    const setSchedule = (holidays: number[], offHoursPolicy: object) => program.methods
+      .setTradingSchedule(0, alwaysOpen, holidays, [], offHoursPolicy)
+      .accounts({
+        tradingSchedule: tradingSchedulePDA,
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    await setSchedule([today, today + 1], { reject: {} });
+    
+    const marketStatus = await program.methods
+      .getMarketStatus()
+      .accounts({ tradingSchedule: tradingSchedulePDA })
+      .view();
+    const nextOpen = (today + 2) * 86400;
+    assert.equal(marketStatus.isOpen, false);
+    assert.equal(marketStatus.nextOpen.toNumber(), nextOpen);
+    
+    const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user1.publicKey.toBuffer(),
+        (await nextOrderId(user1.publicKey)).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    try {
+      await placeBuyOrder(buyOrderPDA, solAmount, maxPricePerShare);
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.toString(), "MarketClosed");
+    }
+    
+    // Queued orders are accepted but cannot be filled before the open
+    await setSchedule([today, today + 1], { queue: {} });
+    await placeBuyOrder(buyOrderPDA, solAmount, maxPricePerShare);
+    
+    const buyOrder = await program.account.buyOrder.fetch(buyOrderPDA);
+    assert.equal(buyOrder.queuedUntil.toNumber(), nextOpen);
+    
+    await publishPrice(1000000);
+    try {
+      await fulfillBuyOrder(buyOrderPDA, solAmount, 1000, 1000000);
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.toString(), "OrderQueued");
+    }
+    
+    // The user can still cancel while queued
+    await program.methods
+      .cancelBuyOrder()
+      .accounts({
+        ...solQuoteAccounts,
+        buyOrder: buyOrderPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        user: user1.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    // Reopen the market for the remaining tests
+    await setSchedule([], { reject: {} });
+    const reopened = await program.methods
+      .getMarketStatus()
+      .accounts({ tradingSchedule: tradingSchedulePDA })
+      .view();
+    assert.equal(reopened.isOpen, true);
+  });
+
+  it("Trigger and cancel conditional orders", async () => {
+    const sharesToSell = 100;
+    const triggerPrice = 800000;
+    const minPricePerShare = 700000;
+    const userStockTokenAccount = getAssociatedTokenAddressSync(stockMintPDA, user1.publicKey);
+    const findOrderPDA = (seed: string, orderId: anchor.BN) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from(seed), user1.publicKey.toBuffer(), orderId.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+    
+    // Stop-loss: sell once the price falls to the trigger
+    const conditionalSellPDA = findOrderPDA("conditional_order", await nextOrderId(user1.publicKey));
+    const conditionalEscrow = findConditionalEscrowPDA(conditionalSellPDA);
+    const balanceBefore = Number((await getAccount(provider.connection, userStockTokenAccount)).amount);
+    
+    await program.methods
+      .placeConditionalSellOrder(
+        stockSymbol,
+        new anchor.BN(sharesToSell),
+        new anchor.BN(triggerPrice),
+        { below: {} },
+        new anchor.BN(minPricePerShare)
+      )
+      .accounts({
+        userAccount: findUserAccountPDA(user1.publicKey),
+        conditionalOrder: conditionalSellPDA,
+        stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        userStockTokenAccount,
+        conditionalEscrow,
+        tradingPool: tradingPoolPDA,
+        userEligibility: null,
+        user: user1.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    assert.equal(Number((await getAccount(provider.connection, conditionalEscrow)).amount), sharesToSell);
+    assert.equal(
+      Number((await getAccount(provider.connection, userStockTokenAccount)).amount),
+      balanceBefore - sharesToSell
+    );
+    
+    // Anyone may crank the trigger, but only once the feed has crossed it
+    const triggerSellOrder = async () => {
+      const sellOrderPDA = findOrderPDA("sell_order", await nextOrderId(user1.publicKey));
+      await program.methods
+        .triggerConditionalSellOrder()
+        .accounts({
+          conditionalOrder: conditionalSellPDA,
+          userAccount: findUserAccountPDA(user1.publicKey),
+          sellOrder: sellOrderPDA,
+          stockMint: stockMintPDA,
+          stockMintInfo: stockMintInfoPDA,
+          priceFeed: priceFeedPDA,
+          tradingSchedule: tradingSchedulePDA,
+          conditionalEscrow,
+          escrowTokenAccount: findEscrowPDA(sellOrderPDA),
+          tradingPool: tradingPoolPDA,
+          cranker: user2.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user2])
+        .rpc();
+      return sellOrderPDA;
+    };
+    
+    await publishPrice(1000000);
+    try {
+      await triggerSellOrder();
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.toString(), "TriggerNotReached");
+    }
+    
+    await publishPrice(750000);
+    const sellOrderPDA = await triggerSellOrder();
+    
+    const sellOrder = await program.account.sellOrder.fetch(sellOrderPDA);
+    assert.equal(sellOrder.user.toBase58(), user1.publicKey.toBase58());
+    assert.equal(sellOrder.sharesToSell.toNumber(), sharesToSell);
+    assert.equal(sellOrder.minPricePerShare.toNumber(), minPricePerShare);
+    assert.equal(sellOrder.status.pending !== undefined, true);
+    assert.equal(
+      Number((await getAccount(provider.connection, findEscrowPDA(sellOrderPDA))).amount),
+      sharesToSell
+    );
+    assert.isNull(await provider.connection.getAccountInfo(conditionalSellPDA));
+    assert.isNull(await provider.connection.getAccountInfo(conditionalEscrow));
+    
+    await program.methods
+      .cancelSellOrder()
+      .accounts({
+        sellOrder: sellOrderPDA,
+        stockMint: stockMintPDA,
+        userStockTokenAccount,
+        escrowTokenAccount: findEscrowPDA(sellOrderPDA),
+        tradingPool: tradingPoolPDA,
+        user: user1.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user1])
+      .rpc();
+    
+    // An untriggered stop-loss hands its shares back when cancelled
+    const cancelledSellPDA = findOrderPDA("conditional_order", await nextOrderId(user1.publicKey));
+    const cancelledEscrow = findConditionalEscrowPDA(cancelledSellPDA);
+    
+    await program.methods
+      .placeConditionalSellOrder(
+        stockSymbol,
+        new anchor.BN(sharesToSell),
+        new anchor.BN(600000),
+        { below: {} },
+        new anchor.BN(500000)
+      )
+      .accounts({
+        userAccount: findUserAccountPDA(user1.publicKey),
+        conditionalOrder: cancelledSellPDA,
+        stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        userStockTokenAccount,
+        conditionalEscrow: cancelledEscrow,
+        tradingPool: tradingPoolPDA,
+        userEligibility: null,
+        user: user1.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    assert.equal(
+      Number((await getAccount(provider.connection, userStockTokenAccount)).amount),
+      balanceBefore - sharesToSell
+    );
+    
+    await program.methods
+      .cancelConditionalSellOrder()
+      .accounts({
+        conditionalOrder: cancelledSellPDA,
+        stockMint: stockMintPDA,
+        userStockTokenAccount,
+        conditionalEscrow: cancelledEscrow,
+        tradingPool: tradingPoolPDA,
+        user: user1.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user1])
+      .rpc();
+    
+    assert.isNull(await provider.connection.getAccountInfo(cancelledSellPDA));
+    assert.isNull(await provider.connection.getAccountInfo(cancelledEscrow));
+    assert.equal(
+      Number((await getAccount(provider.connection, userStockTokenAccount)).amount),
+      balanceBefore
+    );
+    
+    // Buy stop: the escrowed SOL stays reserved until the user cancels
+    const solAmount = LAMPORTS_PER_SOL / 2;
+    const conditionalBuyPDA = findOrderPDA("conditional_order", await nextOrderId(user1.publicKey));
+    const reservedBefore = (await program.account.tradingPool.fetch(tradingPoolPDA))
+      .reservedLamports.toNumber();
+    
+    await program.methods
+      .placeConditionalBuyOrder(
+        stockSymbol,
+        new anchor.BN(solAmount),
+        new anchor.BN(900000),
+        { above: {} },
+        new anchor.BN(1000000)
+      )
+      .accounts({
+        userAccount: findUserAccountPDA(user1.publicKey),
+        conditionalOrder: conditionalBuyPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        userEligibility: null,
+        user: user1.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    assert.equal(
+      (await program.account.tradingPool.fetch(tradingPoolPDA)).reservedLamports.toNumber(),
+      reservedBefore + solAmount
+    );
+    
+    await program.methods
+      .cancelConditionalBuyOrder()
+      .accounts({
+        conditionalOrder: conditionalBuyPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        user: user1.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    assert.isNull(await provider.connection.getAccountInfo(conditionalBuyPDA));
+    assert.equal(
+      (await program.account.tradingPool.fetch(tradingPoolPDA)).reservedLamports.toNumber(),
+      reservedBefore
+    );
+    assert.equal(
+      Number((await getAccount(provider.connection, userStockTokenAccount)).amount),
+      balanceBefore
+    );
+    
+    // A triggered buy stop hands its reservation over to the new buy order
+    const triggeredBuyPDA = findOrderPDA("conditional_order", await nextOrderId(user1.publicKey));
+    
+    await program.methods
+      .placeConditionalBuyOrder(
+        stockSymbol,
+        new anchor.BN(solAmount),
+        new anchor.BN(900000),
+        { above: {} },
+        new anchor.BN(1000000)
+      )
+      .accounts({
+        userAccount: findUserAccountPDA(user1.publicKey),
+        conditionalOrder: triggeredBuyPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        userEligibility: null,
+        user: user1.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    const triggerBuyOrder = async () => {
+      const buyOrderPDA = findOrderPDA("buy_order", await nextOrderId(user1.publicKey));
+      await program.methods
+        .triggerConditionalBuyOrder()
+        .accounts({
+          conditionalOrder: triggeredBuyPDA,
+          userAccount: findUserAccountPDA(user1.publicKey),
+          buyOrder: buyOrderPDA,
+          stockMintInfo: stockMintInfoPDA,
+          priceFeed: priceFeedPDA,
+          tradingSchedule: tradingSchedulePDA,
+          tradingPool: tradingPoolPDA,
+          cranker: user2.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user2])
+        .rpc();
+      return buyOrderPDA;
+    };
+    
+    try {
+      await triggerBuyOrder();
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.toString(), "TriggerNotReached");
+    }
+    
+    await publishPrice(1000000);
+    const buyOrderPDA = await triggerBuyOrder();
+    
+    const buyOrder = await program.account.buyOrder.fetch(buyOrderPDA);
+    assert.equal(buyOrder.user.toBase58(), user1.publicKey.toBase58());
+    assert.equal(buyOrder.solAmount.toNumber(), solAmount);
+    assert.equal(buyOrder.maxPricePerShare.toNumber(), 1000000);
+    assert.equal(buyOrder.status.pending !== undefined, true);
+    assert.isNull(await provider.connection.getAccountInfo(triggeredBuyPDA));
+    assert.equal(
+      (await program.account.tradingPool.fetch(tradingPoolPDA)).reservedLamports.toNumber(),
+      reservedBefore + solAmount
+    );
+    
+    // Cancelling the buy order releases the reservation it took over
+    await program.methods
+      .cancelBuyOrder()
+      .accounts({
+        ...solQuoteAccounts,
+        buyOrder: buyOrderPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        user: user1.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    assert.equal(
+      (await program.account.tradingPool.fetch(tradingPoolPDA)).reservedLamports.toNumber(),
+      reservedBefore
+    );
+  });
+
+  it("Pause and resume trading", async () => {
+    const solAmount = 1 * LAMPORTS_PER_SOL;
+    const maxPricePerShare = 1000000;
+    
+    // Pause the whole pool
+    const tx = await program.methods
+      .setPause(true, null)
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        stockMintInfo: null,
+        vaultAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    console.log("Set pause tx:", tx);
+    
+    let tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.equal(tradingPool.paused, true);
+    
+    const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user2.publicKey.toBuffer(),
+        (await nextOrderId(user2.publicKey)).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    const placeOrder = () => program.methods
+      .placeBuyOrder(stockSymbol, new anchor.BN(solAmount), new anchor.BN(maxPricePerShare), null)
+      .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user2.publicKey),
+        buyOrder: buyOrderPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        userEligibility: null,
+        user: user2.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user2])
+      .rpc();
+    
+    try {
+      await placeOrder();
+      assert.fail("Should have failed while paused");
+    } catch (error) {
+      assert.include(error.toString(), "TradingPaused");
+    }
+    
+    // Resume the pool but halt the symbol
+    await program.methods
+      .setPause(false, { halted: {} })
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        stockMintInfo: stockMintInfoPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    const stockMintInfo = await program.account.stockMintInfo.fetch(stockMintInfoPDA);
+    assert.equal(stockMintInfo.tradingStatus.halted !== undefined, true);
+    
+    try {
+      await placeOrder();
+      assert.fail("Should have failed while the symbol is halted");
+    } catch (error) {
+      assert.include(error.toString(), "SymbolNotTradable");
+    }
+    
+    // Reactivate the symbol
+    await program.methods
+      .setPause(null, { active: {} })
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        stockMintInfo: stockMintInfoPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.equal(tradingPool.paused, false);
+  });
+
+  it("Charge protocol fee on fulfillment and withdraw fees", async () => {
+    const feeBps = 30; // 0.3%
+    const solAmount = 1 * LAMPORTS_PER_SOL;
+    const maxPricePerShare = 1000000;
+    const sharesPurchased = 900;
+    const pricePerShare = 1000000;
+    const totalCost = sharesPurchased * pricePerShare;
+    const feeAmount = Math.floor(totalCost * feeBps / 10000);
+    const refundAmount = solAmount - totalCost - feeAmount;
+    
+    await program.methods
+      .setFeeConfig(feeBps, null)
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.equal(tradingPool.feeBps, feeBps);
+    const orderId = await nextOrderId(user1.publicKey);
+    
+    const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user1.publicKey.toBuffer(),
+        orderId.toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    const userStockTokenAccount = await getAssociatedTokenAddress(
+      stockMintPDA,
+      user1.publicKey
+    );
+    
+    await program.methods
+      .placeBuyOrder(stockSymbol, new anchor.BN(solAmount), new anchor.BN(maxPricePerShare), null)
+      .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user1.publicKey),
+        buyOrder: buyOrderPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        userEligibility: null,
+        user: user1.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    await publishPrice(pricePerShare);
+    
+    const feeVaultInitialBalance = await provider.connection.getBalance(feeVaultPDA);
+    
+    const fulfillTx = await program.methods
+      .fulfillBuyOrder(
+        new anchor.BN(sharesPurchased),
+        new anchor.BN(pricePerShare),
+        new anchor.BN(totalCost),
+        new anchor.BN(refundAmount)
+      )
+      .accounts({
+        ...solQuoteAccounts,
+        buyOrder: buyOrderPDA,
+        stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        priceFeed: priceFeedPDA,
+        reserveAttestation: findReserveAttestationPDA(stockSymbol),
+        userStockTokenAccount: userStockTokenAccount,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        feeVault: feeVaultPDA,
+        userEligibility: null,
+        user: user1.publicKey,
+        backendAuthority: backendAuthority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([backendAuthority])
+      .rpc();
+    
+    const receipt = await fetchReceipt(fulfillTx);
+    assert.equal(receipt.feesPaid.toNumber(), feeAmount);
+    
+    const feeVaultBalance = await provider.connection.getBalance(feeVaultPDA);
+    assert.equal(feeVaultBalance - feeVaultInitialBalance, feeAmount);
+    
+    // Fee authority (the vault authority by default) sweeps the fees
+    const tx = await program.methods
+      .withdrawFees(new anchor.BN(feeAmount))
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        feeVault: feeVaultPDA,
+        feeAuthority: vaultAuthority.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    console.log("Withdraw fees tx:", tx);
+    
+    const feeVaultFinalBalance = await provider.connection.getBalance(feeVaultPDA);
+    assert.equal(feeVaultFinalBalance, feeVaultInitialBalance);
+    
+    // Reset the fee for the remaining tests
+    await program.methods
+      .setFeeConfig(0, null)
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+  });
+
+  it("Buy and sell with an allowlisted quote token", async () => {
+    const usdcAmount = 100_000_000; // 100 USDC
+    const pricePerShare = 150_000; // 0.15 USDC per share
+    const sharesPurchased = 500;
+    const totalCost = sharesPurchased * pricePerShare; // 75 USDC
+    const refundAmount = usdcAmount - totalCost;
+    
+    const usdcMint = await createMint(
+      provider.connection,
+      vaultAuthority,
+      vaultAuthority.publicKey,
+      null,
+      6
+    );
+    const userUsdcAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      vaultAuthority,
+      usdcMint,
+      user1.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      vaultAuthority,
+      usdcMint,
+      userUsdcAccount.address,
+      vaultAuthority,
+      usdcAmount
+    );
+    
+    const [quoteMintConfigPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("quote_mint"), usdcMint.toBuffer()],
+      program.programId
+    );
+    const [quoteVaultPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("quote_vault"), usdcMint.toBuffer()],
+      program.programId
+    );
+    const [quoteFeeVaultPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("quote_fee_vault"), usdcMint.toBuffer()],
+      program.programId
+    );
+    const [usdcPriceFeedPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("price_feed"), Buffer.from(stockSymbol), usdcMint.toBuffer()],
+      program.programId
+    );
+    
+    const tx = await program.methods
+      .addQuoteMint()
+      .accounts({
+        quoteMintConfig: quoteMintConfigPDA,
+        quoteMint: usdcMint,
+        quoteVault: quoteVaultPDA,
+        quoteFeeVault: quoteFeeVaultPDA,
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    console.log("Add quote mint tx:", tx);
+    
+    await program.methods
+      .initializePriceFeed(stockSymbol, usdcMint)
+      .accounts({
+        priceFeed: usdcPriceFeedPDA,
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    // Buy with USDC escrowed in the quote vault
+    let tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user1.publicKey.toBuffer(),
+        (await nextOrderId(user1.publicKey)).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    await program.methods
+      .placeBuyOrder(stockSymbol, new anchor.BN(usdcAmount), new anchor.BN(pricePerShare), null)
+      .accounts({
+        userAccount: findUserAccountPDA(user1.publicKey),
+        buyOrder: buyOrderPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        quoteMintConfig: quoteMintConfigPDA,
+        quoteVault: quoteVaultPDA,
+        userQuoteTokenAccount: userUsdcAccount.address,
+        userEligibility: null,
+        user: user1.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    let quoteMintConfig = await program.account.quoteMintConfig.fetch(quoteMintConfigPDA);
+    assert.equal(quoteMintConfig.reservedAmount.toNumber(), usdcAmount);
+    const buyOrder = await program.account.buyOrder.fetch(buyOrderPDA);
+    assert.equal(buyOrder.quoteMint.toBase58(), usdcMint.toBase58());
+    
+    await publishPrice(pricePerShare, usdcPriceFeedPDA);
+    
+    const userStockTokenAccount = await getAssociatedTokenAddress(
+      stockMintPDA,
+      user1.publicKey
+    );
+    
+    await program.methods
+      .fulfillBuyOrder(
+        new anchor.BN(sharesPurchased),
+        new anchor.BN(pricePerShare),
+        new anchor.BN(totalCost),
+        new anchor.BN(refundAmount)
+      )
+      .accounts({
+        buyOrder: buyOrderPDA,
+        stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        priceFeed: usdcPriceFeedPDA,
+        reserveAttestation: findReserveAttestationPDA(stockSymbol),
+        userStockTokenAccount: userStockTokenAccount,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        feeVault: feeVaultPDA,
+        quoteMintConfig: quoteMintConfigPDA,
+        quoteVault: quoteVaultPDA,
+        quoteFeeVault: quoteFeeVaultPDA,
+        userQuoteTokenAccount: userUsdcAccount.address,
+        quoteTokenProgram: TOKEN_PROGRAM_ID,
+        userEligibility: null,
+        user: user1.publicKey,
+        backendAuthority: backendAuthority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([backendAuthority])
+      .rpc();
+    
+    // The refund came back in USDC and the cost stays in the quote vault
+    let userUsdc = await getAccount(provider.connection, userUsdcAccount.address);
+    assert.equal(Number(userUsdc.amount), refundAmount);
+    let quoteVault = await getAccount(provider.connection, quoteVaultPDA);
+    assert.equal(Number(quoteVault.amount), totalCost);
+    quoteMintConfig = await program.account.quoteMintConfig.fetch(quoteMintConfigPDA);
+    assert.equal(quoteMintConfig.reservedAmount.toNumber(), 0);
+    
+    // Sell part of the position for USDC proceeds
+    const sharesSold = 200;
+    const totalProceeds = sharesSold * pricePerShare;
+    
+    tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    const [sellOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("sell_order"),
+        user1.publicKey.toBuffer(),
+        (await nextOrderId(user1.publicKey)).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    const escrowPDA = findEscrowPDA(sellOrderPDA);
+    
+    await program.methods
+      .placeSellOrder(stockSymbol, new anchor.BN(sharesSold), new anchor.BN(pricePerShare), null)
+      .accounts({
+        userAccount: findUserAccountPDA(user1.publicKey),
+        sellOrder: sellOrderPDA,
+        stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
+        userStockTokenAccount: userStockTokenAccount,
+        escrowTokenAccount: escrowPDA,
+        tradingPool: tradingPoolPDA,
+        quoteMintConfig: quoteMintConfigPDA,
+        userEligibility: null,
+        user: user1.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    const sellTx = await program.methods
+      .fulfillSellOrder(
+        new anchor.BN(sharesSold),
+        new anchor.BN(pricePerShare),
+        new anchor.BN(totalProceeds),
+        new anchor.BN(0)
+      )
+      .accounts({
+        sellOrder: sellOrderPDA,
+        stockMint: stockMintPDA,
+        stockMintInfo: stockMintInfoPDA,
+        priceFeed: usdcPriceFeedPDA,
+        userStockTokenAccount: userStockTokenAccount,
+        escrowTokenAccount: escrowPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        feeVault: feeVaultPDA,
+        quoteMintConfig: quoteMintConfigPDA,
+        quoteVault: quoteVaultPDA,
+        quoteFeeVault: quoteFeeVaultPDA,
+        userQuoteTokenAccount: userUsdcAccount.address,
+        quoteTokenProgram: TOKEN_PROGRAM_ID,
+        userEligibility: null,
+        user: user1.publicKey,
+        backendAuthority: backendAuthority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([backendAuthority])
+      .rpc();
+    
+    userUsdc = await getAccount(provider.connection, userUsdcAccount.address);
+    assert.equal(Number(userUsdc.amount), refundAmount + totalProceeds);
+    const receipt = await fetchReceipt(sellTx);
+    assert.equal(receipt.quoteMint.toBase58(), usdcMint.toBase58());
+    assert.equal(receipt.quoteFilled.toNumber(), totalProceeds);
+    quoteVault = await getAccount(provider.connection, quoteVaultPDA);
+    assert.equal(Number(quoteVault.amount), totalCost - totalProceeds);
+    
+    // Once disabled, the token can no longer be used for new orders
+    await program.methods
+      .setQuoteMintEnabled(false)
+      .accounts({
+        quoteMintConfig: quoteMintConfigPDA,
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    const [rejectedOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user1.publicKey.toBuffer(),
+        (await nextOrderId(user1.publicKey)).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    try {
+      await program.methods
+        .placeBuyOrder(stockSymbol, new anchor.BN(refundAmount), new anchor.BN(pricePerShare), null)
+        .accounts({
+          userAccount: findUserAccountPDA(user1.publicKey),
+          buyOrder: rejectedOrderPDA,
+          stockMintInfo: stockMintInfoPDA,
+          tradingSchedule: tradingSchedulePDA,
+          tradingPool: tradingPoolPDA,
+          tradingPoolVault: tradingPoolVaultPDA,
+          quoteMintConfig: quoteMintConfigPDA,
+          quoteVault: quoteVaultPDA,
+          userQuoteTokenAccount: userUsdcAccount.address,
+          userEligibility: null,
+          user: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+      
+      assert.fail("Should have failed with a disabled quote mint");
+    } catch (error) {
+      assert.include(error.toString(), "QuoteMintDisabled");
+    }
+  });
+
+  it("Buy fractional shares of a stock with decimals", async () => {
+    const fractionalSymbol = "TSLA";
+    const decimals = 6;
+    const solAmount = 10_000_000;
+    const pricePerShare = 1_000_001; // Lamports per whole share
+    const sharesPurchased = 1_500_000; // 1.5 shares in base units
+    const totalCost = 1_500_002; // 1_500_001.5 lamports, rounded up
+    
+    const [fractionalMintPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("stock_mint"), Buffer.from(fractionalSymbol)],
+      program.programId
+    );
+    const [fractionalMintInfoPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("stock_mint_info"), Buffer.from(fractionalSymbol)],
+      program.programId
+    );
+    const [fractionalPriceFeedPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("price_feed"), Buffer.from(fractionalSymbol)],
+      program.programId
+    );
+    
+    await program.methods
+      .createStockMint(fractionalSymbol, decimals, "Tesla, Inc.", `${metadataBaseUri}/${fractionalSymbol}`)
+      .accounts({
+        stockMint: fractionalMintPDA,
+        stockMintInfo: fractionalMintInfoPDA,
+        tradingPool: tradingPoolPDA,
+        metadata: findMetadataPDA(fractionalMintPDA),
+        vaultAuthority: vaultAuthority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    const mintAccount = await getMint(provider.connection, fractionalMintPDA);
+    assert.equal(mintAccount.decimals, decimals);
+    const stockMintInfo = await program.account.stockMintInfo.fetch(fractionalMintInfoPDA);
+    assert.equal(stockMintInfo.decimals, decimals);
+    
+    await program.methods
+      .initializePriceFeed(fractionalSymbol, null)
+      .accounts({
+        priceFeed: fractionalPriceFeedPDA,
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    await attestReserves(fractionalSymbol, fullyBacked);
+    
+    const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user1.publicKey.toBuffer(),
+        (await nextOrderId(user1.publicKey)).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    await program.methods
+      .placeBuyOrder(fractionalSymbol, new anchor.BN(solAmount), new anchor.BN(pricePerShare), null)
+      .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user1.publicKey),
+        buyOrder: buyOrderPDA,
+        stockMintInfo: fractionalMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        userEligibility: null,
+        user: user1.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    await publishPrice(pricePerShare, fractionalPriceFeedPDA);
+    
+    const userStockTokenAccount = await getAssociatedTokenAddress(
+      fractionalMintPDA,
+      user1.publicKey
+    );
+    const fulfill = (cost: number) =>
+      program.methods
+        .fulfillBuyOrder(
+          new anchor.BN(sharesPurchased),
+          new anchor.BN(pricePerShare),
+          new anchor.BN(cost),
+          new anchor.BN(solAmount - cost)
+        )
+        .accounts({
+          ...solQuoteAccounts,
+          buyOrder: buyOrderPDA,
+          stockMint: fractionalMintPDA,
+          stockMintInfo: fractionalMintInfoPDA,
+          priceFeed: fractionalPriceFeedPDA,
+          reserveAttestation: findReserveAttestationPDA(fractionalSymbol),
+          userStockTokenAccount: userStockTokenAccount,
+          tradingPool: tradingPoolPDA,
+          tradingPoolVault: tradingPoolVaultPDA,
+          feeVault: feeVaultPDA,
+          userEligibility: null,
+          user: user1.publicKey,
+          backendAuthority: backendAuthority.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backendAuthority])
+        .rpc();
+    
+    // The fractional lamport rounds against the buyer
+    try {
+      await fulfill(totalCost - 1);
+      assert.fail("Should have failed with a rounded-down cost");
+    } catch (error) {
+      assert.include(error.toString(), "NotionalMismatch");
+    }
+    
+    const tx = await fulfill(totalCost);
+    
+    const receipt = await fetchReceipt(tx);
+    assert.equal(receipt.status.fulfilled !== undefined, true);
+    assert.equal(receipt.quoteFilled.toNumber(), totalCost);
+    const updatedTradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.equal(
+      updatedTradingPool.reservedLamports.toNumber(),
+      tradingPool.reservedLamports.toNumber()
+    );
+    
+    const userTokenAccount = await getAccount(provider.connection, userStockTokenAccount);
+    assert.equal(Number(userTokenAccount.amount), sharesPurchased);
+  });
+
+  it("Token-2022 stock mint with a transfer hook on eligibility grants", async () => {
+    const hookedSymbol = "NVDA";
+    const solAmount = 1_000_000_000;
+    const pricePerShare = 1_000_000;
+    const sharesPurchased = 1000;
+    const sharesToSell = 400;
+    
+    const [hookedMintPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("stock_mint"), Buffer.from(hookedSymbol)],
+      program.programId
+    );
+    const [hookedMintInfoPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("stock_mint_info"), Buffer.from(hookedSymbol)],
+      program.programId
+    );
+    const [hookedPriceFeedPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("price_feed"), Buffer.from(hookedSymbol)],
+      program.programId
+    );
+    const [hookConfigPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("hook_config")],
+      hookProgram.programId
+    );
+    const [extraAccountMetaListPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("extra-account-metas"), hookedMintPDA.toBuffer()],
+      hookProgram.programId
+    );
+    // The hook reads the same grants the compliance authority keeps for orders
+    const grantEligibility = (holder: PublicKey) => program.methods
+      .grantEligibility(holder, Array.from(Buffer.from("US")), null)
+      .accounts({
+        userEligibility: findUserEligibilityPDA(holder),
+        tradingPool: tradingPoolPDA,
+        complianceAuthority: vaultAuthority.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    const revokeEligibility = (holder: PublicKey) => program.methods
+      .revokeEligibility()
+      .accounts({
+        userEligibility: findUserEligibilityPDA(holder),
+        tradingPool: tradingPoolPDA,
+        complianceAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    // Accounts Token-2022 needs to invoke the hook for a transfer to `holder`
+    const hookAccountsFor = (holder: PublicKey) => [
+      { pubkey: hookProgram.programId, isSigner: false, isWritable: false },
+      { pubkey: extraAccountMetaListPDA, isSigner: false, isWritable: false },
+      { pubkey: program.programId, isSigner: false, isWritable: false },
+      { pubkey: tradingPoolPDA, isSigner: false, isWritable: false },
+      { pubkey: findUserEligibilityPDA(holder), isSigner: false, isWritable: false },
+    ];
+    
+    // Only the hook program's upgrade authority, the provider wallet, may set it up
+    const [hookProgramDataPDA] = PublicKey.findProgramAddressSync(
+      [hookProgram.programId.toBuffer()],
+      new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+    try {
+      await hookProgram.methods
+        .initializeHookConfig(user2.publicKey)
+        .accounts({
+          hookConfig: hookConfigPDA,
+          program: hookProgram.programId,
+          programData: hookProgramDataPDA,
+          payer: user2.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user2])
+        .rpc();
+      assert.fail("Should have failed for a non-upgrade authority");
+    } catch (error) {
+      assert.include(error.toString(), "UnauthorizedAuthority");
+    }
+    
+    await hookProgram.methods
+      .initializeHookConfig(vaultAuthority.publicKey)
+      .accounts({
+        hookConfig: hookConfigPDA,
+        program: hookProgram.programId,
+        programData: hookProgramDataPDA,
+        payer: provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+    
+    const tx = await program.methods
+      .createToken2022StockMint(
+        hookedSymbol,
+        0,
+        "NVIDIA Corporation",
+        `${metadataBaseUri}/${hookedSymbol}`
+      )
+      .accounts({
+        stockMint: hookedMintPDA,
+        stockMintInfo: hookedMintInfoPDA,
+        tradingPool: tradingPoolPDA,
+        metadata: findMetadataPDA(hookedMintPDA),
+        transferHookProgram: hookProgram.programId,
+        vaultAuthority: vaultAuthority.publicKey,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    console.log("Create Token-2022 stock mint tx:", tx);
+    
+    const mintAccount = await getMint(
+      provider.connection,
+      hookedMintPDA,
+      "confirmed",
+      TOKEN_2022_PROGRAM_ID
+    );
+    assert.equal(mintAccount.mintAuthority?.toBase58(), tradingPoolPDA.toBase58());
+    
+    await hookProgram.methods
+      .initializeExtraAccountMetaList()
+      .accounts({
+        extraAccountMetaList: extraAccountMetaListPDA,
+        mint: hookedMintPDA,
+        hookConfig: hookConfigPDA,
+        authority: vaultAuthority.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    await grantEligibility(user1.publicKey);
+    
+    await program.methods
+      .initializePriceFeed(hookedSymbol, null)
+      .accounts({
+        priceFeed: hookedPriceFeedPDA,
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    await attestReserves(hookedSymbol, fullyBacked);
+    
+    // Buy: minting does not invoke the hook
+    let tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user1.publicKey.toBuffer(),
+        (await nextOrderId(user1.publicKey)).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    await program.methods
+      .placeBuyOrder(hookedSymbol, new anchor.BN(solAmount), new anchor.BN(pricePerShare), null)
+      .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user1.publicKey),
+        buyOrder: buyOrderPDA,
+        stockMintInfo: hookedMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        userEligibility: null,
+        user: user1.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    await publishPrice(pricePerShare, hookedPriceFeedPDA);
+    
+    const userStockTokenAccount = getAssociatedTokenAddressSync(
+      hookedMintPDA,
+      user1.publicKey,
+      false,
+      TOKEN_2022_PROGRAM_ID
+    );
+    
+    await program.methods
+      .fulfillBuyOrder(
+        new anchor.BN(sharesPurchased),
+        new anchor.BN(pricePerShare),
+        new anchor.BN(sharesPurchased * pricePerShare),
+        new anchor.BN(0)
+      )
+      .accounts({
+        ...solQuoteAccounts,
+        buyOrder: buyOrderPDA,
+        stockMint: hookedMintPDA,
+        stockMintInfo: hookedMintInfoPDA,
+        priceFeed: hookedPriceFeedPDA,
+        reserveAttestation: findReserveAttestationPDA(hookedSymbol),
+        userStockTokenAccount: userStockTokenAccount,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        feeVault: feeVaultPDA,
+        userEligibility: null,
+        user: user1.publicKey,
+        backendAuthority: backendAuthority.publicKey,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([backendAuthority])
+      .rpc();
+    
+    let userTokenAccount = await getAccount(
+      provider.connection,
+      userStockTokenAccount,
+      "confirmed",
+      TOKEN_2022_PROGRAM_ID
+    );
+    assert.equal(Number(userTokenAccount.amount), sharesPurchased);
+    
+    // Sell: the escrow belongs to the trading pool, which the hook lets
+    // through without a grant even while eligibility is enforced
+    await setEnforcement(true);
+    tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    const [sellOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("sell_order"),
+        user1.publicKey.toBuffer(),
+        (await nextOrderId(user1.publicKey)).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    const hookedEscrowPDA = findEscrowPDA(sellOrderPDA);
+    
+    await program.methods
+      .placeSellOrder(hookedSymbol, new anchor.BN(sharesToSell), new anchor.BN(pricePerShare), null)
+      .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user1.publicKey),
+        sellOrder: sellOrderPDA,
+        stockMint: hookedMintPDA,
+        stockMintInfo: hookedMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
+        userStockTokenAccount: userStockTokenAccount,
+        escrowTokenAccount: hookedEscrowPDA,
+        tradingPool: tradingPoolPDA,
+        userEligibility: findUserEligibilityPDA(user1.publicKey),
+        user: user1.publicKey,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(hookAccountsFor(tradingPoolPDA))
+      .signers([user1])
+      .rpc();
+    
+    const escrowAccount = await getAccount(
+      provider.connection,
+      hookedEscrowPDA,
+      "confirmed",
+      TOKEN_2022_PROGRAM_ID
+    );
+    assert.equal(Number(escrowAccount.amount), sharesToSell);
+    
+    // Cancelling returns the shares through the hook to the user's grant
+    await program.methods
+      .cancelSellOrder()
+      .accounts({
+        sellOrder: sellOrderPDA,
+        stockMint: hookedMintPDA,
+        userStockTokenAccount: userStockTokenAccount,
+        escrowTokenAccount: hookedEscrowPDA,
+        tradingPool: tradingPoolPDA,
+        user: user1.publicKey,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+      })
+      .remainingAccounts(hookAccountsFor(user1.publicKey))
+      .signers([user1])
+      .rpc();
+    assert.isNull(await provider.connection.getAccountInfo(hookedEscrowPDA));
+    
+    userTokenAccount = await getAccount(
+      provider.connection,
+      userStockTokenAccount,
+      "confirmed",
+      TOKEN_2022_PROGRAM_ID
+    );
+    assert.equal(Number(userTokenAccount.amount), sharesPurchased);
+    
+    // Batch sell: each fill carries the user's grant while eligibility is
+    // enforced, followed by the hook accounts for returning unsold shares
+    const batchSharesToSell = [300, 100];
+    const batchSharesSold = [200, 100];
+    const batchSellOrderPDAs: PublicKey[] = [];
+    const batchEscrowPDAs: PublicKey[] = [];
+    for (const shares of batchSharesToSell) {
+      const [batchSellOrderPDA] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("sell_order"),
+          user1.publicKey.toBuffer(),
+          (await nextOrderId(user1.publicKey)).toArrayLike(Buffer, "le", 8)
+        ],
+        program.programId
+      );
+      const batchEscrowPDA = findEscrowPDA(batchSellOrderPDA);
+      
+      await program.methods
+        .placeSellOrder(hookedSymbol, new anchor.BN(shares), new anchor.BN(pricePerShare), null)
+        .accounts({
+          ...solQuoteAccounts,
+          userAccount: findUserAccountPDA(user1.publicKey),
+          sellOrder: batchSellOrderPDA,
+          stockMint: hookedMintPDA,
+          stockMintInfo: hookedMintInfoPDA,
+          tradingSchedule: tradingSchedulePDA,
+          userStockTokenAccount: userStockTokenAccount,
+          escrowTokenAccount: batchEscrowPDA,
+          tradingPool: tradingPoolPDA,
+          userEligibility: findUserEligibilityPDA(user1.publicKey),
+          user: user1.publicKey,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(hookAccountsFor(tradingPoolPDA))
+        .signers([user1])
+        .rpc();
+      
+      batchSellOrderPDAs.push(batchSellOrderPDA);
+      batchEscrowPDAs.push(batchEscrowPDA);
+    }
+    
+    // The first order is partially sold and returns the rest through the
+    // hook; the second is sold in full and needs no hook accounts
+    const sellFills = batchSharesSold.map((shares, i) => ({
+      sharesSold: new anchor.BN(shares),
+      pricePerShare: new anchor.BN(pricePerShare),
+      totalProceeds: new anchor.BN(shares * pricePerShare),
+      sharesReturned: new anchor.BN(batchSharesToSell[i] - shares),
+      hookAccountCount: batchSharesToSell[i] > shares ? 5 : 0,
+    }));
+    const sellRemainingAccounts = [];
+    for (let i = 0; i < sellFills.length; i++) {
+      sellRemainingAccounts.push(
+        { pubkey: batchSellOrderPDAs[i], isSigner: false, isWritable: true },
+        { pubkey: user1.publicKey, isSigner: false, isWritable: true },
+        { pubkey: userStockTokenAccount, isSigner: false, isWritable: true },
+        { pubkey: batchEscrowPDAs[i], isSigner: false, isWritable: true },
+        { pubkey: findUserEligibilityPDA(user1.publicKey), isSigner: false, isWritable: false },
+        ...(sellFills[i].hookAccountCount > 0 ? hookAccountsFor(user1.publicKey) : [])
+      );
+    }
+    
+    const hookedSupplyBefore = (await program.account.stockMintInfo.fetch(hookedMintInfoPDA))
+      .totalSupply.toNumber();
+    const user1BalanceBefore = await provider.connection.getBalance(user1.publicKey);
+    let batchRentReturned = 0;
+    for (let i = 0; i < sellFills.length; i++) {
+      batchRentReturned += await provider.connection.getBalance(batchSellOrderPDAs[i]);
+      batchRentReturned += await provider.connection.getBalance(batchEscrowPDAs[i]);
+    }
+    
+    await publishPrice(pricePerShare, hookedPriceFeedPDA);
+    
+    const batchTx = await program.methods
+      .fulfillSellOrdersBatch(hookedSymbol, null, sellFills)
+      .accounts({
+        stockMint: hookedMintPDA,
+        stockMintInfo: hookedMintInfoPDA,
+        priceFeed: hookedPriceFeedPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        feeVault: feeVaultPDA,
+        quoteMintConfig: null,
+        quoteVault: null,
+        quoteFeeVault: null,
+        quoteTokenProgram: null,
+        backendAuthority: backendAuthority.publicKey,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(sellRemainingAccounts)
+      .signers([backendAuthority])
+      .rpc();
+    
+    console.log("Fulfill sell orders batch tx:", batchTx);
+    
+    // Both orders and their escrows are closed, sold shares are burned and
+    // the unsold ones are back with the user
+    for (let i = 0; i < sellFills.length; i++) {
+      assert.isNull(await program.account.sellOrder.fetchNullable(batchSellOrderPDAs[i]));
+      assert.isNull(await provider.connection.getAccountInfo(batchEscrowPDAs[i]));
+    }
+    
+    const totalBatchSold = batchSharesSold[0] + batchSharesSold[1];
+    userTokenAccount = await getAccount(
+      provider.connection,
+      userStockTokenAccount,
+      "confirmed",
+      TOKEN_2022_PROGRAM_ID
+    );
+    assert.equal(Number(userTokenAccount.amount), sharesPurchased - totalBatchSold);
+    
+    const hookedMintInfo = await program.account.stockMintInfo.fetch(hookedMintInfoPDA);
+    assert.equal(hookedMintInfo.totalSupply.toNumber(), hookedSupplyBefore - totalBatchSold);
+    
+    const user1BalanceAfter = await provider.connection.getBalance(user1.publicKey);
+    assert.equal(
+      user1BalanceAfter - user1BalanceBefore,
+      totalBatchSold * pricePerShare + batchRentReturned
+    );
+    
+    // Transfers outside the program only reach eligible holders while
+    // eligibility is enforced
+    const user2StockTokenAccount = await createAssociatedTokenAccountIdempotent(
+      provider.connection,
+      user2,
+      hookedMintPDA,
+      user2.publicKey,
+      {},
+      TOKEN_2022_PROGRAM_ID
+    );
+    const transferToUser2 = async () => {
+      const transferIx = await createTransferCheckedWithTransferHookInstruction(
+        provider.connection,
+        userStockTokenAccount,
+        hookedMintPDA,
+        user2StockTokenAccount,
+        user1.publicKey,
+        BigInt(100),
+        0,
+        [],
+        "confirmed",
+        TOKEN_2022_PROGRAM_ID
+      );
+      await sendAndConfirmTransaction(
+        provider.connection,
+        new Transaction().add(transferIx),
+        [user1]
+      );
+    };
+    
+    try {
+      await transferToUser2();
+      assert.fail("Should have failed with an ungranted holder");
+    } catch (error) {
+      assert.include(error.toString(), "0x1771"); // HolderNotEligible
+    }
+    
+    await grantEligibility(user2.publicKey);
+    await transferToUser2();
+    
+    await revokeEligibility(user2.publicKey);
+    try {
+      await transferToUser2();
+      assert.fail("Should have failed with a revoked holder");
+    } catch (error) {
+      assert.include(error.toString(), "0x1771"); // HolderNotEligible
+    }
+    
+    // Without enforcement any wallet can receive shares
+    await setEnforcement(false);
+    await transferToUser2();
+    
+    const user2TokenAccount = await getAccount(
+      provider.connection,
+      user2StockTokenAccount,
+      "confirmed",
+      TOKEN_2022_PROGRAM_ID
+    );
+    assert.equal(Number(user2TokenAccount.amount), 200);
+  });
+
+  it("Apply a stock split", async () => {
+    const splitSymbol = "TSLA";
+    const sharesToSell = 500_000; // 0.5 tokens, one whole share each before the split
+    const minPricePerShare = 1_000_000;
+    const postSplitPrice = 500_000; // Lamports per whole share after a 2-for-1 split
+    const totalProceeds = 500_000; // 0.5 tokens x 2 shares per token x 500_000
+    
+    const [splitMintPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("stock_mint"), Buffer.from(splitSymbol)],
+      program.programId
+    );
+    const [splitMintInfoPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("stock_mint_info"), Buffer.from(splitSymbol)],
+      program.programId
+    );
+    const [splitPriceFeedPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("price_feed"), Buffer.from(splitSymbol)],
+      program.programId
+    );
+    const userStockTokenAccount = await getAssociatedTokenAddress(
+      splitMintPDA,
+      user1.publicKey
+    );
+    
+    const [sellOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("sell_order"),
+        user1.publicKey.toBuffer(),
+        (await nextOrderId(user1.publicKey)).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    const escrowTokenAccount = findEscrowPDA(sellOrderPDA);
+    
+    await program.methods
+      .placeSellOrder(splitSymbol, new anchor.BN(sharesToSell), new anchor.BN(minPricePerShare), null)
+      .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user1.publicKey),
+        sellOrder: sellOrderPDA,
+        stockMint: splitMintPDA,
+        stockMintInfo: splitMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
+        userStockTokenAccount: userStockTokenAccount,
+        escrowTokenAccount: escrowTokenAccount,
+        tradingPool: tradingPoolPDA,
+        userEligibility: null,
+        user: user1.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    const applySplit = () => program.methods
+      .applyStockSplit(new anchor.BN(2), new anchor.BN(1))
+      .accounts({
+        stockMintInfo: splitMintInfoPDA,
+        orderBook: findOrderBookPDA(splitSymbol),
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    const setStatus = (tradingStatus: object) => program.methods
+      .setPause(null, tradingStatus)
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        stockMintInfo: splitMintInfoPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    // Fills must not straddle the old and new ratio
+    try {
+      await applySplit();
+      assert.fail("Should have failed while the symbol is active");
+    } catch (error) {
+      assert.include(error.toString(), "SymbolNotHalted");
+    }
+    
+    await setStatus({ halted: {} });
+    const tx = await applySplit();
+    console.log("Apply stock split tx:", tx);
+    await setStatus({ active: {} });
+    
+    const stockMintInfo = await program.account.stockMintInfo.fetch(splitMintInfoPDA);
+    assert.equal(stockMintInfo.splitNumerator.toNumber(), 2);
+    assert.equal(stockMintInfo.splitDenominator.toNumber(), 1);
+    
+    // Balances and escrow are untouched; each token now stands for two shares
+    const escrowAccount = await getAccount(provider.connection, escrowTokenAccount);
+    assert.equal(Number(escrowAccount.amount), sharesToSell);
+    
+    await publishPrice(postSplitPrice, splitPriceFeedPDA);
+    
+    const fulfill = (pricePerShare: number, proceeds: number) =>
+      program.methods
+        .fulfillSellOrder(
+          new anchor.BN(sharesToSell),
+          new anchor.BN(pricePerShare),
+          new anchor.BN(proceeds),
+          new anchor.BN(0)
+        )
+        .accounts({
+          ...solQuoteAccounts,
+          sellOrder: sellOrderPDA,
+          stockMint: splitMintPDA,
+          stockMintInfo: splitMintInfoPDA,
+          priceFeed: splitPriceFeedPDA,
+          userStockTokenAccount: userStockTokenAccount,
+          escrowTokenAccount: escrowTokenAccount,
+          tradingPool: tradingPoolPDA,
+          tradingPoolVault: tradingPoolVaultPDA,
+          feeVault: feeVaultPDA,
+          userEligibility: null,
+          user: user1.publicKey,
+          backendAuthority: backendAuthority.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backendAuthority])
+        .rpc();
+    
+    // The pre-split minimum halves along with the share price
+    try {
+      await fulfill(postSplitPrice - 1_000, 499_000);
+      assert.fail("Should have failed below the converted minimum");
+    } catch (error) {
+      assert.include(error.toString(), "PriceBelowMinimum");
+    }
+    
+    const userInitialBalance = await provider.connection.getBalance(user1.publicKey);
+    const rentReturned =
+      (await provider.connection.getBalance(sellOrderPDA)) +
+      (await provider.connection.getBalance(escrowTokenAccount));
+    const tx = await fulfill(postSplitPrice, totalProceeds);
+    
+    const receipt = await fetchReceipt(tx);
+    assert.equal(receipt.status.fulfilled !== undefined, true);
+    assert.equal(receipt.quoteFilled.toNumber(), totalProceeds);
+    
+    const userFinalBalance = await provider.connection.getBalance(user1.publicKey);
+    assert.equal(userFinalBalance - userInitialBalance, totalProceeds + rentReturned);
+    
+    const updatedStockMintInfo = await program.account.stockMintInfo.fetch(splitMintInfoPDA);
+    assert.equal(
+      updatedStockMintInfo.totalSupply.toNumber(),
+      stockMintInfo.totalSupply.toNumber() - sharesToSell
+    );
+  });
+
+  it("Deposit vault funds", async () => {
+    const depositAmount = 5 * LAMPORTS_PER_SOL; // 5 SOL
+    
+    const vaultInitialBalance = await provider.connection.getBalance(tradingPoolVaultPDA);
+    
+    const tx = await program.methods
+      .depositVaultFunds(new anchor.BN(depositAmount))
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    console.log("Deposit vault funds tx:", tx);
+    
+    const vaultFinalBalance = await provider.connection.getBalance(tradingPoolVaultPDA);
+    assert.equal(vaultFinalBalance - vaultInitialBalance, depositAmount);
+  });
+
+  it("Report vault balances", async () => {
+    const balances = await program.methods
+      .getVaultBalances()
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+      })
+      .view();
+    
+    const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    const vaultBalance = await provider.connection.getBalance(tradingPoolVaultPDA);
+    const rentExemptMinimum = await provider.connection.getMinimumBalanceForRentExemption(0);
+    
+    assert.equal(balances.totalLamports.toNumber(), vaultBalance);
+    assert.equal(balances.reservedLamports.toNumber(), tradingPool.reservedLamports.toNumber());
+    assert.equal(balances.rentExemptMinimum.toNumber(), rentExemptMinimum);
+    assert.equal(
+      balances.freeLamports.toNumber(),
+      vaultBalance - rentExemptMinimum - tradingPool.reservedLamports.toNumber()
+    );
+  });
+
+  it("Distribute a dividend through Merkle claims", async () => {
+    const user3 = Keypair.generate();
+    const claims = [
+      { holder: user1.publicKey, amount: 100_000 },
+      { holder: user2.publicKey, amount: 50_000 },
+      { holder: user3.publicKey, amount: 25_000 },
+    ];
+    const totalAmount = 175_000;
+    const { root, proofs } = buildDividendTree(claims);
+    
+    const blockTime = await waitForBlockTimeAfter(0);
+    const recordDate = blockTime - 1;
+    const expiresAt = blockTime + 10;
+    const [distributionPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("distribution"),
+        Buffer.from(stockSymbol),
+        new anchor.BN(recordDate).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    const tradingPoolBefore = await program.account.tradingPool.fetch(tradingPoolPDA);
+    
+    const createDistribution = () => program.methods
+      .createDistribution(
+        stockSymbol,
+        new anchor.BN(recordDate),
+        root,
+        new anchor.BN(totalAmount),
+        claims.length,
+        new anchor.BN(expiresAt),
+        null
+      )
+      .accounts({
+        distribution: distributionPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        quoteMintConfig: null,
+        quoteVault: null,
+        vaultAuthority: vaultAuthority.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    const tx = await createDistribution();
+    
+    console.log("Create distribution tx:", tx);
+    
+    // The dividend is earmarked in the vault until claimed or expired
+    let tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.equal(
+      tradingPool.reservedLamports.toNumber(),
+      tradingPoolBefore.reservedLamports.toNumber() + totalAmount
+    );
+    
+    const claim = (index: number, amount: number, holder: Keypair) =>
+      program.methods
+        .claimDividend(index, new anchor.BN(amount), proofs[index])
+        .accounts({
+          distribution: distributionPDA,
+          tradingPool: tradingPoolPDA,
+          tradingPoolVault: tradingPoolVaultPDA,
+          quoteMintConfig: null,
+          quoteVault: null,
+          holderQuoteTokenAccount: null,
+          holder: holder.publicKey,
+          tokenProgram: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([holder])
+        .rpc();
+    
+    // Claims only open after the withdrawal delay, so holders can check the root first
+    const { claimableAt } = await program.account.distribution.fetch(distributionPDA);
+    try {
+      await claim(0, claims[0].amount, user1);
+      assert.fail("Should have failed before claims open");
+    } catch (error) {
+      assert.include(error.toString(), "DistributionNotClaimable");
+    }
+    await waitForBlockTimeAfter(claimableAt.toNumber() - 1);
+    
+    try {
+      await claim(0, claims[0].amount * 2, user1);
+      assert.fail("Should have failed with an inflated amount");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidMerkleProof");
+    }
+    
+    try {
+      await claim(0, claims[0].amount, user2);
+      assert.fail("Should have failed for another holder's leaf");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidMerkleProof");
+    }
+    
+    const user1InitialBalance = await provider.connection.getBalance(user1.publicKey);
+    await claim(0, claims[0].amount, user1);
+    const user1FinalBalance = await provider.connection.getBalance(user1.publicKey);
+    assert.equal(user1FinalBalance - user1InitialBalance, claims[0].amount);
+    
+    try {
+      await claim(0, claims[0].amount, user1);
+      assert.fail("Should have failed on a second claim");
+    } catch (error) {
+      assert.include(error.toString(), "DividendAlreadyClaimed");
+    }
+    
+    await claim(1, claims[1].amount, user2);
+    
+    const distribution = await program.account.distribution.fetch(distributionPDA);
+    assert.equal(distribution.claimedAmount.toNumber(), claims[0].amount + claims[1].amount);
+    assert.deepEqual(Array.from(distribution.merkleRoot), root);
+    
+    const reclaim = (cranker: Keypair = user2) => program.methods
+      .reclaimExpiredDistribution()
+      .accounts({
+        distribution: distributionPDA,
+        tradingPool: tradingPoolPDA,
+        quoteMintConfig: null,
+        cranker: cranker.publicKey,
+      })
+      .signers([cranker])
+      .rpc();
+    
+    try {
+      await reclaim();
+      assert.fail("Should have failed before expiry");
+    } catch (error) {
+      assert.include(error.toString(), "DistributionNotExpired");
+    }
+    
+    // user3 misses the window; the unclaimed amount goes back to the free balance
+    await waitForBlockTimeAfter(expiresAt);
+    try {
+      await claim(2, claims[2].amount, user3);
+      assert.fail("Should have failed after expiry");
+    } catch (error) {
+      assert.include(error.toString(), "DistributionExpired");
+    }
+    
+    await reclaim();
+    
+    tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.equal(
+      tradingPool.reservedLamports.toNumber(),
+      tradingPoolBefore.reservedLamports.toNumber()
+    );
+    
+    // The distribution stays behind as a tombstone, so it can neither be
+    // reclaimed twice nor recreated to replay its proofs
+    const reclaimed = await program.account.distribution.fetch(distributionPDA);
+    assert.isTrue(reclaimed.reclaimed);
+    
+    try {
+      await reclaim(user1);
+      assert.fail("Should have failed after the reclaim");
+    } catch (error) {
+      assert.include(error.toString(), "DistributionReclaimed");
+    }
+    
+    try {
+      await createDistribution();
+      assert.fail("Should have failed for an existing distribution");
+    } catch (error) {
+      assert.include(error.toString(), "already in use");
+    }
+  });
+
+  it("Queue and execute vault withdrawal", async () => {
+    const withdrawAmount = 1 * LAMPORTS_PER_SOL; // 1 SOL
+    
+    const tx = await program.methods
+      .queueVaultWithdrawal(new anchor.BN(withdrawAmount), null)
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    console.log("Queue vault withdrawal tx:", tx);
+    
+    let tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    const queued = tradingPool.pendingWithdrawal;
+    assert.equal(queued.amount.toNumber(), withdrawAmount);
+    assert.equal(queued.destination.toBase58(), vaultAuthority.publicKey.toBase58());
+    
+    const executeAccounts = {
+      ...solQuoteAccounts,
+      tradingPool: tradingPoolPDA,
+      tradingPoolVault: tradingPoolVaultPDA,
+      destination: vaultAuthority.publicKey,
+      executor: provider.wallet.publicKey,
+      systemProgram: SystemProgram.programId,
+    };
+    
+    // Funds stay in the vault until the delay has passed
+    try {
+      await program.methods
+        .executeVaultWithdrawal()
+        .accounts(executeAccounts)
+        .rpc();
+      
+      assert.fail("Should have failed before the withdrawal delay");
+    } catch (error) {
+      assert.include(error.toString(), "WithdrawalTimelockActive");
+    }
+    
+    await waitForBlockTimeAfter(queued.executableAt.toNumber());
+    
+    const vaultInitialBalance = await provider.connection.getBalance(tradingPoolVaultPDA);
+    const authorityInitialBalance = await provider.connection.getBalance(vaultAuthority.publicKey);
+    
+    // Anyone can execute a matured withdrawal
+    await program.methods
+      .executeVaultWithdrawal()
+      .accounts(executeAccounts)
+      .rpc();
+    
+    const vaultFinalBalance = await provider.connection.getBalance(tradingPoolVaultPDA);
+    const authorityFinalBalance = await provider.connection.getBalance(vaultAuthority.publicKey);
+    
+    assert.equal(vaultInitialBalance - vaultFinalBalance, withdrawAmount);
+    assert.equal(authorityFinalBalance - authorityInitialBalance, withdrawAmount);
+    
+    tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.isNull(tradingPool.pendingWithdrawal);
+    
+    // The delay can be raised but never lowered
+    try {
+      await program.methods
+        .setWithdrawalDelay(new anchor.BN(withdrawalDelay - 1))
+        .accounts({
+          tradingPool: tradingPoolPDA,
+          vaultAuthority: vaultAuthority.publicKey,
+        })
+        .signers([vaultAuthority])
+        .rpc();
+      
+      assert.fail("Should have failed lowering the delay");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidWithdrawalDelay");
+    }
+    
+    // ...and is capped at 30 days
+    try {
+      await program.methods
+        .setWithdrawalDelay(new anchor.BN(30 * 86400 + 1))
+        .accounts({
+          tradingPool: tradingPoolPDA,
+          vaultAuthority: vaultAuthority.publicKey,
+        })
+        .signers([vaultAuthority])
+        .rpc();
+      
+      assert.fail("Should have failed above the maximum delay");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidWithdrawalDelay");
+    }
+  });
+
+  it("Cancel queued vault withdrawal", async () => {
+    // Asking for the whole vault would dip into reserved SOL and the rent floor
+    const vaultBalance = await provider.connection.getBalance(tradingPoolVaultPDA);
+    
+    await program.methods
+      .queueVaultWithdrawal(new anchor.BN(vaultBalance), null)
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    const { pendingWithdrawal } = await program.account.tradingPool.fetch(tradingPoolPDA);
+    await waitForBlockTimeAfter(pendingWithdrawal.executableAt.toNumber());
+    
+    try {
+      await program.methods
+        .executeVaultWithdrawal()
+        .accounts({
+          ...solQuoteAccounts,
+          tradingPool: tradingPoolPDA,
+          tradingPoolVault: tradingPoolVaultPDA,
+          destination: vaultAuthority.publicKey,
+          executor: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      
+      assert.fail("Should have failed withdrawing more than the free balance");
+    } catch (error) {
+      assert.include(error.toString(), "InsufficientVaultFunds");
+    }
+    
+    const tx = await program.methods
+      .cancelVaultWithdrawal()
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    console.log("Cancel vault withdrawal tx:", tx);
+    
+    const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.isNull(tradingPool.pendingWithdrawal);
+  });
+
+  it("Update authorities", async () => {
+    const newVaultAuthority = rotatedVaultAuthority;
+    const newBackendAuthority = rotatedBackendAuthority;
+    
+    const tx = await program.methods
+      .proposeAuthorityTransfer(
+        newVaultAuthority.publicKey,
+        newBackendAuthority.publicKey
+      )
+      .accounts({
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: vaultAuthority.publicKey,
+      })
+      .signers([vaultAuthority])
+      .rpc();
+    
+    console.log("Propose authority transfer tx:", tx);
+    
+    // Nothing changes until the new authorities accept
+    let tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.equal(tradingPool.vaultAuthority.toBase58(), vaultAuthority.publicKey.toBase58());
+    assert.equal(tradingPool.pendingVaultAuthority.toBase58(), newVaultAuthority.publicKey.toBase58());
+    
+    // Only a pending authority can accept
+    try {
+      await program.methods
+        .acceptAuthority()
+        .accounts({
+          tradingPool: tradingPoolPDA,
+          newAuthority: user1.publicKey,
+        })
+        .signers([user1])
+        .rpc();
+      
+      assert.fail("Should have failed for a key without a pending transfer");
+    } catch (error) {
+      assert.include(error.toString(), "NoPendingAuthority");
+    }
+    
+    for (const newAuthority of [newVaultAuthority, newBackendAuthority]) {
+      await program.methods
+        .acceptAuthority()
+        .accounts({
+          tradingPool: tradingPoolPDA,
+          newAuthority: newAuthority.publicKey,
+        })
+        .signers([newAuthority])
+        .rpc();
+    }
+    
+    // Verify authorities were updated
+    tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.equal(tradingPool.vaultAuthority.toBase58(), newVaultAuthority.publicKey.toBase58());
+    assert.equal(tradingPool.backendAuthority.toBase58(), newBackendAuthority.publicKey.toBase58());
+    assert.isNull(tradingPool.pendingVaultAuthority);
+    assert.isNull(tradingPool.pendingBackendAuthority);
+  });
+
+  it("Should fail: unauthorized backend trying to fulfill order", async () => {
+    // First create a new buy order
+    const solAmount = 1 * LAMPORTS_PER_SOL;
+    const maxPricePerShare = 1000000;
+    
+    const orderId = await nextOrderId(user2.publicKey);
+    
+    const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user2.publicKey.toBuffer(),
+        orderId.toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    // Place order
+    await program.methods
+      .placeBuyOrder(stockSymbol, new anchor.BN(solAmount), new anchor.BN(maxPricePerShare), null)
+      .accounts({
+        ...solQuoteAccounts,
+        userAccount: findUserAccountPDA(user2.publicKey),
+        buyOrder: buyOrderPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingSchedule: tradingSchedulePDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        userEligibility: null,
+        user: user2.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user2])
+      .rpc();
+    
+    // Try to fulfill with wrong authority (using old backend authority)
+    const userStockTokenAccount = await getAssociatedTokenAddress(
+      stockMintPDA,
+      user2.publicKey
+    );
+    
+    try {
+      await program.methods
+        .fulfillBuyOrder(
+          new anchor.BN(1000),
+          new anchor.BN(1000000),
+          new anchor.BN(1000000000),
+          new anchor.BN(0)
+        )
+        .accounts({
+          ...solQuoteAccounts,
           buyOrder: buyOrderPDA,
           stockMint: stockMintPDA,
           stockMintInfo: stockMintInfoPDA,
+          priceFeed: priceFeedPDA,
+          reserveAttestation: findReserveAttestationPDA(stockSymbol),
           userStockTokenAccount: userStockTokenAccount,
           tradingPool: tradingPoolPDA,
           tradingPoolVault: tradingPoolVaultPDA,
+          feeVault: feeVaultPDA,
+          userEligibility: null,
           user: user2.publicKey,
           backendAuthority: backendAuthority.publicKey, // Old backend authority
           tokenProgram: TOKEN_PROGRAM_ID,
@@ -543,7 +4444,7 @@ describe("stock_contracts", () => {
   it("Should fail: price exceeds limit in buy order fulfillment", async () => {
     // Get the last order that has max price of 1000000
     const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
-    const orderId = tradingPool.totalOrders.toNumber() - 1;
+    const orderId = (await nextOrderId(user2.publicKey)).toNumber() - 1;
     
     const [buyOrderPDA] = PublicKey.findProgramAddressSync(
       [
@@ -572,12 +4473,17 @@ describe("stock_contracts", () => {
           new anchor.BN(0)
         )
         .accounts({
+          ...solQuoteAccounts,
           buyOrder: buyOrderPDA,
           stockMint: stockMintPDA,
           stockMintInfo: stockMintInfoPDA,
+          priceFeed: priceFeedPDA,
+          reserveAttestation: findReserveAttestationPDA(stockSymbol),
           userStockTokenAccount: userStockTokenAccount,
           tradingPool: tradingPoolPDA,
           tradingPoolVault: tradingPoolVaultPDA,
+          feeVault: feeVaultPDA,
+          userEligibility: null,
           user: user2.publicKey,
           backendAuthority: currentTradingPool.backendAuthority,
           tokenProgram: TOKEN_PROGRAM_ID,
@@ -593,4 +4499,384 @@ describe("stock_contracts", () => {
       assert.ok(true);
     }
   });
-});
\ No newline at end of file
+
+  it("Should fail: total cost does not match shares times price", async () => {
+    // The last order placed by user2 is still open
+    const orderId = (await nextOrderId(user2.publicKey)).toNumber() - 1;
+    
+    const [buyOrderPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("buy_order"),
+        user2.publicKey.toBuffer(),
+        new anchor.BN(orderId).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    const userStockTokenAccount = await getAssociatedTokenAddress(
+      stockMintPDA,
+      user2.publicKey
+    );
+    
+    await provider.connection.requestAirdrop(rotatedBackendAuthority.publicKey, LAMPORTS_PER_SOL);
+    await new Promise(resolve => setTimeout(resolve, 1000));
+    await publishPrice(1000000);
+    
+    try {
+      await program.methods
+        .fulfillBuyOrder(
+          new anchor.BN(500),
+          new anchor.BN(1000000),
+          new anchor.BN(400 * 1000000), // Undercharges for 500 shares
+          new anchor.BN(0)
+        )
+        .accounts({
+          ...solQuoteAccounts,
+          buyOrder: buyOrderPDA,
+          stockMint: stockMintPDA,
+          stockMintInfo: stockMintInfoPDA,
+          priceFeed: priceFeedPDA,
+          reserveAttestation: findReserveAttestationPDA(stockSymbol),
+          userStockTokenAccount: userStockTokenAccount,
+          tradingPool: tradingPoolPDA,
+          tradingPoolVault: tradingPoolVaultPDA,
+          feeVault: feeVaultPDA,
+          userEligibility: null,
+          user: user2.publicKey,
+          backendAuthority: rotatedBackendAuthority.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([rotatedBackendAuthority])
+        .rpc();
+      
+      assert.fail("Should have failed with notional mismatch");
+    } catch (error) {
+      assert.include(error.toString(), "NotionalMismatch");
+    }
+  });
+
+  it("Multisig-controlled vault withdrawal", async () => {
+    const owner3 = Keypair.generate();
+    const owners = [user1.publicKey, user2.publicKey, owner3.publicKey];
+    const threshold = 2;
+    const withdrawAmount = 0.5 * LAMPORTS_PER_SOL;
+    
+    await provider.connection.requestAirdrop(rotatedVaultAuthority.publicKey, 2 * LAMPORTS_PER_SOL);
+    await new Promise(resolve => setTimeout(resolve, 1000));
+    
+    const [multisigPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("multisig"), tradingPoolPDA.toBuffer()],
+      program.programId
+    );
+    
+    const tx = await program.methods
+      .createMultisig(owners, threshold)
+      .accounts({
+        multisig: multisigPDA,
+        tradingPool: tradingPoolPDA,
+        vaultAuthority: rotatedVaultAuthority.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([rotatedVaultAuthority])
+      .rpc();
+    
+    console.log("Create multisig tx:", tx);
+    
+    const tradingPool = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.equal(tradingPool.multisig.toBase58(), multisigPDA.toBase58());
+    
+    // Direct withdrawals are now disabled
+    try {
+      await program.methods
+        .queueVaultWithdrawal(new anchor.BN(withdrawAmount), null)
+        .accounts({
+          tradingPool: tradingPoolPDA,
+          vaultAuthority: rotatedVaultAuthority.publicKey,
+        })
+        .signers([rotatedVaultAuthority])
+        .rpc();
+      
+      assert.fail("Should have failed without a multisig proposal");
+    } catch (error) {
+      assert.include(error.toString(), "MultisigRequired");
+    }
+    
+    const [proposalPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("multisig_proposal"),
+        multisigPDA.toBuffer(),
+        new anchor.BN(0).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    await program.methods
+      .proposeMultisigAction({
+        withdrawVaultFunds: {
+          amount: new anchor.BN(withdrawAmount),
+          quoteMint: null,
+          destination: user2.publicKey,
+        },
+      })
+      .accounts({
+        multisig: multisigPDA,
+        proposal: proposalPDA,
+        tradingPool: tradingPoolPDA,
+        proposer: user1.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    const executeAccounts = {
+      multisig: multisigPDA,
+      proposal: proposalPDA,
+      tradingPool: tradingPoolPDA,
+      executor: provider.wallet.publicKey,
+    };
+    
+    // One approval is below the threshold
+    try {
+      await program.methods
+        .executeMultisigProposal()
+        .accounts(executeAccounts)
+        .rpc();
+      
+      assert.fail("Should have failed below threshold");
+    } catch (error) {
+      assert.include(error.toString(), "ThresholdNotMet");
+    }
+    
+    await program.methods
+      .approveMultisigProposal()
+      .accounts({
+        multisig: multisigPDA,
+        proposal: proposalPDA,
+        tradingPool: tradingPoolPDA,
+        owner: user2.publicKey,
+      })
+      .signers([user2])
+      .rpc();
+    
+    await program.methods
+      .executeMultisigProposal()
+      .accounts(executeAccounts)
+      .rpc();
+    
+    // Executing the proposal only queues the withdrawal behind the delay
+    const { pendingWithdrawal } = await program.account.tradingPool.fetch(tradingPoolPDA);
+    assert.equal(pendingWithdrawal.destination.toBase58(), user2.publicKey.toBase58());
+    assert.equal(pendingWithdrawal.queuedBy.toBase58(), multisigPDA.toBase58());
+    
+    await waitForBlockTimeAfter(pendingWithdrawal.executableAt.toNumber());
+    
+    const destinationInitialBalance = await provider.connection.getBalance(user2.publicKey);
+    
+    await program.methods
+      .executeVaultWithdrawal()
+      .accounts({
+        ...solQuoteAccounts,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        destination: user2.publicKey,
+        executor: provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+    
+    const destinationFinalBalance = await provider.connection.getBalance(user2.publicKey);
+    assert.equal(destinationFinalBalance - destinationInitialBalance, withdrawAmount);
+    
+    const proposal = await program.account.multisigProposal.fetch(proposalPDA);
+    assert.equal(proposal.executed, true);
+    assert.equal(proposal.approvals, 0b11);
+    
+    // Dividends also need a proposal once the multisig is set
+    const { root } = buildDividendTree([{ holder: user1.publicKey, amount: 10_000 }]);
+    const blockTime = await waitForBlockTimeAfter(0);
+    const params = {
+      stockSymbol,
+      recordDate: new anchor.BN(blockTime - 1),
+      merkleRoot: root,
+      totalAmount: new anchor.BN(10_000),
+      claimantCount: 1,
+      expiresAt: new anchor.BN(blockTime + 60),
+      quoteMint: null,
+    };
+    const [distributionPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("distribution"),
+        Buffer.from(stockSymbol),
+        params.recordDate.toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    try {
+      await program.methods
+        .createDistribution(
+          params.stockSymbol,
+          params.recordDate,
+          params.merkleRoot,
+          params.totalAmount,
+          params.claimantCount,
+          params.expiresAt,
+          null
+        )
+        .accounts({
+          distribution: distributionPDA,
+          stockMintInfo: stockMintInfoPDA,
+          tradingPool: tradingPoolPDA,
+          tradingPoolVault: tradingPoolVaultPDA,
+          quoteMintConfig: null,
+          quoteVault: null,
+          vaultAuthority: rotatedVaultAuthority.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([rotatedVaultAuthority])
+        .rpc();
+      
+      assert.fail("Should have failed without a multisig proposal");
+    } catch (error) {
+      assert.include(error.toString(), "MultisigRequired");
+    }
+    
+    const [distributionProposalPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("multisig_proposal"),
+        multisigPDA.toBuffer(),
+        new anchor.BN(1).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    );
+    
+    await program.methods
+      .proposeMultisigAction({ createDistribution: { params } })
+      .accounts({
+        multisig: multisigPDA,
+        proposal: distributionProposalPDA,
+        tradingPool: tradingPoolPDA,
+        proposer: user1.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    
+    await program.methods
+      .approveMultisigProposal()
+      .accounts({
+        multisig: multisigPDA,
+        proposal: distributionProposalPDA,
+        tradingPool: tradingPoolPDA,
+        owner: owner3.publicKey,
+      })
+      .signers([owner3])
+      .rpc();
+    
+    await program.methods
+      .executeDistributionProposal(params)
+      .accounts({
+        multisig: multisigPDA,
+        proposal: distributionProposalPDA,
+        distribution: distributionPDA,
+        stockMintInfo: stockMintInfoPDA,
+        tradingPool: tradingPoolPDA,
+        tradingPoolVault: tradingPoolVaultPDA,
+        quoteMintConfig: null,
+        quoteVault: null,
+        executor: provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+    
+    const distribution = await program.account.distribution.fetch(distributionPDA);
+    assert.equal(distribution.totalAmount.toNumber(), 10_000);
+    assert.isAbove(distribution.claimableAt.toNumber(), blockTime - 1);
+    
+    // So do fee, oracle and compliance changes
+    try {
+      await program.methods
+        .setFeeConfig(25, null)
+        .accounts({
+          tradingPool: tradingPoolPDA,
+          vaultAuthority: rotatedVaultAuthority.publicKey,
+        })
+        .signers([rotatedVaultAuthority])
+        .rpc();
+      
+      assert.fail("Should have failed without a multisig proposal");
+    } catch (error) {
+      assert.include(error.toString(), "MultisigRequired");
+    }
+    
+    const findProposalPDA = (proposalId: number) => PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("multisig_proposal"),
+        multisigPDA.toBuffer(),
+        new anchor.BN(proposalId).toArrayLike(Buffer, "le", 8)
+      ],
+      program.programId
+    )[0];
+    const propose = (proposalId: number, action: object) => program.methods
+      .proposeMultisigAction(action)
+      .accounts({
+        multisig: multisigPDA,
+        proposal: findProposalPDA(proposalId),
+        tradingPool: tradingPoolPDA,
+        proposer: user1.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user1])
+      .rpc();
+    const approve = (proposalId: number, owner: Keypair) => program.methods
+      .approveMultisigProposal()
+      .accounts({
+        multisig: multisigPDA,
+        proposal: findProposalPDA(proposalId),
+        tradingPool: tradingPoolPDA,
+        owner: owner.publicKey,
+      })
+      .signers([owner])
+      .rpc();
+    const execute = (proposalId: number) => program.methods
+      .executeMultisigProposal()
+      .accounts({
+        multisig: multisigPDA,
+        proposal: findProposalPDA(proposalId),
+        tradingPool: tradingPoolPDA,
+        executor: provider.wallet.publicKey,
+      })
+      .rpc();
+    
+    await propose(2, { setFeeConfig: { feeBps: 25, feeAuthority: null } });
+    await approve(2, user2);
+    await execute(2);
+    assert.equal((await program.account.tradingPool.fetch(tradingPoolPDA)).feeBps, 25);
+    
+    // Rotating the owners voids proposals made for the old set
+    await propose(3, { setComplianceConfig: { complianceAuthority: null, enforceEligibility: true } });
+    
+    const owner4 = Keypair.generate();
+    await propose(4, {
+      setOwners: { owners: [user1.publicKey, owner3.publicKey, owner4.publicKey], threshold: 2 },
+    });
+    await approve(4, owner3);
+    await execute(4);
+    
+    const multisig = await program.account.multisig.fetch(multisigPDA);
+    assert.deepEqual(
+      multisig.owners.map((owner) => owner.toBase58()),
+      [user1.publicKey, owner3.publicKey, owner4.publicKey].map((owner) => owner.toBase58())
+    );
+    assert.equal(multisig.minProposalId.toNumber(), 5);
+    
+    try {
+      await approve(3, owner3);
+      assert.fail("Should have failed for a proposal of the old owner set");
+    } catch (error) {
+      assert.include(error.toString(), "StaleProposal");
+    }
+  });
+});